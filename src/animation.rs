@@ -52,6 +52,11 @@ impl Spinner {
         });
     }
 
+    /// Handle to the underlying bar, e.g. to clear it from another component.
+    pub fn progress_bar(&self) -> ProgressBar {
+        self.pb.clone()
+    }
+
     #[allow(dead_code)]
     pub fn finish_with_message(&self, message: &str) {
         self.pb.finish_with_message(message.to_string());
@@ -27,6 +27,107 @@ pub enum Commands {
 
     /// Generate commit message for a GitHub PR
     Pr(PrArgs),
+
+    /// Manage the prepare-commit-msg and commit-msg git hooks
+    Hook {
+        #[clap(subcommand)]
+        command: HookCommand,
+    },
+
+    /// Check a commit message against the configured convention
+    Lint {
+        /// File containing the message, or - to read stdin
+        file: String,
+    },
+
+    /// Inspect the effective configuration
+    Config {
+        #[clap(subcommand)]
+        command: ConfigCommand,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ConfigCommand {
+    /// Print the path of the config file
+    Path {
+        /// The repository's .fastcommit.toml instead of the global config
+        #[clap(long)]
+        repo: bool,
+    },
+    /// Print the effective value of a setting, e.g. `text_wrap.default_width`
+    Get {
+        key: String,
+        /// Show which layer set the value
+        #[clap(long = "show-origin")]
+        show_origin: bool,
+    },
+    /// Write a setting to the config file, keeping its comments and layout
+    Set {
+        key: String,
+        value: String,
+        /// Write to the repository's .fastcommit.toml instead of the global config
+        #[clap(long)]
+        repo: bool,
+    },
+    /// List effective values of all settings
+    List {
+        /// Show which layer (default, global, repo, command line) set each value
+        #[clap(long = "show-origin")]
+        show_origin: bool,
+    },
+    /// Open the config file in $VISUAL or $EDITOR and validate it afterwards
+    Edit {
+        /// Edit the repository's .fastcommit.toml instead of the global config
+        #[clap(long)]
+        repo: bool,
+    },
+    /// Check config files for syntax errors, unknown keys and invalid values
+    Validate {
+        /// File to check, defaults to the global and repository configs
+        file: Option<String>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum HookCommand {
+    /// Install the prepare-commit-msg hook, chaining any existing one
+    Install {
+        /// Install the commit-msg hook that runs `fastcommit lint` instead
+        #[clap(long = "commit-msg")]
+        commit_msg: bool,
+    },
+
+    /// Remove the hook and restore the previous one
+    Uninstall {
+        /// Remove the commit-msg lint hook instead
+        #[clap(long = "commit-msg")]
+        commit_msg: bool,
+    },
+
+    /// Run by git: write a generated message into the commit message file
+    Run {
+        /// Commit message file passed by git
+        message_file: String,
+
+        /// Source of the message (message, template, merge, squash or commit)
+        source: Option<String>,
+
+        /// Commit object name, for amends and -c/-C
+        sha: Option<String>,
+    },
+}
+
+impl Args {
+    /// The common options of the subcommand being run, if it has any.
+    pub fn common(&self) -> Option<&CommonArgs> {
+        match &self.command {
+            Some(Commands::Commit(args)) => Some(&args.common),
+            Some(Commands::Pr(args)) => Some(&args.common),
+            None => Some(&self.commit_args.common),
+            Some(_) => None,
+        }
+    }
 }
 
 impl Default for Commands {
@@ -47,6 +148,13 @@ pub struct CommonArgs {
     #[clap(short, long, help = "Set the verbosity level")]
     pub verbosity: Option<Verbosity>,
 
+    #[clap(
+        long,
+        value_name = "NAME",
+        help = "Use the [profiles.<NAME>] endpoint and model for every request"
+    )]
+    pub profile: Option<String>,
+
     #[clap(
         short,
         long,
@@ -54,12 +162,24 @@ pub struct CommonArgs {
     )]
     pub prompt: Option<String>,
 
+    #[clap(
+        long = "learn-style",
+        help = "Follow the commit style of the repository's recent history"
+    )]
+    pub learn_style: bool,
+
     #[clap(
         long = "no-sanitize",
         help = "Temporarily disable sensitive info sanitizer for this run"
     )]
     pub no_sanitize: bool,
 
+    #[clap(
+        long,
+        help = "Stream the commit message to the terminal while it is generated"
+    )]
+    pub stream: bool,
+
     #[clap(long = "no-wrap", help = "Disable text wrapping for long lines")]
     pub no_wrap: bool,
 
@@ -83,6 +203,60 @@ pub struct CommonArgs {
         allow_hyphen_values = true
     )]
     pub commit_args: Vec<String>,
+
+    #[clap(
+        short = 'y',
+        long = "yes",
+        help = "Commit without reviewing the generated message interactively"
+    )]
+    pub yes: bool,
+}
+
+impl CommonArgs {
+    /// Options that override config values, as the top config layer.
+    pub fn config_overrides(&self) -> toml::Table {
+        let mut table = toml::Table::new();
+        if let Some(c) = self.conventional {
+            table.insert("conventional".into(), c.into());
+        }
+        if let Some(l) = self.language {
+            table.insert("language".into(), enum_value(l));
+        }
+        if let Some(v) = self.verbosity {
+            table.insert("verbosity".into(), enum_value(v));
+        }
+        if let Some(p) = &self.profile {
+            table.insert("profile".into(), p.clone().into());
+        }
+        if self.learn_style {
+            let mut style = toml::Table::new();
+            style.insert("learn".into(), true.into());
+            table.insert("style".into(), style.into());
+        }
+        if self.no_sanitize {
+            // CLI override to disable sanitizer
+            table.insert("sanitize_secrets".into(), false.into());
+        }
+        if self.stream {
+            table.insert("stream".into(), true.into());
+        }
+        if self.no_wrap {
+            let mut text_wrap = toml::Table::new();
+            text_wrap.insert("enabled".into(), false.into());
+            table.insert("text_wrap".into(), text_wrap.into());
+        }
+        if self.commit {
+            table.insert("auto_commit".into(), true.into());
+        }
+        if !self.commit_args.is_empty() {
+            table.insert("commit_args".into(), self.commit_args.clone().into());
+        }
+        table
+    }
+}
+
+fn enum_value(value: impl serde::Serialize) -> toml::Value {
+    toml::Value::try_from(value).expect("unit enum variants serialize to strings")
 }
 
 #[derive(Parser, Debug, Default)]
@@ -111,6 +285,33 @@ pub struct CommitArgs {
     )]
     pub range: Option<String>,
 
+    #[clap(
+        long,
+        conflicts_with_all = ["diff_file", "range"],
+        help = "Describe unstaged changes in the work tree instead of staged ones"
+    )]
+    pub unstaged: bool,
+
+    #[clap(
+        short = 'a',
+        long,
+        conflicts_with_all = ["diff_file", "range", "unstaged"],
+        help = "Describe all changes in the work tree, including untracked files"
+    )]
+    pub all: bool,
+
+    #[clap(
+        long,
+        value_name = "REF",
+        conflicts_with_all = ["diff_file", "range", "unstaged", "all"],
+        help = "Describe the current branch since its merge-base with REF (e.g. origin/main)"
+    )]
+    pub base: Option<String>,
+
+    /// Only describe changes to these paths (after `--`)
+    #[clap(last = true, value_name = "PATHSPEC", conflicts_with = "diff_file")]
+    pub pathspecs: Vec<String>,
+
     #[clap(
         short = 'm',
         long = "message",
@@ -118,10 +319,27 @@ pub struct CommitArgs {
     )]
     pub generate_message: bool,
 
+    #[clap(
+        long,
+        value_name = "N",
+        value_parser = clap::value_parser!(u32).range(1..=10),
+        conflicts_with = "generate_branch",
+        help = "Generate N alternative commit messages to pick from (JSON when not a terminal)"
+    )]
+    pub candidates: Option<u32>,
+
     #[clap(flatten)]
     pub common: CommonArgs,
 }
 
+impl CommitArgs {
+    /// Whether `--unstaged`, `--all`, `--base` or pathspecs select other changes
+    /// than the staged ones, so `git commit` wouldn't record what is described.
+    pub fn differs_from_staged(&self) -> bool {
+        self.unstaged || self.all || self.base.is_some() || !self.pathspecs.is_empty()
+    }
+}
+
 #[derive(Parser, Debug)]
 pub struct PrArgs {
     /// PR number, auto-detect from current branch if not specified
@@ -317,4 +535,79 @@ mod tests {
         assert!(args.commit_args.common.commit);
         assert!(args.commit_args.common.no_sanitize);
     }
+
+    #[test]
+    fn test_candidates_option() {
+        let args = parse_args(["fastcommit", "--candidates", "3"]).unwrap();
+        assert_eq!(args.commit_args.candidates, Some(3));
+        assert!(parse_args(["fastcommit", "--candidates", "0"]).is_err());
+        assert!(parse_args(["fastcommit", "-b", "--candidates", "3"]).is_err());
+    }
+
+    #[test]
+    fn test_hook_run_args() {
+        let args = parse_args([
+            "fastcommit",
+            "hook",
+            "run",
+            ".git/COMMIT_EDITMSG",
+            "message",
+        ])
+        .unwrap();
+        match args.command {
+            Some(Commands::Hook {
+                command:
+                    HookCommand::Run {
+                        message_file,
+                        source,
+                        sha,
+                    },
+            }) => {
+                assert_eq!(message_file, ".git/COMMIT_EDITMSG");
+                assert_eq!(source.as_deref(), Some("message"));
+                assert_eq!(sha, None);
+            }
+            other => panic!("unexpected command: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_profile_option() {
+        let args = parse_args(["fastcommit", "pr", "--profile", "strong"]).unwrap();
+        let overrides = args.common().unwrap().config_overrides();
+        assert_eq!(overrides["profile"].as_str(), Some("strong"));
+    }
+
+    #[test]
+    fn test_learn_style_option() {
+        let args = parse_args(["fastcommit", "--learn-style"]).unwrap();
+        let overrides = args.common().unwrap().config_overrides();
+        assert_eq!(overrides["style"]["learn"].as_bool(), Some(true));
+    }
+
+    #[test]
+    fn test_diff_selection_options() {
+        let args = parse_args(["fastcommit", "-m", "--", "src/parser", "docs"]).unwrap();
+        assert_eq!(args.commit_args.pathspecs, vec!["src/parser", "docs"]);
+        assert!(args.commit_args.generate_message);
+        assert!(args.commit_args.differs_from_staged());
+
+        let args = parse_args(["fastcommit", "commit", "-a", "--", "src"]).unwrap();
+        let Some(Commands::Commit(commit_args)) = args.command else {
+            panic!("Expected Commit subcommand");
+        };
+        assert!(commit_args.all);
+        assert_eq!(commit_args.pathspecs, vec!["src"]);
+
+        let args = parse_args(["fastcommit", "--base", "origin/main"]).unwrap();
+        assert_eq!(args.commit_args.base.as_deref(), Some("origin/main"));
+        assert!(!parse_args(["fastcommit", "-r", "HEAD~1"])
+            .unwrap()
+            .commit_args
+            .differs_from_staged());
+
+        assert!(parse_args(["fastcommit", "--unstaged", "--all"]).is_err());
+        assert!(parse_args(["fastcommit", "--base", "main", "-r", "HEAD~1"]).is_err());
+        assert!(parse_args(["fastcommit", "src"]).is_err());
+    }
 }
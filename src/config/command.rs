@@ -0,0 +1,500 @@
+//! `fastcommit config`: inspect, edit and validate config files.
+
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+
+use regex::Regex;
+use toml::{Table, Value};
+use toml_edit::{DocumentMut, ImDocument, Item};
+
+use super::layered::{repo_config_path, GLOBAL_ONLY_KEYS, REPO_CONFIG_FILE};
+use super::overrides::{insert_dotted, known_keys, parse_value, profile_fields, split_profile_key};
+use super::{global_config_path, Config, CustomSanitizePattern, LayeredConfig};
+use crate::cli::ConfigCommand;
+use crate::lint::Diagnostic;
+use crate::review::open_editor;
+use crate::template_engine::is_builtin_variable;
+
+/// Run a config subcommand; returns `false` when validation found problems.
+///
+/// `layered` is only needed to show effective values, so a config file that
+/// fails to load can still be edited and validated.
+pub fn handle_config_command(
+    command: &ConfigCommand,
+    layered: anyhow::Result<LayeredConfig>,
+) -> anyhow::Result<bool> {
+    match command {
+        ConfigCommand::Path { repo } => {
+            println!("{}", target_path(*repo)?.display());
+        }
+        ConfigCommand::Get { key, show_origin } => {
+            let key = canonical_key(key)?;
+            let layered = layered?;
+            let origins = layered.origins();
+            let (value, source) = origins
+                .get(&key)
+                .ok_or_else(|| anyhow::anyhow!("'{}' is not set", key))?;
+            let value = match value {
+                Value::String(s) => s.clone(),
+                value => value.to_string(),
+            };
+            if *show_origin {
+                println!("{source}\t{value}");
+            } else {
+                println!("{value}");
+            }
+        }
+        ConfigCommand::Set { key, value, repo } => {
+            let path = target_path(*repo)?;
+            let key = canonical_key(key)?;
+            if *repo && GLOBAL_ONLY_KEYS.contains(&key.as_str()) {
+                return Err(anyhow::anyhow!(
+                    "'{}' can only be set in the global config",
+                    key
+                ));
+            }
+            let text = std::fs::read_to_string(&path).unwrap_or_default();
+            let updated = set_value(&text, &key, value)?;
+            // 只拒绝本次修改引入的问题，文件中已有的错误可以逐个修复
+            let existing: BTreeSet<String> = validate_text(&text, *repo)
+                .into_iter()
+                .map(|d| d.message)
+                .collect();
+            let diagnostics: Vec<Diagnostic> = validate_text(&updated, *repo)
+                .into_iter()
+                .filter(|d| !existing.contains(&d.message))
+                .collect();
+            if !diagnostics.is_empty() {
+                print_diagnostics(&path, &diagnostics);
+                return Err(anyhow::anyhow!("Not saving {}", path.display()));
+            }
+            write_file(&path, &updated)?;
+        }
+        ConfigCommand::List { show_origin } => {
+            for (key, (value, source)) in layered?.origins() {
+                // 不在终端中直接打印密钥
+                let is_secret = key == "api_key" || key.ends_with(".api_key");
+                let value = if is_secret && value.as_str().is_some_and(|v| !v.is_empty()) {
+                    "\"********\"".to_string()
+                } else {
+                    value.to_string()
+                };
+                if *show_origin {
+                    println!("{source}\t{key} = {value}");
+                } else {
+                    println!("{key} = {value}");
+                }
+            }
+        }
+        ConfigCommand::Edit { repo } => {
+            let path = target_path(*repo)?;
+            if !path.exists() {
+                let initial = if *repo {
+                    "# fastcommit repository config, overrides ~/.fastcommit/config.toml\n"
+                        .to_string()
+                } else {
+                    toml::to_string(&Config::default())?
+                };
+                write_file(&path, &initial)?;
+            }
+            open_editor(&path)?;
+            return validate_file(&path, *repo);
+        }
+        ConfigCommand::Validate { file } => {
+            let files: Vec<(PathBuf, bool)> = match file {
+                Some(file) => {
+                    let path = PathBuf::from(file);
+                    let is_repo = path.file_name().is_some_and(|n| n == REPO_CONFIG_FILE);
+                    vec![(path, is_repo)]
+                }
+                None => [
+                    (Some(global_config_path()?), false),
+                    (super::find_repo_config(), true),
+                ]
+                .into_iter()
+                .filter_map(|(path, is_repo)| Some((path?, is_repo)))
+                .filter(|(path, _)| path.exists())
+                .collect(),
+            };
+            let mut valid = true;
+            for (path, is_repo) in files {
+                valid &= validate_file(&path, is_repo)?;
+            }
+            return Ok(valid);
+        }
+    }
+    Ok(true)
+}
+
+fn target_path(repo: bool) -> anyhow::Result<PathBuf> {
+    if repo {
+        repo_config_path().ok_or_else(|| anyhow::anyhow!("Not inside a git repository"))
+    } else {
+        global_config_path()
+    }
+}
+
+fn write_file(path: &Path, text: &str) -> anyhow::Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(path, text)
+        .map_err(|e| anyhow::anyhow!("Failed to write {}: {}", path.display(), e))
+}
+
+/// Accept keys written like environment variables or git config names too.
+fn canonical_key(key: &str) -> anyhow::Result<String> {
+    let keys = known_keys();
+    if keys.values().any(|(k, _)| k == key)
+        || split_profile_key(key).is_some()
+        || key
+            .strip_prefix("template_vars.")
+            .is_some_and(|name| !name.contains('.'))
+    {
+        return Ok(key.to_string());
+    }
+    let normalized: String = key
+        .chars()
+        .filter(|c| !matches!(c, '_' | '-' | '.'))
+        .flat_map(char::to_lowercase)
+        .collect();
+    keys.get(&normalized)
+        .map(|(k, _)| k.clone())
+        .ok_or_else(|| anyhow::anyhow!("Unknown config key '{}'", key))
+}
+
+/// Set dotted `key` to `raw` in the TOML `text`, keeping comments and layout.
+fn set_value(text: &str, key: &str, raw: &str) -> anyhow::Result<String> {
+    let mut doc: DocumentMut = text
+        .parse()
+        .map_err(|e: toml_edit::TomlError| anyhow::anyhow!("{}", e.message()))?;
+    let template = match split_profile_key(key) {
+        Some((_, field)) => profile_fields()
+            .into_iter()
+            .find(|(f, _)| *f == field)
+            .map(|(_, sample)| sample),
+        None => known_keys()
+            .into_values()
+            .find(|(k, _)| k == key)
+            .and_then(|(_, template)| template),
+    };
+    let value = parse_value(key, raw, template.as_ref()).map_err(|e| anyhow::anyhow!("{}", e))?;
+    let value: toml_edit::Value = value
+        .to_string()
+        .parse()
+        .map_err(|e: toml_edit::TomlError| anyhow::anyhow!("{}", e.message()))?;
+
+    let mut parts: Vec<&str> = key.split('.').collect();
+    let leaf = parts.pop().unwrap_or(key);
+    let mut table = doc.as_table_mut() as &mut dyn toml_edit::TableLike;
+    for part in parts {
+        table = table
+            .entry(part)
+            .or_insert_with(|| {
+                // 只有中间层的表（如 [profiles]）不单独输出表头
+                let mut table = toml_edit::Table::new();
+                table.set_implicit(true);
+                Item::Table(table)
+            })
+            .as_table_like_mut()
+            .ok_or_else(|| anyhow::anyhow!("'{}' is not a table", part))?;
+    }
+    match table.get_mut(leaf) {
+        // 保留原有的行内注释和格式
+        Some(Item::Value(existing)) => {
+            let decor = existing.decor().clone();
+            *existing = value;
+            *existing.decor_mut() = decor;
+        }
+        _ => {
+            table.insert(leaf, Item::Value(value));
+        }
+    }
+    Ok(doc.to_string())
+}
+
+fn print_diagnostics(path: &Path, diagnostics: &[Diagnostic]) {
+    for d in diagnostics {
+        eprintln!("{}:{d}", path.display());
+    }
+}
+
+fn validate_file(path: &Path, is_repo: bool) -> anyhow::Result<bool> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", path.display(), e))?;
+    let diagnostics = validate_text(&text, is_repo);
+    if diagnostics.is_empty() {
+        eprintln!("{}: OK", path.display());
+    } else {
+        print_diagnostics(path, &diagnostics);
+    }
+    Ok(diagnostics.is_empty())
+}
+
+/// Check syntax, unknown keys, value types (including enum values) and
+/// `custom_sanitize_patterns` regexes, pointing at the offending line.
+pub fn validate_text(text: &str, is_repo: bool) -> Vec<Diagnostic> {
+    let position = |offset: usize| {
+        let before = &text[..offset.min(text.len())];
+        let line = before.matches('\n').count() + 1;
+        let column = before.rsplit('\n').next().unwrap_or("").chars().count() + 1;
+        (line, column)
+    };
+    let at = |span: Option<std::ops::Range<usize>>, message: String| {
+        let (line, column) = position(span.map_or(0, |s| s.start));
+        Diagnostic::new(line, column, message)
+    };
+
+    let doc = match ImDocument::parse(text) {
+        Ok(doc) => doc,
+        Err(e) => return vec![at(e.span(), e.message().trim().to_string())],
+    };
+    let Ok(values) = toml::from_str::<Table>(text) else {
+        return vec![Diagnostic::new(1, 1, "invalid TOML")];
+    };
+
+    let keys = known_keys();
+    let leaves: BTreeSet<&str> = keys.values().map(|(k, _)| k.as_str()).collect();
+    let tables: BTreeSet<&str> = leaves
+        .iter()
+        .filter_map(|k| k.rsplit_once('.').map(|(table, _)| table))
+        .collect();
+    let Ok(Value::Table(defaults)) = Value::try_from(Config::default()) else {
+        unreachable!("Config serializes to a table");
+    };
+
+    let mut diagnostics = Vec::new();
+    let mut stack = vec![(String::new(), doc.as_table() as &dyn toml_edit::TableLike)];
+    while let Some((prefix, table)) = stack.pop() {
+        for (name, item) in table.iter() {
+            let key = if prefix.is_empty() {
+                name.to_string()
+            } else {
+                format!("{prefix}.{name}")
+            };
+            let span = table
+                .get_key_value(name)
+                .and_then(|(k, _)| k.span())
+                .or_else(|| item.span());
+
+            // 模板变量的名称和类型都由用户决定
+            if key == "template_vars" {
+                match item.as_table_like() {
+                    Some(vars) => diagnostics.extend(
+                        vars.iter()
+                            .filter(|(name, _)| is_builtin_variable(name))
+                            .map(|(name, var)| {
+                                let span = vars.get_key_value(name).and_then(|(k, _)| k.span());
+                                at(
+                                    span.or_else(|| var.span()),
+                                    format!("'template_vars.{name}' is a built-in template variable and is ignored"),
+                                )
+                            }),
+                    ),
+                    None => diagnostics.push(at(span, format!("'{key}' must be a table"))),
+                }
+                continue;
+            }
+
+            // profiles 下的表名由用户决定
+            let is_profile = key == "profiles"
+                || key
+                    .strip_prefix("profiles.")
+                    .is_some_and(|name| !name.contains('.'));
+            if tables.contains(key.as_str()) || is_profile {
+                match item.as_table_like() {
+                    Some(inner) => stack.push((key, inner)),
+                    None => diagnostics.push(at(span, format!("'{key}' must be a table"))),
+                }
+                continue;
+            }
+            let profile_field = split_profile_key(&key).map(|(_, field)| field);
+            if !leaves.contains(key.as_str()) && profile_field.is_none() {
+                diagnostics.push(at(span, format!("unknown key '{key}'")));
+                continue;
+            }
+            let global_only = profile_field.unwrap_or(&key);
+            if is_repo && GLOBAL_ONLY_KEYS.contains(&global_only) {
+                diagnostics.push(at(
+                    span,
+                    format!("'{key}' is ignored in repository config, set it in the global config"),
+                ));
+                continue;
+            }
+
+            // 在默认配置上单独替换该值，借助反序列化检查类型和枚举取值
+            let Some(value) = lookup(&values, &key) else {
+                continue;
+            };
+            let mut candidate = defaults.clone();
+            insert_dotted(&mut candidate, &key, value.clone());
+            if let Err(e) = Value::Table(candidate).try_into::<Config>() {
+                diagnostics.push(at(
+                    item.span().or(span),
+                    format!("invalid value for '{key}': {}", e.message().trim()),
+                ));
+                continue;
+            }
+
+            if key == "custom_sanitize_patterns" {
+                diagnostics.extend(
+                    check_patterns(value, item)
+                        .into_iter()
+                        .map(|(s, m)| at(s.or(span.clone()), m)),
+                );
+            }
+        }
+    }
+    diagnostics.sort_by_key(|d| (d.line, d.column));
+    diagnostics
+}
+
+/// Compile every custom sanitize regex, returning the span of each broken entry.
+fn check_patterns(value: &Value, item: &Item) -> Vec<(Option<std::ops::Range<usize>>, String)> {
+    let patterns: Vec<CustomSanitizePattern> = match value.clone().try_into() {
+        Ok(patterns) => patterns,
+        Err(_) => return Vec::new(),
+    };
+    let spans: Vec<_> = match item {
+        Item::ArrayOfTables(array) => array.iter().map(|t| t.span()).collect(),
+        Item::Value(toml_edit::Value::Array(array)) => array.iter().map(|v| v.span()).collect(),
+        _ => Vec::new(),
+    };
+    patterns
+        .iter()
+        .enumerate()
+        .filter_map(|(i, pattern)| {
+            let err = Regex::new(&pattern.regex).err()?;
+            let message = format!(
+                "invalid regex in custom_sanitize_patterns '{}': {}",
+                pattern.name,
+                err.to_string().lines().last().unwrap_or_default().trim()
+            );
+            Some((spans.get(i).cloned().flatten(), message))
+        })
+        .collect()
+}
+
+fn lookup<'a>(table: &'a Table, key: &str) -> Option<&'a Value> {
+    match key.split_once('.') {
+        Some((head, rest)) => lookup(table.get(head)?.as_table()?, rest),
+        None => table.get(key),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn messages(text: &str, is_repo: bool) -> Vec<String> {
+        validate_text(text, is_repo)
+            .iter()
+            .map(ToString::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn test_valid_config() {
+        let text = toml::to_string(&Config::default()).unwrap();
+        assert_eq!(messages(&text, false), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_reports_unknown_keys_enums_and_regexes() {
+        let text = r#"# my config
+langauge = "en"
+verbosity = "loud"
+
+[text_wrap]
+default_width = "wide"
+colour = true
+
+[[custom_sanitize_patterns]]
+name = "OK"
+regex = "abc"
+
+[[custom_sanitize_patterns]]
+name = "BROKEN"
+regex = "(unclosed"
+"#;
+        let messages = messages(text, false);
+        assert_eq!(messages.len(), 5, "{messages:#?}");
+        assert_eq!(messages[0], "2:1: unknown key 'langauge'");
+        assert!(
+            messages[1].starts_with("3:13: invalid value for 'verbosity': unknown variant `loud`")
+        );
+        assert!(messages[2].starts_with("6:17: invalid value for 'text_wrap.default_width'"));
+        assert_eq!(messages[3], "7:1: unknown key 'text_wrap.colour'");
+        assert!(messages[4].starts_with("13:1: invalid regex in custom_sanitize_patterns 'BROKEN'"));
+    }
+
+    #[test]
+    fn test_syntax_error_position() {
+        assert_eq!(messages("language = \n", false).len(), 1);
+        assert!(messages("language = \n", false)[0].starts_with("1:12: "));
+    }
+
+    #[test]
+    fn test_repo_config_rejects_endpoint() {
+        assert_eq!(
+            messages("api_key = \"sk\"\n", true),
+            vec!["1:1: 'api_key' is ignored in repository config, set it in the global config"]
+        );
+    }
+
+    #[test]
+    fn test_profiles() {
+        let text = "[profiles.local]\nprovider = \"ollama\"\nmodel = \"qwen2.5-coder\"\napi_key = \"x\"\nmodle = \"y\"\n\n[profiles.cheap]\nmax_tokens = \"many\"\n\n[task_profiles]\nbranch = \"cheap\"\n";
+        let messages = messages(text, true);
+        assert_eq!(messages.len(), 3, "{messages:#?}");
+        assert_eq!(
+            messages[0],
+            "4:1: 'profiles.local.api_key' is ignored in repository config, set it in the global config"
+        );
+        assert_eq!(messages[1], "5:1: unknown key 'profiles.local.modle'");
+        assert!(messages[2].starts_with("8:14: invalid value for 'profiles.cheap.max_tokens'"));
+
+        let updated = set_value("", "profiles.fast.temperature", "0.5").unwrap();
+        assert_eq!(updated, "[profiles.fast]\ntemperature = 0.5\n");
+        assert!(set_value("", "profiles.fast.max_tokens", "x").is_err());
+    }
+
+    #[test]
+    fn test_template_vars() {
+        let text =
+            "[template_vars]\nteam = \"payments\"\nscopes = [\"api\", \"db\"]\ndiff = \"x\"\n";
+        assert_eq!(
+            messages(text, true),
+            vec!["4:1: 'template_vars.diff' is a built-in template variable and is ignored"]
+        );
+        assert_eq!(
+            canonical_key("template_vars.team").unwrap(),
+            "template_vars.team"
+        );
+        let updated = set_value("", "template_vars.team", "payments").unwrap();
+        assert_eq!(updated, "[template_vars]\nteam = \"payments\"\n");
+    }
+
+    #[test]
+    fn test_set_value_preserves_comments() {
+        let text = "# global settings\nlanguage = \"zh\" # default language\n\n[text_wrap]\nenabled = true\n";
+        let updated = set_value(text, "language", "en").unwrap();
+        assert_eq!(
+            updated,
+            "# global settings\nlanguage = \"en\" # default language\n\n[text_wrap]\nenabled = true\n"
+        );
+        let updated = set_value(&updated, "text_wrap.default_width", "100").unwrap();
+        assert!(updated.ends_with("[text_wrap]\nenabled = true\ndefault_width = 100\n"));
+        assert!(set_value(text, "conventional", "maybe").is_err());
+    }
+
+    #[test]
+    fn test_canonical_key() {
+        assert_eq!(canonical_key("api_key").unwrap(), "api_key");
+        assert_eq!(
+            canonical_key("textWrap.defaultWidth").unwrap(),
+            "text_wrap.default_width"
+        );
+        assert!(canonical_key("nope").is_err());
+    }
+}
@@ -0,0 +1,293 @@
+//! Config resolved from several layers, each overriding the ones before it.
+
+use std::collections::BTreeMap;
+use std::fmt::{self, Display};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use toml::{Table, Value};
+
+use super::Config;
+
+/// Name of the per-repository config file, looked up at the repository root.
+pub const REPO_CONFIG_FILE: &str = ".fastcommit.toml";
+
+/// Keys a repository file may not set: a cloned repo must not be able to send
+/// the diff, or the user's key, to a server of its choosing.
+pub(super) const GLOBAL_ONLY_KEYS: &[&str] = &["api_base", "api_key"];
+
+/// Where a config value came from, from lowest to highest priority.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigSource {
+    Default,
+    Global(PathBuf),
+    Repo(PathBuf),
+    GitConfig,
+    Env,
+    Cli,
+}
+
+impl Display for ConfigSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigSource::Default => write!(f, "default"),
+            ConfigSource::Global(path) => write!(f, "global ({})", path.display()),
+            ConfigSource::Repo(path) => write!(f, "repo ({})", path.display()),
+            ConfigSource::GitConfig => write!(f, "git config"),
+            ConfigSource::Env => write!(f, "environment"),
+            ConfigSource::Cli => write!(f, "command line"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ConfigLayer {
+    pub source: ConfigSource,
+    pub values: Table,
+}
+
+/// All layers that make up the effective config, in priority order.
+#[derive(Debug, Clone)]
+pub struct LayeredConfig {
+    layers: Vec<ConfigLayer>,
+}
+
+impl LayeredConfig {
+    /// Start with the built-in defaults as the lowest layer.
+    pub fn new() -> anyhow::Result<Self> {
+        let Value::Table(defaults) = Value::try_from(Config::default())? else {
+            unreachable!("Config serializes to a table");
+        };
+        Ok(Self {
+            layers: vec![ConfigLayer {
+                source: ConfigSource::Default,
+                values: defaults,
+            }],
+        })
+    }
+
+    pub fn push(&mut self, source: ConfigSource, values: Table) {
+        self.layers.push(ConfigLayer { source, values });
+    }
+
+    /// Parse a TOML file and add it as a layer.
+    pub fn push_file(&mut self, source: ConfigSource, path: &Path) -> anyhow::Result<()> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", path.display(), e))?;
+        let mut values: Table = toml::from_str(&text)
+            .map_err(|e| anyhow::anyhow!("Failed to parse {}: {}", path.display(), e))?;
+        let is_repo = matches!(source, ConfigSource::Repo(_));
+        if is_repo {
+            for key in strip_global_only(&mut values) {
+                eprintln!(
+                    "Warning: ignoring '{}' in {}, it can only be set in the global config",
+                    key,
+                    path.display()
+                );
+            }
+        }
+        if let Some(Value::Table(templates)) = values.get_mut("templates") {
+            resolve_template_paths(templates, path, is_repo);
+        }
+        self.push(source, values);
+        Ok(())
+    }
+
+    /// All layers merged; tables are merged key by key, other values replaced.
+    pub fn merged(&self) -> Table {
+        let mut merged = Table::new();
+        for layer in &self.layers {
+            merge(&mut merged, &layer.values);
+        }
+        merged
+    }
+
+    pub fn config(&self) -> anyhow::Result<Config> {
+        Value::Table(self.merged())
+            .try_into()
+            .map_err(|e| anyhow::anyhow!("Invalid configuration: {}", e))
+    }
+
+    /// Every effective value as a dotted key, with the layer that set it.
+    pub fn origins(&self) -> BTreeMap<String, (Value, &ConfigSource)> {
+        let mut origins = BTreeMap::new();
+        for layer in &self.layers {
+            let mut values = BTreeMap::new();
+            flatten("", &layer.values, &mut values);
+            for (key, value) in values {
+                origins.insert(key, (value, &layer.source));
+            }
+        }
+        origins
+    }
+}
+
+/// Remove [`GLOBAL_ONLY_KEYS`] from the top level and from every profile,
+/// returning the dotted keys that were removed.
+fn strip_global_only(values: &mut Table) -> Vec<String> {
+    let mut removed: Vec<String> = GLOBAL_ONLY_KEYS
+        .iter()
+        .filter(|key| values.remove(**key).is_some())
+        .map(|key| key.to_string())
+        .collect();
+    if let Some(Value::Table(profiles)) = values.get_mut("profiles") {
+        for (name, profile) in profiles.iter_mut() {
+            let Value::Table(profile) = profile else {
+                continue;
+            };
+            for key in GLOBAL_ONLY_KEYS {
+                if profile.remove(*key).is_some() {
+                    removed.push(format!("profiles.{name}.{key}"));
+                }
+            }
+        }
+    }
+    removed
+}
+
+/// Make template paths relative to the config file's directory. A repository
+/// file may only point inside the repository, or it could send any local file
+/// to the model.
+fn resolve_template_paths(templates: &mut Table, config_path: &Path, is_repo: bool) {
+    let dir = config_path.parent().unwrap_or(Path::new("."));
+    templates.retain(|key, value| {
+        let Some(path) = value.as_str().map(PathBuf::from) else {
+            return true;
+        };
+        let escapes = path.is_absolute()
+            || path
+                .components()
+                .any(|c| matches!(c, std::path::Component::ParentDir));
+        if is_repo && escapes {
+            eprintln!(
+                "Warning: ignoring templates.{} in {}, it must be a path inside the repository",
+                key,
+                config_path.display()
+            );
+            return false;
+        }
+        *value = Value::String(dir.join(path).to_string_lossy().into_owned());
+        true
+    });
+}
+
+fn merge(base: &mut Table, overlay: &Table) {
+    for (key, value) in overlay {
+        match (base.get_mut(key), value) {
+            (Some(Value::Table(base)), Value::Table(overlay)) => merge(base, overlay),
+            _ => {
+                base.insert(key.clone(), value.clone());
+            }
+        }
+    }
+}
+
+fn flatten(prefix: &str, table: &Table, out: &mut BTreeMap<String, Value>) {
+    for (key, value) in table {
+        let key = if prefix.is_empty() {
+            key.clone()
+        } else {
+            format!("{prefix}.{key}")
+        };
+        match value {
+            Value::Table(table) => flatten(&key, table, out),
+            value => {
+                out.insert(key, value.clone());
+            }
+        }
+    }
+}
+
+/// Where `.fastcommit.toml` belongs in the current repository, whether or not it exists.
+pub fn repo_config_path() -> Option<PathBuf> {
+    Command::new("git")
+        .args(["rev-parse", "--show-toplevel"])
+        .output()
+        .ok()
+        .filter(|o| o.status.success())
+        .map(|o| PathBuf::from(String::from_utf8_lossy(&o.stdout).trim()).join(REPO_CONFIG_FILE))
+}
+
+/// Find `.fastcommit.toml` at the root of the current repository, or in the
+/// nearest parent directory when not inside a git work tree.
+pub fn find_repo_config() -> Option<PathBuf> {
+    if let Some(path) = repo_config_path() {
+        return path.is_file().then_some(path);
+    }
+
+    let cwd = std::env::current_dir().ok()?;
+    cwd.ancestors()
+        .map(|dir| dir.join(REPO_CONFIG_FILE))
+        .find(|path| path.is_file())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::CommitLanguage;
+
+    fn table(text: &str) -> Table {
+        toml::from_str(text).unwrap()
+    }
+
+    #[test]
+    fn test_later_layers_win_and_tables_merge() {
+        let mut layered = LayeredConfig::new().unwrap();
+        layered.push(
+            ConfigSource::Global("g.toml".into()),
+            table("language = \"en\"\nbranch_prefix = \"me/\"\n[text_wrap]\ndefault_width = 100\n"),
+        );
+        layered.push(
+            ConfigSource::Repo("r.toml".into()),
+            table("language = \"zh\"\n[text_wrap]\nenabled = false\n"),
+        );
+
+        let config = layered.config().unwrap();
+        assert_eq!(config.language, CommitLanguage::Chinese);
+        assert_eq!(config.branch_prefix.as_deref(), Some("me/"));
+        assert_eq!(config.text_wrap.default_width, 100);
+        assert!(!config.text_wrap.enabled);
+        // 未设置 api_base 时使用 provider 的默认地址
+        assert_eq!(config.api_base(), "https://api.openai.com/v1/");
+
+        let origins = layered.origins();
+        assert_eq!(origins["language"].1, &ConfigSource::Repo("r.toml".into()));
+        assert_eq!(
+            origins["text_wrap.default_width"].1,
+            &ConfigSource::Global("g.toml".into())
+        );
+        assert_eq!(
+            origins["text_wrap.preserve_words"].1,
+            &ConfigSource::Default
+        );
+    }
+
+    #[test]
+    fn test_repo_file_cannot_set_endpoint() {
+        let dir = std::env::temp_dir().join(format!("fastcommit-layered-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(REPO_CONFIG_FILE);
+        std::fs::write(
+            &path,
+            "api_base = \"https://evil.example\"\nconventional = false\n\n[profiles.fast]\nmodel = \"m\"\napi_base = \"https://evil.example\"\n\n[templates]\ncommit = \".fastcommit/commit.md\"\nsystem = \"/home/me/.ssh/id_rsa\"\n",
+        )
+        .unwrap();
+
+        let mut layered = LayeredConfig::new().unwrap();
+        layered
+            .push_file(ConfigSource::Repo(path.clone()), &path)
+            .unwrap();
+        let config = layered.config().unwrap();
+        assert!(!config.conventional);
+        assert_eq!(config.api_base(), "https://api.openai.com/v1/");
+        let fast = &config.profiles["fast"];
+        assert_eq!(fast.model.as_deref(), Some("m"));
+        assert_eq!(fast.api_base, None);
+        assert_eq!(
+            config.templates.commit,
+            Some(dir.join(".fastcommit/commit.md"))
+        );
+        assert_eq!(config.templates.system, None);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
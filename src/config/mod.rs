@@ -0,0 +1,811 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::path::PathBuf;
+
+use crate::constants::{
+    DEFAULT_CONNECT_TIMEOUT_SECS, DEFAULT_DIFF_CONTEXT, DEFAULT_DIFF_MAX_FILE_LINES,
+    DEFAULT_LOCAL_TIMEOUT_SECS, DEFAULT_MAX_RETRIES, DEFAULT_MAX_TOKENS, DEFAULT_OPENAI_MODEL,
+    DEFAULT_READ_TIMEOUT_SECS, DEFAULT_RETRY_BASE_DELAY_MS, DEFAULT_STYLE_EXAMPLES,
+    DEFAULT_STYLE_MIN_COMMITS, DEFAULT_STYLE_SAMPLE, DEFAULT_SUMMARIZE_CHUNK_TOKENS,
+    DEFAULT_SUMMARIZE_CONCURRENCY, DEFAULT_SUMMARIZE_MAX_DIFF_TOKENS,
+};
+use crate::provider::ProviderKind;
+
+mod command;
+mod layered;
+mod overrides;
+
+pub use command::handle_config_command;
+pub use layered::{find_repo_config, ConfigSource, LayeredConfig};
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TextWrapConfig {
+    /// Enable text wrapping for long lines
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// Default line width for text wrapping
+    #[serde(default = "default_wrap_width")]
+    pub default_width: usize,
+    /// Preserve word boundaries when wrapping
+    #[serde(default = "default_true")]
+    pub preserve_words: bool,
+    /// Break long words when necessary
+    #[serde(default = "default_true")]
+    pub break_long_words: bool,
+    /// Handle code blocks specially
+    #[serde(default = "default_true")]
+    pub handle_code_blocks: bool,
+    /// Preserve links in text
+    #[serde(default = "default_true")]
+    pub preserve_links: bool,
+    /// Hanging indent for wrapped lines (empty string for no indent)
+    #[serde(default = "default_hanging_indent")]
+    pub hanging_indent: String,
+}
+
+fn default_wrap_width() -> usize {
+    80
+}
+
+fn default_hanging_indent() -> String {
+    String::new() // 默认无悬挂缩进
+}
+
+impl Default for TextWrapConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            default_width: 80,
+            preserve_words: true,
+            break_long_words: true,
+            handle_code_blocks: true,
+            preserve_links: true,
+            hanging_indent: String::new(),
+        }
+    }
+}
+
+/// HTTP settings for talking to the model provider.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct HttpConfig {
+    /// Timeout for establishing a connection, in seconds
+    #[serde(default = "default_connect_timeout_secs")]
+    pub connect_timeout_secs: u64,
+    /// Timeout between two reads of the response, in seconds
+    #[serde(default = "default_read_timeout_secs")]
+    pub read_timeout_secs: u64,
+    /// Retries on connection errors, timeouts, 429 and 5xx responses
+    #[serde(default = "default_max_retries")]
+    pub max_retries: u32,
+    /// Initial backoff delay in milliseconds, doubled on every retry
+    #[serde(default = "default_retry_base_delay_ms")]
+    pub retry_base_delay_ms: u64,
+}
+
+fn default_connect_timeout_secs() -> u64 {
+    DEFAULT_CONNECT_TIMEOUT_SECS
+}
+
+fn default_read_timeout_secs() -> u64 {
+    DEFAULT_READ_TIMEOUT_SECS
+}
+
+fn default_max_retries() -> u32 {
+    DEFAULT_MAX_RETRIES
+}
+
+fn default_retry_base_delay_ms() -> u64 {
+    DEFAULT_RETRY_BASE_DELAY_MS
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        Self {
+            connect_timeout_secs: DEFAULT_CONNECT_TIMEOUT_SECS,
+            read_timeout_secs: DEFAULT_READ_TIMEOUT_SECS,
+            max_retries: DEFAULT_MAX_RETRIES,
+            retry_base_delay_ms: DEFAULT_RETRY_BASE_DELAY_MS,
+        }
+    }
+}
+
+/// Map-reduce summarization of diffs that exceed the model context.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SummarizeConfig {
+    /// Summarize oversized diffs chunk by chunk instead of sending them whole
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// Estimated diff size in tokens above which the diff is summarized
+    #[serde(default = "default_summarize_max_diff_tokens")]
+    pub max_diff_tokens: usize,
+    /// Estimated size in tokens of each chunk sent for summarization
+    #[serde(default = "default_summarize_chunk_tokens")]
+    pub chunk_tokens: usize,
+    /// Number of chunks summarized in parallel
+    #[serde(default = "default_summarize_concurrency")]
+    pub concurrency: usize,
+}
+
+fn default_summarize_max_diff_tokens() -> usize {
+    DEFAULT_SUMMARIZE_MAX_DIFF_TOKENS
+}
+
+fn default_summarize_chunk_tokens() -> usize {
+    DEFAULT_SUMMARIZE_CHUNK_TOKENS
+}
+
+fn default_summarize_concurrency() -> usize {
+    DEFAULT_SUMMARIZE_CONCURRENCY
+}
+
+impl Default for SummarizeConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_diff_tokens: DEFAULT_SUMMARIZE_MAX_DIFF_TOKENS,
+            chunk_tokens: DEFAULT_SUMMARIZE_CHUNK_TOKENS,
+            concurrency: DEFAULT_SUMMARIZE_CONCURRENCY,
+        }
+    }
+}
+
+/// Settings for local model servers (Ollama, llama.cpp).
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LocalProviderConfig {
+    /// Pull the model through Ollama when it is not available locally
+    #[serde(default)]
+    pub auto_pull: bool,
+    /// Read timeout in seconds, replaces `http.read_timeout_secs` for local providers
+    #[serde(default = "default_local_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+fn default_local_timeout_secs() -> u64 {
+    DEFAULT_LOCAL_TIMEOUT_SECS
+}
+
+impl Default for LocalProviderConfig {
+    fn default() -> Self {
+        Self {
+            auto_pull: false,
+            timeout_secs: DEFAULT_LOCAL_TIMEOUT_SECS,
+        }
+    }
+}
+
+/// A named endpoint and model, e.g. `[profiles.local]`. Unset fields fall
+/// back to the top-level settings.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct ProfileConfig {
+    /// Backend of this profile. When it differs from the top-level `provider`,
+    /// the top-level `api_base` and `api_key` are not inherited.
+    pub provider: Option<ProviderKind>,
+    pub api_base: Option<String>,
+    pub api_key: Option<String>,
+    pub model: Option<String>,
+    pub max_tokens: Option<u32>,
+    /// Sampling temperature, replaces `generation.temperature`
+    pub temperature: Option<f32>,
+}
+
+/// Reasoning effort for models that think before answering (OpenAI `reasoning_effort`).
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum ReasoningEffort {
+    #[serde(rename = "low")]
+    Low,
+    #[serde(rename = "medium")]
+    Medium,
+    #[serde(rename = "high")]
+    High,
+}
+
+/// Sampling parameters sent with a request. Unset values use the task's
+/// built-in default or are left to the backend.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct GenerationParams {
+    /// Maximum tokens to generate, falls back to the top-level `max_tokens`
+    pub max_tokens: Option<u32>,
+    pub temperature: Option<f32>,
+    pub top_p: Option<f32>,
+    /// Sequences that end generation
+    pub stop: Option<Vec<String>>,
+    /// Ask reasoning models for more or less thinking; temperature is then
+    /// only sent when set explicitly
+    pub reasoning_effort: Option<ReasoningEffort>,
+}
+
+impl GenerationParams {
+    /// `self` with every value set in `overlay` replaced.
+    fn overlay(&self, overlay: &GenerationParams) -> GenerationParams {
+        GenerationParams {
+            max_tokens: overlay.max_tokens.or(self.max_tokens),
+            temperature: overlay.temperature.or(self.temperature),
+            top_p: overlay.top_p.or(self.top_p),
+            stop: overlay.stop.clone().or_else(|| self.stop.clone()),
+            reasoning_effort: overlay.reasoning_effort.or(self.reasoning_effort),
+        }
+    }
+}
+
+/// `[generation]` applies to every task, `[generation.commit]` etc. to one task.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct GenerationConfig {
+    #[serde(flatten)]
+    pub params: GenerationParams,
+    /// Drop parameters the selected provider doesn't support instead of failing
+    #[serde(default)]
+    pub omit_unsupported: bool,
+    #[serde(default)]
+    pub commit: GenerationParams,
+    #[serde(default)]
+    pub branch: GenerationParams,
+    #[serde(default)]
+    pub pr: GenerationParams,
+}
+
+/// Prompt template files replacing the built-in prompts. Relative paths are
+/// resolved against the directory of the config file that sets them.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct TemplatesConfig {
+    /// Commit message prompt
+    pub commit: Option<PathBuf>,
+    /// Branch name prompt
+    pub branch: Option<PathBuf>,
+    /// PR commit message prompt, defaults to `commit`
+    pub pr: Option<PathBuf>,
+    /// System message of commit, branch and PR requests
+    pub system: Option<PathBuf>,
+}
+
+/// Git information added to the prompt. Everything is off by default so that
+/// nothing beyond the diff is sent unless asked for.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct ContextConfig {
+    /// Current branch name, as `{{branch}}`
+    #[serde(default)]
+    pub branch: bool,
+    /// Changed files with their status, as `{{files}}`
+    #[serde(default)]
+    pub files: bool,
+    /// Per-file line counts like `git diff --stat`, as `{{stat}}`
+    #[serde(default)]
+    pub stat: bool,
+    /// Repository name, as `{{repo}}`
+    #[serde(default)]
+    pub repo: bool,
+    /// Number of recent commit subjects, as `{{recent_commits}}` (0 disables)
+    #[serde(default)]
+    pub recent_commits: usize,
+}
+
+/// Learning the commit message style from the repository's history.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct StyleConfig {
+    /// Show recent commits to the model as examples and follow their convention
+    #[serde(default)]
+    pub learn: bool,
+    /// Number of example messages put in the prompt
+    #[serde(default = "default_style_examples")]
+    pub examples: usize,
+    /// Number of recent commits used to detect the convention
+    #[serde(default = "default_style_sample")]
+    pub sample: usize,
+    /// Below this many commits, fall back to the `conventional` setting
+    #[serde(default = "default_style_min_commits")]
+    pub min_commits: usize,
+    /// Only learn from commits by this author (`git log --author`)
+    #[serde(default)]
+    pub author: Option<String>,
+    /// Only learn from commits touching these paths
+    #[serde(default)]
+    pub paths: Vec<String>,
+}
+
+fn default_style_examples() -> usize {
+    DEFAULT_STYLE_EXAMPLES
+}
+
+fn default_style_sample() -> usize {
+    DEFAULT_STYLE_SAMPLE
+}
+
+fn default_style_min_commits() -> usize {
+    DEFAULT_STYLE_MIN_COMMITS
+}
+
+impl Default for StyleConfig {
+    fn default() -> Self {
+        Self {
+            learn: false,
+            examples: DEFAULT_STYLE_EXAMPLES,
+            sample: DEFAULT_STYLE_SAMPLE,
+            min_commits: DEFAULT_STYLE_MIN_COMMITS,
+            author: None,
+            paths: Vec::new(),
+        }
+    }
+}
+
+/// Files left out of the diff sent to the model, on top of the patterns in
+/// the repository's `.fastcommitignore`.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DiffConfig {
+    /// Gitignore-style patterns, e.g. `Cargo.lock` or `vendor/`
+    #[serde(default)]
+    pub exclude: Vec<String>,
+    /// Also leave out files marked `linguist-generated` or `-diff` in `.gitattributes`
+    #[serde(default = "default_true")]
+    pub gitattributes: bool,
+    /// Replace `Cargo.lock`, `package-lock.json` and `go.sum` hunks with the
+    /// dependency versions they change
+    #[serde(default = "default_true")]
+    pub lockfiles: bool,
+    /// Write the message of lockfile-only changes without calling the model
+    #[serde(default = "default_true")]
+    pub local_deps_message: bool,
+    /// Rewrite renames, mode changes, binary and oversized files as short notes
+    #[serde(default = "default_true")]
+    pub compact: bool,
+    /// Unchanged lines around each change when compacting. Diffs read from git
+    /// get exactly this many; `--diff-file` and PR diffs can only be reduced
+    #[serde(default = "default_diff_context")]
+    pub context: usize,
+    /// Files with more changed lines only show their hunk headers (0 disables)
+    #[serde(default = "default_diff_max_file_lines")]
+    pub max_file_lines: usize,
+    /// Name the function, impl or class enclosing each hunk in its header
+    #[serde(default = "default_true")]
+    pub symbols: bool,
+    /// Token budget for adding the whole content of small changed files (0 disables)
+    #[serde(default)]
+    pub full_file_tokens: usize,
+}
+
+fn default_diff_context() -> usize {
+    DEFAULT_DIFF_CONTEXT
+}
+
+fn default_diff_max_file_lines() -> usize {
+    DEFAULT_DIFF_MAX_FILE_LINES
+}
+
+impl Default for DiffConfig {
+    fn default() -> Self {
+        Self {
+            exclude: Vec::new(),
+            gitattributes: true,
+            lockfiles: true,
+            local_deps_message: true,
+            compact: true,
+            context: DEFAULT_DIFF_CONTEXT,
+            max_file_lines: DEFAULT_DIFF_MAX_FILE_LINES,
+            symbols: true,
+            full_file_tokens: 0,
+        }
+    }
+}
+
+/// Profile used by each task when none is selected with `--profile`.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct TaskProfiles {
+    /// Commit messages, including `fastcommit hook run`
+    pub commit: Option<String>,
+    /// Branch names (`-b`)
+    pub branch: Option<String>,
+    /// PR commit messages (`fastcommit pr`)
+    pub pr: Option<String>,
+}
+
+/// What a model request is generating, used to pick the profile.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Task {
+    Commit,
+    Branch,
+    Pr,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CustomSanitizePattern {
+    /// A short name/identifier for the pattern. e.g. "INTERNAL_URL"
+    pub name: String,
+    /// The regex pattern string. It should be a valid Rust regex.
+    pub regex: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Config {
+    /// Backend used to talk to the model.
+    #[serde(default)]
+    pub provider: ProviderKind,
+    /// Endpoint of the backend, the provider's default when unset; read it
+    /// through [`Config::api_base`].
+    api_base: Option<String>,
+    /// API key, may be left empty for local providers.
+    #[serde(default)]
+    pub api_key: String,
+    pub model: Option<String>,
+    /// The maximum number of tokens to generate in the commit message.
+    pub max_tokens: Option<u32>,
+    /// Sampling parameters, for all tasks and per task
+    #[serde(default)]
+    pub generation: GenerationConfig,
+    /// Named endpoint/model profiles
+    #[serde(default)]
+    pub profiles: BTreeMap<String, ProfileConfig>,
+    /// Profile used for all tasks, usually set with `--profile`
+    pub profile: Option<String>,
+    /// Default profile per task
+    #[serde(default)]
+    pub task_profiles: TaskProfiles,
+    /// Profiles, or models of the same endpoint, tried in order when a request
+    /// fails with a transient error or the reply has no `<aicommit>` block
+    #[serde(default)]
+    pub fallback: Vec<String>,
+    /// Whether to use conventional commit message format.
+    pub conventional: bool,
+    pub language: CommitLanguage,
+    pub verbosity: Verbosity,
+    /// Prefix for generated branch names (e.g. username in monorepo)
+    pub branch_prefix: Option<String>,
+    /// Enable sanitizing sensitive information (API keys, tokens, secrets) before sending diff to AI provider.
+    #[serde(default = "default_true")]
+    pub sanitize_secrets: bool,
+    /// User defined extra regex patterns for sanitizer.
+    #[serde(default)]
+    pub custom_sanitize_patterns: Vec<CustomSanitizePattern>,
+    /// Text wrapping configuration
+    #[serde(default)]
+    pub text_wrap: TextWrapConfig,
+    /// HTTP timeouts and retries
+    #[serde(default)]
+    pub http: HttpConfig,
+    /// Local model server configuration
+    #[serde(default)]
+    pub local: LocalProviderConfig,
+    /// Files excluded from the diff
+    #[serde(default)]
+    pub diff: DiffConfig,
+    /// Summarization of diffs that exceed the model context
+    #[serde(default)]
+    pub summarize: SummarizeConfig,
+    /// Custom prompt template files
+    #[serde(default)]
+    pub templates: TemplatesConfig,
+    /// Extra variables available to prompt templates
+    #[serde(default)]
+    pub template_vars: toml::Table,
+    /// Git information available to prompt templates
+    #[serde(default)]
+    pub context: ContextConfig,
+    /// Commit style learned from the repository's history
+    #[serde(default)]
+    pub style: StyleConfig,
+    /// Stream the commit message to the terminal while it is generated
+    #[serde(default)]
+    pub stream: bool,
+    /// Automatically run git commit after generating the message
+    #[serde(default)]
+    pub auto_commit: bool,
+    /// Extra arguments to pass to git commit
+    #[serde(default)]
+    pub commit_args: Vec<String>,
+}
+
+impl Config {
+    pub fn api_base(&self) -> String {
+        let api_base = self
+            .api_base
+            .as_deref()
+            .unwrap_or(self.provider.default_api_base());
+
+        let api_base = if api_base.ends_with("/") {
+            api_base.to_owned()
+        } else {
+            format!("{api_base}/")
+        };
+
+        api_base
+    }
+
+    /// The config used for `task`: its profile, if any, applied over the
+    /// top-level settings (`profile` takes priority over `task_profiles`), and
+    /// `generation.params` resolved from `[generation]`, the profile and
+    /// `[generation.<task>]`, in increasing priority.
+    ///
+    /// The result has no profile or per-task settings left, so resolving it
+    /// again changes nothing.
+    pub fn for_task(&self, task: Task) -> anyhow::Result<Config> {
+        let (task_profile, task_params) = match task {
+            Task::Commit => (&self.task_profiles.commit, &self.generation.commit),
+            Task::Branch => (&self.task_profiles.branch, &self.generation.branch),
+            Task::Pr => (&self.task_profiles.pr, &self.generation.pr),
+        };
+        let mut config = self.clone();
+        config.profile = None;
+        config.task_profiles = TaskProfiles::default();
+        config.generation.commit = GenerationParams::default();
+        config.generation.branch = GenerationParams::default();
+        config.generation.pr = GenerationParams::default();
+
+        if let Some(name) = self.profile.as_deref().or(task_profile.as_deref()) {
+            log::debug!("Using profile '{name}' for {task:?}");
+            self.apply_profile(name, &mut config)?;
+        }
+        config.generation.params = config.generation.params.overlay(task_params);
+        Ok(config)
+    }
+
+    /// The configs of the `fallback` entries, in order, each with a name for messages.
+    ///
+    /// An entry naming a profile applies it over this config, any other entry
+    /// is a model on the same endpoint.
+    pub fn fallbacks(&self) -> anyhow::Result<Vec<(String, Config)>> {
+        self.fallback
+            .iter()
+            .map(|entry| {
+                let mut config = self.clone();
+                config.fallback.clear();
+                if self.profiles.contains_key(entry) {
+                    self.apply_profile(entry, &mut config)?;
+                    let name = format!("{} ({})", entry, config.backend_name());
+                    Ok((name, config))
+                } else {
+                    config.model = Some(entry.clone());
+                    Ok((config.backend_name(), config))
+                }
+            })
+            .collect()
+    }
+
+    /// `provider/model`, e.g. `ollama/qwen2.5-coder`.
+    pub fn backend_name(&self) -> String {
+        format!(
+            "{}/{}",
+            self.provider,
+            self.model.as_deref().unwrap_or(DEFAULT_OPENAI_MODEL)
+        )
+    }
+
+    fn apply_profile(&self, name: &str, config: &mut Config) -> anyhow::Result<()> {
+        let profile = self.profiles.get(name).ok_or_else(|| {
+            let known: Vec<&str> = self.profiles.keys().map(String::as_str).collect();
+            anyhow::anyhow!(
+                "Unknown profile '{}', defined profiles: {}",
+                name,
+                if known.is_empty() {
+                    "none".to_string()
+                } else {
+                    known.join(", ")
+                }
+            )
+        })?;
+
+        if let Some(provider) = profile.provider {
+            // 切换到其他后端时不沿用原有的地址和密钥
+            if provider != self.provider {
+                config.api_base = None;
+                config.api_key = String::new();
+            }
+            config.provider = provider;
+        }
+        if let Some(api_base) = &profile.api_base {
+            config.api_base = Some(api_base.clone());
+        }
+        if let Some(api_key) = &profile.api_key {
+            config.api_key = api_key.clone();
+        }
+        if let Some(model) = &profile.model {
+            config.model = Some(model.clone());
+        }
+        let params = &mut config.generation.params;
+        params.max_tokens = profile.max_tokens.or(params.max_tokens);
+        params.temperature = profile.temperature.or(params.temperature);
+        Ok(())
+    }
+}
+
+/// Commit message verbosity level.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Copy, clap::ValueEnum, Default)]
+pub enum Verbosity {
+    /// Detailed commit message.
+    #[serde(rename = "verbose")]
+    #[clap(name = "verbose")]
+    Verbose,
+    /// Normal commit message.
+    #[serde(rename = "normal")]
+    #[clap(name = "normal")]
+    Normal,
+    /// Quiet commit message.
+    #[serde(rename = "quiet")]
+    #[clap(name = "quiet")]
+    #[default]
+    Quiet,
+}
+
+impl Verbosity {
+    pub fn as_template_level(self) -> &'static str {
+        match self {
+            Verbosity::Verbose => "详细",
+            Verbosity::Normal => "中等",
+            Verbosity::Quiet => "简洁",
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, clap::ValueEnum, Default)]
+pub enum CommitLanguage {
+    #[clap(name = "en")]
+    #[serde(rename = "en")]
+    English,
+    #[clap(name = "zh")]
+    #[serde(rename = "zh")]
+    #[default]
+    Chinese,
+}
+
+impl Display for CommitLanguage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CommitLanguage::English => write!(f, "English"),
+            CommitLanguage::Chinese => write!(f, "中文"),
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            provider: ProviderKind::default(),
+            api_base: None,
+            api_key: "".to_owned(),
+            model: Some(DEFAULT_OPENAI_MODEL.into()),
+            max_tokens: Some(DEFAULT_MAX_TOKENS),
+            generation: GenerationConfig::default(),
+            profiles: BTreeMap::new(),
+            profile: None,
+            task_profiles: TaskProfiles::default(),
+            fallback: Vec::new(),
+            conventional: true,
+            language: CommitLanguage::default(),
+            verbosity: Verbosity::default(),
+            branch_prefix: None,
+            sanitize_secrets: true,
+            custom_sanitize_patterns: Vec::new(),
+            text_wrap: TextWrapConfig::default(),
+            http: HttpConfig::default(),
+            local: LocalProviderConfig::default(),
+            diff: DiffConfig::default(),
+            summarize: SummarizeConfig::default(),
+            templates: TemplatesConfig::default(),
+            template_vars: toml::Table::new(),
+            context: ContextConfig::default(),
+            style: StyleConfig::default(),
+            stream: false,
+            auto_commit: false,
+            commit_args: Vec::new(),
+        }
+    }
+}
+
+pub fn global_config_path() -> anyhow::Result<std::path::PathBuf> {
+    dirs::home_dir()
+        .map(|p| p.join(".fastcommit/config.toml"))
+        .ok_or_else(|| anyhow::anyhow!("Failed to get home directory"))
+}
+
+/// Resolve the config layers: built-in defaults, `~/.fastcommit/config.toml`,
+/// the repository's `.fastcommit.toml`, `git config fastcommit.*`,
+/// `FASTCOMMIT_*` environment variables, then `cli` overrides.
+///
+/// Every file is optional, so the tool also runs without any config on disk.
+pub async fn load_layered_config(cli: toml::Table) -> anyhow::Result<LayeredConfig> {
+    let mut layered = LayeredConfig::new()?;
+    let config_path = global_config_path()?;
+    if config_path.exists() {
+        layered.push_file(ConfigSource::Global(config_path.clone()), &config_path)?;
+    }
+    if let Some(path) = find_repo_config() {
+        log::debug!("Using repository config {}", path.display());
+        layered.push_file(ConfigSource::Repo(path.clone()), &path)?;
+    }
+    layered.push(ConfigSource::GitConfig, overrides::git_config_overrides()?);
+    layered.push(ConfigSource::Env, overrides::env_overrides()?);
+    if !cli.is_empty() {
+        layered.push(ConfigSource::Cli, cli);
+    }
+    Ok(layered)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(text: &str) -> Config {
+        let mut layered = LayeredConfig::new().unwrap();
+        layered.push(ConfigSource::Cli, toml::from_str(text).unwrap());
+        layered.config().unwrap()
+    }
+
+    #[test]
+    fn test_task_profiles() {
+        let base = config(
+            r#"
+            api_key = "sk-global"
+            model = "gpt-4o"
+            [profiles.cheap]
+            model = "gpt-4o-mini"
+            max_tokens = 64
+            [profiles.local]
+            provider = "ollama"
+            model = "qwen2.5-coder"
+            temperature = 0.1
+            [task_profiles]
+            branch = "cheap"
+            pr = "local"
+            "#,
+        );
+
+        let commit = base.for_task(Task::Commit).unwrap();
+        assert_eq!(commit.model.as_deref(), Some("gpt-4o"));
+
+        let branch = base.for_task(Task::Branch).unwrap();
+        assert_eq!(branch.model.as_deref(), Some("gpt-4o-mini"));
+        assert_eq!(branch.generation.params.max_tokens, Some(64));
+        assert_eq!(branch.api_key, "sk-global");
+
+        // 切换后端时不继承全局的密钥和地址
+        let pr = base.for_task(Task::Pr).unwrap();
+        assert_eq!(pr.provider, ProviderKind::Ollama);
+        assert_eq!(pr.api_key, "");
+        assert_eq!(pr.api_base(), "http://localhost:11434/");
+        assert_eq!(pr.generation.params.temperature, Some(0.1));
+
+        // --profile 优先于按任务的默认值
+        let forced = Config {
+            profile: Some("local".into()),
+            ..base.clone()
+        };
+        assert_eq!(
+            forced.for_task(Task::Branch).unwrap().model.as_deref(),
+            Some("qwen2.5-coder")
+        );
+
+        let fallback = Config {
+            fallback: vec!["local".into(), "gpt-4o-mini".into()],
+            ..base.clone()
+        };
+        let names: Vec<String> = fallback
+            .for_task(Task::Commit)
+            .unwrap()
+            .fallbacks()
+            .unwrap()
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect();
+        assert_eq!(
+            names,
+            vec!["local (ollama/qwen2.5-coder)", "openai/gpt-4o-mini"]
+        );
+
+        // 默认配置不带 api_base，由 provider 决定地址
+        let anthropic = Config {
+            provider: ProviderKind::Anthropic,
+            ..Config::default()
+        };
+        assert_eq!(anthropic.api_base(), "https://api.anthropic.com/v1/");
+
+        let unknown = Config {
+            profile: Some("nope".into()),
+            ..base
+        };
+        assert_eq!(
+            unknown.for_task(Task::Commit).unwrap_err().to_string(),
+            "Unknown profile 'nope', defined profiles: cheap, local"
+        );
+    }
+}
@@ -0,0 +1,382 @@
+//! Config layers read from `FASTCOMMIT_*` environment variables and `git config fastcommit.*`.
+
+use std::collections::BTreeMap;
+use std::process::Command;
+
+use toml::{Table, Value};
+
+use super::Config;
+
+/// Prefix of environment variables that override config keys.
+pub const ENV_PREFIX: &str = "FASTCOMMIT_";
+
+/// List keys whose elements never contain spaces, so they may also be given
+/// as space separated words. Other lists need TOML array syntax.
+const WORD_LIST_KEYS: &[&str] = &["commit_args", "fallback", "diff.exclude"];
+
+/// Optional keys that are unset by default and so missing from the serialized
+/// defaults, with a sample value of their type.
+fn optional_keys() -> Vec<(String, Value)> {
+    let string = || Value::String(String::new());
+    let mut keys = vec![
+        ("api_base".to_string(), string()),
+        ("branch_prefix".to_string(), string()),
+        ("profile".to_string(), string()),
+        ("task_profiles.commit".to_string(), string()),
+        ("task_profiles.branch".to_string(), string()),
+        ("task_profiles.pr".to_string(), string()),
+        ("templates.commit".to_string(), string()),
+        ("templates.branch".to_string(), string()),
+        ("templates.pr".to_string(), string()),
+        ("templates.system".to_string(), string()),
+        ("style.author".to_string(), string()),
+    ];
+    for table in [
+        "generation",
+        "generation.commit",
+        "generation.branch",
+        "generation.pr",
+    ] {
+        keys.extend([
+            (format!("{table}.max_tokens"), Value::Integer(0)),
+            (format!("{table}.temperature"), Value::Float(0.0)),
+            (format!("{table}.top_p"), Value::Float(0.0)),
+            (format!("{table}.stop"), Value::Array(Vec::new())),
+            (format!("{table}.reasoning_effort"), string()),
+        ]);
+    }
+    keys
+}
+
+/// Fields of a `[profiles.<name>]` table, with a sample value of their type.
+pub(super) fn profile_fields() -> [(&'static str, Value); 6] {
+    let string = || Value::String(String::new());
+    [
+        ("provider", string()),
+        ("api_base", string()),
+        ("api_key", string()),
+        ("model", string()),
+        ("max_tokens", Value::Integer(0)),
+        ("temperature", Value::Float(0.0)),
+    ]
+}
+
+/// Split `profiles.<name>.<field>` into the profile name and a known field.
+pub(super) fn split_profile_key(key: &str) -> Option<(&str, &'static str)> {
+    let (name, field) = key.strip_prefix("profiles.")?.split_once('.')?;
+    let (field, _) = profile_fields().into_iter().find(|(f, _)| *f == field)?;
+    Some((name, field))
+}
+
+/// Every settable dotted key, indexed by its normalized name, with its default
+/// value (if any) deciding how strings are converted.
+///
+/// Profile keys are not included since their names are not known up front.
+pub(super) fn known_keys() -> BTreeMap<String, (String, Option<Value>)> {
+    let mut keys = BTreeMap::new();
+    if let Ok(Value::Table(defaults)) = Value::try_from(Config::default()) {
+        collect_keys("", &defaults, &mut keys);
+    }
+    for (key, sample) in optional_keys() {
+        keys.insert(normalize(&key), (key, Some(sample)));
+    }
+    keys
+}
+
+/// Keys of tables whose entries are named by the user, `profiles.<name>.<field>`
+/// and `template_vars.<name>`, in their canonical form with a sample value.
+fn user_named_key(name: &str) -> Option<(String, Option<Value>)> {
+    let (table, rest) = name.split_once('.')?;
+    match normalize(table).as_str() {
+        "profiles" => {
+            let (profile, field) = rest.split_once('.')?;
+            // git 的变量名不能含 `_`，字段名按规范化后的形式匹配
+            let (field, sample) = profile_fields()
+                .into_iter()
+                .find(|(f, _)| normalize(f) == normalize(field))?;
+            Some((format!("profiles.{profile}.{field}"), Some(sample)))
+        }
+        "templatevars" if !rest.is_empty() && !rest.contains('.') => {
+            Some((format!("template_vars.{rest}"), None))
+        }
+        _ => None,
+    }
+}
+
+fn collect_keys(prefix: &str, table: &Table, out: &mut BTreeMap<String, (String, Option<Value>)>) {
+    for (key, value) in table {
+        let key = if prefix.is_empty() {
+            key.clone()
+        } else {
+            format!("{prefix}.{key}")
+        };
+        match value {
+            Value::Table(table) => collect_keys(&key, table, out),
+            value => {
+                out.insert(normalize(&key), (key, Some(value.clone())));
+            }
+        }
+    }
+}
+
+/// `TEXT_WRAP__DEFAULT_WIDTH`, `text_wrap.defaultwidth` and `text-wrap.default-width`
+/// all name the same key: case, `_`, `-` and `.` are ignored.
+fn normalize(name: &str) -> String {
+    name.chars()
+        .filter(|c| !matches!(c, '_' | '-' | '.'))
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// Convert a string from the environment or git config for `key` to the type of `template`.
+pub(super) fn parse_value(key: &str, raw: &str, template: Option<&Value>) -> Result<Value, String> {
+    let invalid = |kind: &str| format!("expected {kind}, got '{raw}'");
+    match template {
+        None | Some(Value::String(_)) => Ok(Value::String(raw.to_string())),
+        Some(Value::Boolean(_)) => match raw.trim().to_lowercase().as_str() {
+            "true" | "yes" | "on" | "1" => Ok(Value::Boolean(true)),
+            "false" | "no" | "off" | "0" => Ok(Value::Boolean(false)),
+            _ => Err(invalid("a boolean")),
+        },
+        Some(Value::Integer(_)) => raw
+            .trim()
+            .parse()
+            .map(Value::Integer)
+            .map_err(|_| invalid("an integer")),
+        Some(Value::Float(_)) => raw
+            .trim()
+            .parse()
+            .map(Value::Float)
+            .map_err(|_| invalid("a number")),
+        // 元素不含空格的列表也可写成以空白分隔的字符串
+        Some(Value::Array(_)) if !raw.trim_start().starts_with('[') => {
+            if !WORD_LIST_KEYS.contains(&key) {
+                return Err(invalid(r#"a TOML array such as ["a", "b"]"#));
+            }
+            Ok(Value::Array(
+                raw.split_whitespace()
+                    .map(|s| Value::String(s.into()))
+                    .collect(),
+            ))
+        }
+        Some(_) => toml::from_str::<Table>(&format!("value = {raw}"))
+            .ok()
+            .and_then(|mut t| t.remove("value"))
+            .ok_or_else(|| invalid("a TOML value")),
+    }
+}
+
+pub(super) fn insert_dotted(table: &mut Table, key: &str, value: Value) {
+    match key.split_once('.') {
+        Some((head, rest)) => {
+            let entry = table
+                .entry(head)
+                .or_insert_with(|| Value::Table(Table::new()));
+            if let Value::Table(inner) = entry {
+                insert_dotted(inner, rest, value);
+            }
+        }
+        None => {
+            table.insert(key.to_string(), value);
+        }
+    }
+}
+
+/// Build a layer from `(name, value)` pairs, where `describe` names the origin
+/// of a pair in error messages.
+fn overrides_table(
+    pairs: impl IntoIterator<Item = (String, String)>,
+    describe: impl Fn(&str) -> String,
+) -> anyhow::Result<Table> {
+    let keys = known_keys();
+    let mut table = Table::new();
+    for (name, raw) in pairs {
+        let Some((key, template)) = keys
+            .get(&normalize(&name))
+            .cloned()
+            .or_else(|| user_named_key(&name))
+        else {
+            log::warn!("Ignoring {}: no such config key", describe(&name));
+            continue;
+        };
+        let value = parse_value(&key, &raw, template.as_ref())
+            .map_err(|e| anyhow::anyhow!("Invalid {}: {}", describe(&name), e))?;
+        insert_dotted(&mut table, &key, value);
+    }
+    Ok(table)
+}
+
+/// Overrides from `FASTCOMMIT_<KEY>` variables, e.g. `FASTCOMMIT_API_KEY` or
+/// `FASTCOMMIT_TEXT_WRAP__DEFAULT_WIDTH`.
+pub fn env_overrides() -> anyhow::Result<Table> {
+    // 非 UTF-8 的变量不可能是我们的配置，直接跳过
+    env_table(
+        std::env::vars_os().filter_map(|(name, value)| {
+            Some((name.into_string().ok()?, value.into_string().ok()?))
+        }),
+    )
+}
+
+fn env_table(vars: impl IntoIterator<Item = (String, String)>) -> anyhow::Result<Table> {
+    // `__` 分隔表名，如 PROFILES__LOCAL__MODEL 对应 profiles.local.model
+    let pairs = vars.into_iter().filter_map(|(name, value)| {
+        let name = name.strip_prefix(ENV_PREFIX)?;
+        Some((name.to_lowercase().replace("__", "."), value))
+    });
+    overrides_table(pairs, |name| {
+        format!(
+            "environment variable {ENV_PREFIX}{}",
+            name.replace('.', "__").to_uppercase()
+        )
+    })
+}
+
+/// Overrides from `git config fastcommit.<key>`, e.g. `fastcommit.apiKey` or
+/// `fastcommit.text_wrap.defaultWidth` (git doesn't allow `_` in variable names).
+pub fn git_config_overrides() -> anyhow::Result<Table> {
+    let output = Command::new("git")
+        .args(["config", "--get-regexp", r"^fastcommit\."])
+        .output();
+    // 没有匹配项时 git 以 1 退出；不在仓库中或未安装 git 时同样视为空
+    match output {
+        Ok(output) if output.status.success() => {
+            git_config_table(&String::from_utf8_lossy(&output.stdout))
+        }
+        _ => Ok(Table::new()),
+    }
+}
+
+fn git_config_table(output: &str) -> anyhow::Result<Table> {
+    let pairs = output.lines().filter_map(|line| {
+        let (name, value) = line.split_once(' ').unwrap_or((line, "true"));
+        Some((
+            name.strip_prefix("fastcommit.")?.to_string(),
+            value.to_string(),
+        ))
+    });
+    overrides_table(pairs, |name| format!("git config fastcommit.{name}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_env_overrides() {
+        let table = env_table(vars(&[
+            ("FASTCOMMIT_API_KEY", "sk-env"),
+            ("FASTCOMMIT_MODEL", "gpt-test"),
+            ("FASTCOMMIT_CONVENTIONAL", "no"),
+            ("FASTCOMMIT_TEXT_WRAP__DEFAULT_WIDTH", "100"),
+            ("FASTCOMMIT_COMMIT_ARGS", "-s --no-verify"),
+            ("FASTCOMMIT_BRANCH_PREFIX", "ci/"),
+            ("FASTCOMMIT_NOT_A_KEY", "x"),
+            ("PATH", "/bin"),
+        ]))
+        .unwrap();
+
+        let expected: Table = toml::from_str(
+            r#"
+            api_key = "sk-env"
+            model = "gpt-test"
+            conventional = false
+            commit_args = ["-s", "--no-verify"]
+            branch_prefix = "ci/"
+            [text_wrap]
+            default_width = 100
+            "#,
+        )
+        .unwrap();
+        assert_eq!(table, expected);
+    }
+
+    #[test]
+    fn test_profile_and_template_var_overrides() {
+        let expected: Table = toml::from_str(
+            r#"
+            [profiles.local]
+            model = "qwen2.5-coder"
+            max_tokens = 64
+            [template_vars]
+            team = "payments"
+            "#,
+        )
+        .unwrap();
+        let table = env_table(vars(&[
+            ("FASTCOMMIT_PROFILES__LOCAL__MODEL", "qwen2.5-coder"),
+            ("FASTCOMMIT_PROFILES__LOCAL__MAX_TOKENS", "64"),
+            ("FASTCOMMIT_TEMPLATE_VARS__TEAM", "payments"),
+            ("FASTCOMMIT_PROFILES__LOCAL__NOPE", "x"),
+        ]))
+        .unwrap();
+        assert_eq!(table, expected);
+
+        let table = git_config_table(
+            "fastcommit.profiles.local.model qwen2.5-coder
+fastcommit.profiles.local.maxtokens 64
+fastcommit.template_vars.team payments
+",
+        )
+        .unwrap();
+        assert_eq!(table, expected);
+    }
+
+    #[test]
+    fn test_list_overrides() {
+        let table = env_table(vars(&[
+            ("FASTCOMMIT_FALLBACK", "local gpt-4o-mini"),
+            ("FASTCOMMIT_GENERATION__STOP", r#"["\n\n", "END OF"]"#),
+        ]))
+        .unwrap();
+        let expected: Table = toml::from_str(
+            r#"
+            fallback = ["local", "gpt-4o-mini"]
+            [generation]
+            stop = ["\n\n", "END OF"]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(table, expected);
+
+        let err = env_table(vars(&[("FASTCOMMIT_GENERATION__STOP", "END OF")])).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            r#"Invalid environment variable FASTCOMMIT_GENERATION__STOP: expected a TOML array such as ["a", "b"], got 'END OF'"#
+        );
+    }
+
+    #[test]
+    fn test_env_override_with_wrong_type() {
+        let err = env_table(vars(&[("FASTCOMMIT_MAX_TOKENS", "lots")])).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Invalid environment variable FASTCOMMIT_MAX_TOKENS: expected an integer, got 'lots'"
+        );
+    }
+
+    #[test]
+    fn test_git_config_overrides() {
+        let table = git_config_table(
+            "fastcommit.apikey sk-git\nfastcommit.language en\nfastcommit.text_wrap.defaultwidth 90\nfastcommit.stream\n",
+        )
+        .unwrap();
+        let expected: Table = toml::from_str(
+            r#"
+            api_key = "sk-git"
+            language = "en"
+            stream = true
+            [text_wrap]
+            default_width = 90
+            "#,
+        )
+        .unwrap();
+        assert_eq!(table, expected);
+    }
+}
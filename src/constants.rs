@@ -2,6 +2,26 @@ pub const DEFAULT_OPENAI_API_BASE: &str = "https://api.openai.com/v1";
 
 pub const DEFAULT_OPENAI_MODEL: &str = "gpt-3.5-turbo";
 
+pub const DEFAULT_ANTHROPIC_API_BASE: &str = "https://api.anthropic.com/v1";
+
+pub const DEFAULT_OLLAMA_API_BASE: &str = "http://localhost:11434";
+
+pub const DEFAULT_LLAMACPP_API_BASE: &str = "http://localhost:8080/v1";
+
+pub const DEFAULT_CONNECT_TIMEOUT_SECS: u64 = 10;
+
+pub const DEFAULT_READ_TIMEOUT_SECS: u64 = 120;
+
+pub const DEFAULT_MAX_RETRIES: u32 = 3;
+
+pub const DEFAULT_RETRY_BASE_DELAY_MS: u64 = 1000;
+
+/// Local models can be slow on CPU, so they get a much longer read timeout.
+pub const DEFAULT_LOCAL_TIMEOUT_SECS: u64 = 600;
+
+/// Value sent in the `anthropic-version` header.
+pub const ANTHROPIC_API_VERSION: &str = "2023-06-01";
+
 pub const DEFAULT_PROMPT_TEMPLATE: &str = r#"
 # 角色
 
@@ -15,6 +35,33 @@ pub const DEFAULT_PROMPT_TEMPLATE: &str = r#"
 
 {{user_description}}
 
+{{#if repo}}
+仓库：{{repo}}
+
+{{/if}}
+{{#if branch}}
+当前分支：{{branch}}
+
+{{/if}}
+{{#if recent_commits}}
+# 最近的提交标题（可参考其风格）
+{{#each recent_commits}}
+- {{this}}
+{{/each}}
+
+{{/if}}
+{{#if style}}
+# 仓库提交风格
+
+本仓库的提交信息通常采用{{style}}。请遵循相同的风格，此要求优先于约定式提交规范。以下是最近的提交示例：
+
+{{#each style_examples}}
+<example>
+{{this}}
+</example>
+{{/each}}
+
+{{/if}}
 # 什么是约定式提交规范？
 
 约定式提交 1.0.0
@@ -65,6 +112,20 @@ message内容要使用<aicommit>标签包裹，例如：
 
 </aicommit>
 
+{{#if files}}
+变更文件（A 新增，M 修改，D 删除，R 重命名）：
+
+{{#each files}}
+- {{this.status}} {{this.path}}
+{{/each}}
+
+{{/if}}
+{{#if stat}}
+变更统计：
+
+{{stat}}
+
+{{/if}}
 变更内容：
 
 {{diff}}
@@ -77,16 +138,46 @@ pub enum PromptTemplateReplaceLabel {
     ConventionalCommit,
     Diff,
     UserDescription,
+    Branch,
+    Files,
+    Stat,
+    Repo,
+    RecentCommits,
+    Style,
+    StyleExamples,
 }
 
 impl PromptTemplateReplaceLabel {
-    pub fn get_label(&self) -> &str {
+    pub const ALL: [PromptTemplateReplaceLabel; 12] = [
+        PromptTemplateReplaceLabel::Language,
+        PromptTemplateReplaceLabel::VerbosityLevel,
+        PromptTemplateReplaceLabel::ConventionalCommit,
+        PromptTemplateReplaceLabel::Diff,
+        PromptTemplateReplaceLabel::UserDescription,
+        PromptTemplateReplaceLabel::Branch,
+        PromptTemplateReplaceLabel::Files,
+        PromptTemplateReplaceLabel::Stat,
+        PromptTemplateReplaceLabel::Repo,
+        PromptTemplateReplaceLabel::RecentCommits,
+        PromptTemplateReplaceLabel::Style,
+        PromptTemplateReplaceLabel::StyleExamples,
+    ];
+
+    /// Name used between `{{` and `}}` in templates.
+    pub fn name(&self) -> &'static str {
         match self {
-            PromptTemplateReplaceLabel::Language => "{{language}}",
-            PromptTemplateReplaceLabel::VerbosityLevel => "{{verbosity_level}}",
-            PromptTemplateReplaceLabel::ConventionalCommit => "{{conventional_commit}}",
-            PromptTemplateReplaceLabel::Diff => "{{diff}}",
-            PromptTemplateReplaceLabel::UserDescription => "{{user_description}}",
+            PromptTemplateReplaceLabel::Language => "language",
+            PromptTemplateReplaceLabel::VerbosityLevel => "verbosity_level",
+            PromptTemplateReplaceLabel::ConventionalCommit => "conventional_commit",
+            PromptTemplateReplaceLabel::Diff => "diff",
+            PromptTemplateReplaceLabel::UserDescription => "user_description",
+            PromptTemplateReplaceLabel::Branch => "branch",
+            PromptTemplateReplaceLabel::Files => "files",
+            PromptTemplateReplaceLabel::Stat => "stat",
+            PromptTemplateReplaceLabel::Repo => "repo",
+            PromptTemplateReplaceLabel::RecentCommits => "recent_commits",
+            PromptTemplateReplaceLabel::Style => "style",
+            PromptTemplateReplaceLabel::StyleExamples => "style_examples",
         }
     }
 }
@@ -132,5 +223,37 @@ username-refactor-payment-module
 {{diff}}
 "#;
 
+pub const DIFF_CHUNK_SUMMARY_PROMPT: &str = r#"
+# 角色
+作为代码版本控制专家，请总结以下 diff 片段中的变更。该片段只是一次大型提交的一部分，总结将用于之后生成完整的commit message。
+
+# 要求：
+1. 使用{{language}}编写
+2. 按文件列出变更，说明改了什么以及可能的目的
+3. 保留关键的函数名、类型名和配置项名称
+4. 不要输出 commit message，只输出变更总结
+5. 总结内容要使用<aicommit></aicommit>标签包裹
+
+变更内容：
+
+{{diff}}
+"#;
+
+pub const DEFAULT_DIFF_CONTEXT: usize = 1;
+
+pub const DEFAULT_DIFF_MAX_FILE_LINES: usize = 1000;
+
+pub const DEFAULT_STYLE_EXAMPLES: usize = 5;
+
+pub const DEFAULT_STYLE_SAMPLE: usize = 50;
+
+pub const DEFAULT_STYLE_MIN_COMMITS: usize = 10;
+
+pub const DEFAULT_SUMMARIZE_MAX_DIFF_TOKENS: usize = 12000;
+
+pub const DEFAULT_SUMMARIZE_CHUNK_TOKENS: usize = 6000;
+
+pub const DEFAULT_SUMMARIZE_CONCURRENCY: usize = 4;
+
 pub const UPDATE_CHECKER_URL: &str =
     "http://update-checker.longjin666.cn/v1/updates/fastcommit/latest";
@@ -0,0 +1,238 @@
+//! Leaving lockfiles, vendored code and generated files out of the diff sent
+//! to the model. Excluded files are replaced by a one-line note so the model
+//! still knows they changed.
+
+use std::collections::HashSet;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+
+use crate::config::DiffConfig;
+use crate::git_context::{changed_files, git};
+use crate::summarize::split_before;
+
+/// Gitignore-style exclusion rules at the root of the repository.
+pub const IGNORE_FILE: &str = ".fastcommitignore";
+
+/// At most this many excluded paths are named in the note.
+const MAX_NOTED_PATHS: usize = 10;
+
+/// Remove the files excluded by `.fastcommitignore`, `config.exclude` and
+/// `.gitattributes` from `diff`, noting them at the end. Without `in_checkout`
+/// the diff is of another repository, e.g. a PR of `--repo`, and only
+/// `config.exclude` applies.
+pub fn filter_diff(diff: &str, config: &DiffConfig, in_checkout: bool) -> String {
+    let root = git(&["rev-parse", "--show-toplevel"])
+        .map(PathBuf::from)
+        .or_else(|| std::env::current_dir().ok())
+        .filter(|_| in_checkout);
+    let matcher = build_matcher(root.as_deref(), config);
+    let generated = if let Some(root) = root.filter(|_| config.gitattributes) {
+        let paths: Vec<String> = changed_files(diff).into_iter().map(|f| f.path).collect();
+        generated_files(&root, &paths)
+    } else {
+        HashSet::new()
+    };
+    exclude_files(diff, |path| {
+        generated.contains(path)
+            || (!path.starts_with('/')
+                && matcher.matched_path_or_any_parents(path, false).is_ignore())
+    })
+}
+
+/// Rules from `<root>/.fastcommitignore` followed by `config.exclude`, so that
+/// config patterns (including `!` negations) take precedence.
+fn build_matcher(root: Option<&Path>, config: &DiffConfig) -> Gitignore {
+    let mut builder = GitignoreBuilder::new(root.unwrap_or(Path::new("")));
+    if let Some(ignore_file) = root.map(|r| r.join(IGNORE_FILE)) {
+        if ignore_file.is_file() {
+            if let Some(e) = builder.add(&ignore_file) {
+                log::warn!("Invalid pattern in {}: {}", ignore_file.display(), e);
+            }
+        }
+    }
+    for pattern in &config.exclude {
+        if let Err(e) = builder.add_line(None, pattern) {
+            log::warn!("Invalid diff.exclude pattern '{}': {}", pattern, e);
+        }
+    }
+    builder.build().unwrap_or_else(|e| {
+        log::warn!("Failed to build diff exclusion rules: {}", e);
+        Gitignore::empty()
+    })
+}
+
+/// Paths marked `linguist-generated` or `-diff` by the repository's attributes.
+///
+/// The paths go through stdin, a huge diff could exceed the argument limit.
+fn generated_files(root: &Path, paths: &[String]) -> HashSet<String> {
+    if paths.is_empty() {
+        return HashSet::new();
+    }
+    let output = check_attr(root, paths).unwrap_or_else(|e| {
+        log::warn!(
+            "git check-attr failed, not excluding generated files: {}",
+            e
+        );
+        String::new()
+    });
+    parse_check_attr(&output)
+}
+
+fn check_attr(root: &Path, paths: &[String]) -> anyhow::Result<String> {
+    let mut child = Command::new("git")
+        .arg("-C")
+        .arg(root)
+        .args(["check-attr", "--stdin", "-z", "linguist-generated", "diff"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    let mut input = Vec::new();
+    for path in paths {
+        input.extend_from_slice(path.as_bytes());
+        input.push(0);
+    }
+    // 在另一个线程写入，避免输出填满管道时双方互相等待
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    let writer = std::thread::spawn(move || stdin.write_all(&input));
+    let output = child.wait_with_output()?;
+    writer
+        .join()
+        .map_err(|_| anyhow::anyhow!("writing to git check-attr panicked"))??;
+    if !output.status.success() {
+        return Err(anyhow::anyhow!(
+            "{}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Parse `git check-attr -z` output, `path NUL attribute NUL value NUL` per line.
+fn parse_check_attr(output: &str) -> HashSet<String> {
+    let fields: Vec<&str> = output.split('\0').collect();
+    fields
+        .chunks_exact(3)
+        .filter(|entry| {
+            matches!(
+                (entry[1], entry[2]),
+                ("linguist-generated", "set" | "true") | ("diff", "unset")
+            )
+        })
+        .map(|entry| entry[0].to_string())
+        .collect()
+}
+
+/// Drop the file sections of `diff` whose path is `excluded`.
+fn exclude_files(diff: &str, excluded: impl Fn(&str) -> bool) -> String {
+    let mut kept = String::new();
+    let mut dropped: Vec<String> = Vec::new();
+    for section in split_before(diff, |l| l.starts_with("diff --git ")) {
+        let path = changed_files(&section).into_iter().next().map(|f| f.path);
+        match path {
+            Some(path) if excluded(&path) => dropped.push(path),
+            _ => kept.push_str(&section),
+        }
+    }
+    if dropped.is_empty() {
+        return kept;
+    }
+
+    log::info!("Excluded {} files from the diff", dropped.len());
+    let mut names = dropped
+        .iter()
+        .take(MAX_NOTED_PATHS)
+        .map(String::as_str)
+        .collect::<Vec<_>>()
+        .join(", ");
+    if dropped.len() > MAX_NOTED_PATHS {
+        names.push_str(&format!(" and {} more", dropped.len() - MAX_NOTED_PATHS));
+    }
+    let files = if dropped.len() == 1 { "file" } else { "files" };
+    if !kept.is_empty() && !kept.ends_with('\n') {
+        kept.push('\n');
+    }
+    kept.push_str(&format!(
+        "{} {} changed (excluded): {}\n",
+        dropped.len(),
+        files,
+        names
+    ));
+    kept
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DIFF: &str = "\
+diff --git a/src/lib.rs b/src/lib.rs
+--- a/src/lib.rs
++++ b/src/lib.rs
+@@ -1 +1 @@
+-a
++b
+diff --git a/Cargo.lock b/Cargo.lock
+--- a/Cargo.lock
++++ b/Cargo.lock
+@@ -1 +1 @@
+-version = 1
++version = 2
+diff --git a/vendor/zlib/inflate.c b/vendor/zlib/inflate.c
+--- a/vendor/zlib/inflate.c
++++ b/vendor/zlib/inflate.c
+@@ -1 +1 @@
+-x
++y
+";
+
+    fn excluded(patterns: &[&str], path: &str) -> bool {
+        let config = DiffConfig {
+            exclude: patterns.iter().map(|p| p.to_string()).collect(),
+            ..Default::default()
+        };
+        build_matcher(None, &config)
+            .matched_path_or_any_parents(path, false)
+            .is_ignore()
+    }
+
+    #[test]
+    fn test_patterns() {
+        assert!(excluded(&["*.lock"], "web/yarn.lock"));
+        assert!(excluded(&["vendor/"], "third_party/vendor/zlib/inflate.c"));
+        assert!(!excluded(
+            &["/vendor/"],
+            "third_party/vendor/zlib/inflate.c"
+        ));
+        assert!(excluded(&["**/*.pb.go"], "api/v1/user.pb.go"));
+        assert!(!excluded(&["*.snap", "!keep.snap"], "tests/keep.snap"));
+        assert!(!excluded(&[], "Cargo.lock"));
+    }
+
+    #[test]
+    fn test_exclude_files() {
+        let filtered = exclude_files(DIFF, |p| p == "Cargo.lock" || p.starts_with("vendor/"));
+        assert!(filtered.starts_with("diff --git a/src/lib.rs b/src/lib.rs\n"));
+        assert!(!filtered.contains("version = 2") && !filtered.contains("+y"));
+        assert!(filtered
+            .ends_with("+b\n2 files changed (excluded): Cargo.lock, vendor/zlib/inflate.c\n"));
+
+        assert_eq!(exclude_files(DIFF, |_| false), DIFF);
+        let filtered = exclude_files(DIFF, |p| p.ends_with(".rs"));
+        assert!(filtered.starts_with("diff --git a/Cargo.lock b/Cargo.lock\n"));
+        assert!(filtered.ends_with("+y\n1 file changed (excluded): src/lib.rs\n"));
+    }
+
+    #[test]
+    fn test_parse_check_attr() {
+        let output = "gen/api.pb.go\0linguist-generated\0true\0gen/api.pb.go\0diff\0unspecified\0\
+                      Cargo.lock\0linguist-generated\0unspecified\0Cargo.lock\0diff\0unset\0\
+                      src/lib.rs\0linguist-generated\0false\0src/lib.rs\0diff\0set\0";
+        let mut generated: Vec<String> = parse_check_attr(output).into_iter().collect();
+        generated.sort();
+        assert_eq!(generated, vec!["Cargo.lock", "gen/api.pb.go"]);
+    }
+}
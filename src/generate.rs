@@ -1,32 +1,291 @@
-use openai_api_rust::chat::*;
-use openai_api_rust::*;
 use std::process::Command;
+use std::sync::Arc;
+
+use tokio::task::JoinSet;
 
 use crate::cli;
-use crate::config::{self, Config};
+use crate::config::{self, Config, DiffConfig, Task};
 
 use crate::constants::BRANCH_NAME_PROMPT;
 use crate::constants::{DEFAULT_MAX_TOKENS, DEFAULT_OPENAI_MODEL, DEFAULT_PROMPT_TEMPLATE};
+use crate::diff_filter::filter_diff;
+use crate::git_context::{git, GitContext};
+use crate::lockfile::{self, summarize_lockfiles, DependencyChange};
+use crate::patch::compact_diff;
+use crate::provider::{self, ChatMessage, ChatRequest, Provider};
 use crate::sanitizer::sanitize_with_config;
-use crate::template_engine::{render_template, TemplateContext};
+use crate::streaming::StreamPrinter;
+use crate::summarize::fit_diff_to_budget;
+use crate::template_engine::{render_template, Template, TemplateContext, TemplateKind};
+
+/// Send a system/user prompt pair to the configured provider and return the raw reply.
+///
+/// Every generation path (commit, branch, pr) goes through here. With `stream` set the
+/// reply is printed while it is generated.
+pub async fn request_completion(
+    config: &Config,
+    system_prompt: &str,
+    user_prompt: String,
+    temperature: f32,
+    stream: Option<&mut StreamPrinter>,
+) -> anyhow::Result<String> {
+    let provider = provider::from_config(config)?;
+    let request = chat_request(config, system_prompt, user_prompt, temperature)?;
+
+    let response = match stream {
+        Some(printer) => {
+            let response = provider
+                .chat_stream(&request, &mut |delta| printer.on_delta(delta))
+                .await;
+            printer.finish();
+            response?
+        }
+        None => provider.chat(&request).await?,
+    };
+    if let Some(usage) = &response.usage {
+        log::debug!(
+            "{} ({}) usage: prompt={:?}, completion={:?}, total={:?}",
+            provider.name(),
+            response.model.as_deref().unwrap_or(&request.model),
+            usage.prompt_tokens,
+            usage.completion_tokens,
+            usage.total_tokens
+        );
+    }
+    Ok(response.content)
+}
+
+/// Request a reply and extract its `<aicommit>` block, moving on to the next
+/// `fallback` backend when a request fails with a transient error or the reply
+/// has no block. Returns the block and the name of the backend that produced it.
+pub async fn request_aicommit(
+    config: &Config,
+    system_prompt: &str,
+    user_prompt: String,
+    temperature: f32,
+    mut stream: Option<&mut StreamPrinter>,
+) -> anyhow::Result<(String, String)> {
+    let backends = backend_chain(config)?;
+    let names: Vec<String> = backends.iter().map(|(name, _)| name.clone()).collect();
+
+    let mut failures = Vec::new();
+    for (i, (name, backend)) in backends.into_iter().enumerate() {
+        let result = request_completion(
+            &backend,
+            system_prompt,
+            user_prompt.clone(),
+            temperature,
+            stream.as_deref_mut(),
+        )
+        .await;
+        let error = match result {
+            Ok(reply) => match extract_aicommit_message(&reply) {
+                Ok(message) => return Ok((message, name)),
+                Err(e) => e,
+            },
+            // 鉴权失败、参数错误等问题换一个后端也无法解决
+            Err(e) if !provider::is_transient(&e) => return Err(e),
+            Err(e) => e,
+        };
+        if let Some(next) = names.get(i + 1) {
+            log::warn!("{} failed: {}, trying the next fallback", name, error);
+            if let Some(printer) = stream.as_deref_mut() {
+                printer.discard(&format!("{name} failed, retrying with {next}"));
+            }
+        }
+        failures.push(format!("{name}: {error}"));
+    }
+    Err(all_failed(failures))
+}
+
+/// `config` followed by its fallbacks, each with its name.
+fn backend_chain(config: &Config) -> anyhow::Result<Vec<(String, Config)>> {
+    let mut backends = vec![(config.backend_name(), config.clone())];
+    backends.extend(config.fallbacks()?);
+    Ok(backends)
+}
+
+fn all_failed(failures: Vec<String>) -> anyhow::Error {
+    match failures.as_slice() {
+        [only] => anyhow::anyhow!("{}", only),
+        _ => anyhow::anyhow!("All backends failed:\n  {}", failures.join("\n  ")),
+    }
+}
+
+/// Tell the user which backend wrote `what` when fallbacks are configured.
+fn report_backend(config: &Config, what: &str, backend: &str) {
+    if !config.fallback.is_empty() {
+        eprintln!("{what} generated by {backend}");
+    }
+}
+
+/// Like [`request_completion`] but returns `n` independent replies.
+///
+/// Backends that support `n` answer in one call, topped up with parallel requests
+/// when fewer replies come back; others get `n` parallel requests. Failed
+/// requests are skipped as long as at least one succeeds.
+pub async fn request_completions(
+    config: &Config,
+    system_prompt: &str,
+    user_prompt: String,
+    temperature: f32,
+    n: u32,
+) -> anyhow::Result<Vec<String>> {
+    let provider: Arc<dyn Provider> = provider::from_config(config)?.into();
+    let request = chat_request(config, system_prompt, user_prompt, temperature)?;
+
+    if !provider.supports_n() {
+        return parallel_completions(provider, request, n).await;
+    }
+    let response = provider
+        .chat(&ChatRequest {
+            n: Some(n),
+            ..request.clone()
+        })
+        .await?;
+    let mut replies = vec![response.content];
+    replies.extend(response.alternatives);
+    // 许多 OpenAI 兼容的服务会忽略 `n`，缺少的回复用并行请求补齐
+    let missing = n.saturating_sub(replies.len() as u32);
+    if missing > 0 {
+        log::debug!(
+            "{} returned {} of {} replies, requesting the rest separately",
+            provider.name(),
+            replies.len(),
+            n
+        );
+        match parallel_completions(provider, request, missing).await {
+            Ok(more) => replies.extend(more),
+            Err(e) => log::warn!("Candidate request failed: {}", e),
+        }
+    }
+    Ok(replies)
+}
+
+/// Send `request` `count` times in parallel, keeping the replies that succeed.
+async fn parallel_completions(
+    provider: Arc<dyn Provider>,
+    request: ChatRequest,
+    count: u32,
+) -> anyhow::Result<Vec<String>> {
+    let request = Arc::new(request);
+    let mut tasks = JoinSet::new();
+    for _ in 0..count {
+        let provider = provider.clone();
+        let request = request.clone();
+        tasks.spawn(async move { provider.chat(&request).await });
+    }
+    let mut replies = Vec::new();
+    let mut last_error = None;
+    while let Some(result) = tasks.join_next().await {
+        match result? {
+            Ok(response) => replies.push(response.content),
+            Err(e) => {
+                log::warn!("Candidate request failed: {}", e);
+                last_error = Some(e);
+            }
+        }
+    }
+    match last_error {
+        Some(e) if replies.is_empty() => Err(e),
+        _ => Ok(replies),
+    }
+}
 
+/// Build a request with `config.generation.params`, checked against what the provider accepts.
+///
+/// `default_temperature` is the task's own default, used unless a temperature is
+/// configured; reasoning models get no default temperature at all.
+fn chat_request(
+    config: &Config,
+    system_prompt: &str,
+    user_prompt: String,
+    default_temperature: f32,
+) -> anyhow::Result<ChatRequest> {
+    let params = &config.generation.params;
+    let default_temperature = params
+        .reasoning_effort
+        .is_none()
+        .then_some(default_temperature);
+    let mut request = ChatRequest {
+        model: config
+            .model
+            .as_deref()
+            .unwrap_or(DEFAULT_OPENAI_MODEL)
+            .to_owned(),
+        messages: vec![
+            ChatMessage::system(system_prompt),
+            ChatMessage::user(user_prompt),
+        ],
+        max_tokens: params
+            .max_tokens
+            .or(config.max_tokens)
+            .unwrap_or(DEFAULT_MAX_TOKENS),
+        temperature: params.temperature.or(default_temperature),
+        top_p: params.top_p,
+        stop: params.stop.clone().unwrap_or_default(),
+        reasoning_effort: params.reasoning_effort,
+        n: None,
+    };
+    config
+        .provider
+        .check_params(&mut request, config.generation.omit_unsupported)?;
+    Ok(request)
+}
+
+/// Compact, sanitize and, if needed, summarize `diff` for the prompt. Runs
+/// once per diff; the prompt builders take its output as it is.
+pub(crate) async fn prepare_prompt_diff(diff: &str, config: &Config) -> anyhow::Result<String> {
+    let diff = compact_diff(diff, &config.diff);
+    let (sanitized_diff, _, redactions) = sanitize_with_config(&diff, None, config);
+    if !redactions.is_empty() {
+        log::debug!("Sanitized {} potential secrets from diff", redactions.len());
+    }
+    // Oversized diffs are replaced by chunk summaries
+    fit_diff_to_budget(&sanitized_diff, config).await
+}
+
+/// `diff` is the output of [`prepare_prompt_diff`].
 pub async fn generate_commit_message(
     diff: &str,
     config: &config::Config,
+    kind: TemplateKind,
+    git: &GitContext,
     user_description: Option<&str>,
+    stream: Option<&mut StreamPrinter>,
 ) -> anyhow::Result<String> {
-    // sanitize diff & user description first
-    let (sanitized_diff, sanitized_user_desc_opt, redactions) =
-        sanitize_with_config(diff, user_description, config);
-    if !redactions.is_empty() {
-        log::debug!(
-            "Sanitized {} potential secrets from diff/prompt",
-            redactions.len()
-        );
+    let prompt = build_commit_prompt(diff, config, kind, git, user_description).await?;
+    let system_prompt = system_prompt(config, COMMIT_SYSTEM_PROMPT)?;
+    let (commit_message, backend) =
+        request_aicommit(config, &system_prompt, prompt, 0.30, stream).await?;
+    report_backend(config, "Commit message", &backend);
+    Ok(commit_message)
+}
+
+const COMMIT_SYSTEM_PROMPT: &str = "你是一个代码版本控制专家，请分析以下变更并生成commit message。";
+const BRANCH_SYSTEM_PROMPT: &str = "你是一个代码版本控制专家，擅长创建描述性的分支名。";
+
+/// The system message from `templates.system`, or `default` when none is configured.
+fn system_prompt(config: &Config, default: &str) -> anyhow::Result<String> {
+    let template = Template::from_config(config, TemplateKind::System, default)?;
+    if config.templates.system.is_none() {
+        return Ok(template.text);
     }
+    render_template(&template, TemplateContext::from_config(config, "", None))
+}
 
-    let auth = Auth::new(config.api_key.as_str());
-    let openai = OpenAI::new(auth, &config.api_base());
+/// Render the commit message prompt for `diff`, the output of [`prepare_prompt_diff`].
+async fn build_commit_prompt(
+    diff: &str,
+    config: &Config,
+    kind: TemplateKind,
+    git: &GitContext,
+    user_description: Option<&str>,
+) -> anyhow::Result<String> {
+    let template = Template::from_config(config, kind, DEFAULT_PROMPT_TEMPLATE)?;
+
+    let sanitized_user_desc_opt =
+        user_description.map(|desc| sanitize_with_config(desc, None, config).0);
 
     // Add "commit message: " prefix to user description if provided (after sanitization)
     let prefixed_user_description = sanitized_user_desc_opt.map(|desc| {
@@ -37,53 +296,11 @@ pub async fn generate_commit_message(
         }
     });
 
-    let template_ctx = TemplateContext::new(
-        config.conventional,
-        config.language,
-        config.verbosity,
-        &sanitized_diff,
-        prefixed_user_description.as_deref(),
-    );
-
-    let messages = vec![
-        Message {
-            role: Role::System,
-            content: "你是一个代码版本控制专家，请分析以下变更并生成commit message。".to_string(),
-        },
-        Message {
-            role: Role::User,
-            content: render_template(DEFAULT_PROMPT_TEMPLATE, template_ctx)?,
-        },
-    ];
-
-    let chat = ChatBody {
-        model: config
-            .model
-            .as_deref()
-            .unwrap_or(DEFAULT_OPENAI_MODEL)
-            .to_owned(),
-        messages,
-        temperature: Some(0.30f32),
-        top_p: None,
-        n: None,
-        stream: Some(false),
-        stop: None, // 移除 stop words 以避免思考过程中的干扰
-        max_tokens: Some(DEFAULT_MAX_TOKENS as i32),
-        presence_penalty: None,
-        frequency_penalty: None,
-        logit_bias: None,
-        user: None,
-    };
-    let response = openai
-        .chat_completion_create(&chat)
-        .map_err(|e| anyhow::anyhow!("Failed to create chat completion: {}", e))?;
-    let msg = &response.choices[0]
-        .message
-        .as_ref()
-        .ok_or(anyhow::anyhow!("No message in response"))?
-        .content;
-    let commit_message = extract_aicommit_message(msg)?;
-    Ok(commit_message)
+    let mut template_ctx =
+        TemplateContext::from_config(config, diff, prefixed_user_description.as_deref());
+    git.apply(&mut template_ctx);
+
+    render_template(&template, template_ctx)
 }
 
 fn delete_thinking_contents(orig: &str) -> String {
@@ -104,7 +321,7 @@ fn delete_thinking_contents(orig: &str) -> String {
     }
 }
 
-fn extract_aicommit_message(response: &str) -> anyhow::Result<String> {
+pub(crate) fn extract_aicommit_message(response: &str) -> anyhow::Result<String> {
     let response = delete_thinking_contents(response);
 
     // 查找所有 <aicommit>...</aicommit> 块
@@ -133,99 +350,280 @@ fn extract_aicommit_message(response: &str) -> anyhow::Result<String> {
         .ok_or(anyhow::anyhow!("Start tag <aicommit> not found"))
 }
 
-fn get_diff(diff_file: Option<&str>, range: Option<&str>) -> anyhow::Result<String> {
-    match diff_file {
-        Some(path) => std::fs::read_to_string(path).map_err(Into::into),
-        None => {
-            let mut cmd = Command::new("git");
-            cmd.arg("diff");
+/// Summarize lockfiles and leave out the files excluded in `[diff]`. The
+/// dependency changes are returned when they are all the diff contains.
+/// `in_checkout` tells whether the diff is of the current repository, whose
+/// `.fastcommitignore` and `.gitattributes` then apply.
+pub(crate) fn prepare_diff(
+    diff: &str,
+    config: &Config,
+    in_checkout: bool,
+) -> (String, Option<Vec<DependencyChange>>) {
+    let (diff, dependencies) = if config.diff.lockfiles {
+        let summary = summarize_lockfiles(diff);
+        let dependencies = summary.dependency_only.then_some(summary.changes);
+        (summary.diff, dependencies)
+    } else {
+        (diff.to_string(), None)
+    };
+    let filtered = filter_diff(&diff, &config.diff, in_checkout);
+    // 有文件被排除时，依赖变更就不是 diff 的全部内容了
+    let dependencies = dependencies.filter(|_| filtered == diff);
+    (filtered, dependencies)
+}
 
-            if let Some(range_str) = range {
-                cmd.arg(range_str);
-            } else {
-                cmd.arg("--cached");
-            }
+/// Read the diff to describe, and the message of a dependency-only change
+/// when it can be written without the model.
+fn get_diff(args: &cli::CommitArgs, config: &Config) -> anyhow::Result<(String, Option<String>)> {
+    let diff = read_diff(args, &config.diff)?;
+    let (diff, dependencies) = prepare_diff(&diff, config, true);
+    let message = dependencies
+        .filter(|_| config.diff.local_deps_message && args.common.prompt.is_none())
+        .map(|changes| lockfile::commit_message(&changes, config.conventional, config.language));
+    Ok((diff, message))
+}
 
-            let output = cmd.output()?;
-            let diff_str = String::from_utf8_lossy(&output.stdout).into_owned();
-            if diff_str.trim().is_empty() {
-                Err(anyhow::anyhow!("No changes to commit"))
-            } else {
-                Ok(diff_str)
-            }
+/// The tree `git diff --all` compares against before the first commit.
+const EMPTY_TREE: &str = "4b825dc642cb6eb9a060e54bf8d69288fbee4904";
+
+fn read_diff(args: &cli::CommitArgs, config: &DiffConfig) -> anyhow::Result<String> {
+    if let Some(path) = &args.diff_file {
+        return std::fs::read_to_string(path).map_err(Into::into);
+    }
+
+    let has_head = git(&["rev-parse", "--verify", "-q", "HEAD"]).is_some();
+    // 直接让 git 输出所需的上下文，也能多于默认的 3 行
+    let context = config.compact.then_some(config.context);
+    let output = Command::new("git")
+        .args(diff_args(args, has_head, context))
+        .output()?;
+    if !output.status.success() {
+        return Err(anyhow::anyhow!(
+            "git diff failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    let mut diff = String::from_utf8_lossy(&output.stdout).into_owned();
+    if args.all {
+        diff.push_str(&untracked_diff(&args.pathspecs)?);
+    }
+
+    if diff.trim().is_empty() {
+        Err(anyhow::anyhow!("No changes to commit"))
+    } else {
+        Ok(diff)
+    }
+}
+
+/// The `git diff` arguments selecting the changes to describe, with `context`
+/// unchanged lines around each change if given.
+fn diff_args(args: &cli::CommitArgs, has_head: bool, context: Option<usize>) -> Vec<String> {
+    let mut diff_args = vec!["diff".to_string()];
+    diff_args.extend(context.map(|c| format!("-U{c}")));
+    if let Some(base) = &args.base {
+        // 三点语法比较的是与 base 的 merge-base，即当前分支上的全部提交
+        diff_args.push(format!("{base}...HEAD"));
+    } else if let Some(range) = &args.range {
+        diff_args.push(range.clone());
+    } else if args.all {
+        diff_args.push(if has_head { "HEAD" } else { EMPTY_TREE }.to_string());
+    } else if !args.unstaged {
+        diff_args.push("--cached".to_string());
+    }
+    if !args.pathspecs.is_empty() {
+        diff_args.push("--".to_string());
+        diff_args.extend(args.pathspecs.iter().cloned());
+    }
+    diff_args
+}
+
+/// New-file diffs of the untracked files `git diff` leaves out, with paths
+/// relative to the repository root like the rest of the diff.
+fn untracked_diff(pathspecs: &[String]) -> anyhow::Result<String> {
+    let root = git(&["rev-parse", "--show-toplevel"])
+        .ok_or_else(|| anyhow::anyhow!("Not inside a git repository"))?;
+    let mut ls_files = Command::new("git");
+    ls_files.args([
+        "ls-files",
+        "--others",
+        "--exclude-standard",
+        "--full-name",
+        "-z",
+    ]);
+    // pathspec 相对于当前目录；没有 pathspec 时与 `git diff HEAD` 一样覆盖整个仓库
+    if pathspecs.is_empty() {
+        ls_files.current_dir(&root);
+    } else {
+        ls_files.arg("--").args(pathspecs);
+    }
+    let output = ls_files.output()?;
+    if !output.status.success() {
+        return Err(anyhow::anyhow!(
+            "git ls-files failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    let files = String::from_utf8_lossy(&output.stdout).into_owned();
+
+    let mut diff = String::new();
+    for file in files.split('\0').filter(|f| !f.is_empty()) {
+        let output = Command::new("git")
+            .current_dir(&root)
+            .args(["diff", "--no-index", "--", "/dev/null", file])
+            .output()?;
+        // --no-index 有差异时以 1 退出，其他状态码才是出错
+        if !matches!(output.status.code(), Some(0 | 1)) {
+            return Err(anyhow::anyhow!(
+                "git diff --no-index {} failed: {}",
+                file,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
         }
+        diff.push_str(&String::from_utf8_lossy(&output.stdout));
     }
+    Ok(diff)
 }
 
-pub async fn generate(args: &cli::CommitArgs, config: &Config) -> anyhow::Result<String> {
-    let diff = get_diff(args.diff_file.as_deref(), args.range.as_deref())?;
-    let message = generate_commit_message(&diff, config, args.common.prompt.as_deref()).await?;
+pub async fn generate(
+    args: &cli::CommitArgs,
+    config: &Config,
+    stream: Option<&mut StreamPrinter>,
+) -> anyhow::Result<String> {
+    let (diff, dependency_message) = get_diff(args, config)?;
+    if let Some(message) = dependency_message {
+        log::info!("Only dependencies changed, skipping the model");
+        return Ok(message);
+    }
+    let config = &config.for_task(Task::Commit)?;
+    let git = GitContext::collect(config, &diff);
+    let diff = prepare_prompt_diff(&diff, config).await?;
+    let message = generate_commit_message(
+        &diff,
+        config,
+        TemplateKind::Commit,
+        &git,
+        args.common.prompt.as_deref(),
+        stream,
+    )
+    .await?;
     Ok(message)
 }
 
-async fn generate_branch_name_with_ai(
-    diff: &str,
-    prefix: Option<&str>,
+/// Generate a new commit message for the same diff, with `user_description`
+/// replacing the one given on the command line.
+pub async fn regenerate(
+    args: &cli::CommitArgs,
     config: &Config,
+    user_description: Option<&str>,
 ) -> anyhow::Result<String> {
-    // sanitize diff only (branch name uses only diff)
-    let (sanitized_diff, _, redactions) = sanitize_with_config(diff, None, config);
-    if !redactions.is_empty() {
-        log::debug!(
-            "Sanitized {} potential secrets from diff before branch generation",
-            redactions.len()
-        );
-    }
+    // 用户要求重新生成时总是调用模型
+    let (diff, _) = get_diff(args, config)?;
+    let config = &config.for_task(Task::Commit)?;
+    let git = GitContext::collect(config, &diff);
+    let diff = prepare_prompt_diff(&diff, config).await?;
+    generate_commit_message(
+        &diff,
+        config,
+        TemplateKind::Commit,
+        &git,
+        user_description,
+        None,
+    )
+    .await
+}
 
-    let auth = Auth::new(config.api_key.as_str());
-    let openai = OpenAI::new(auth, &config.api_base());
+/// 生成 `n` 个候选提交信息，去除重复项后按生成顺序返回
+pub async fn generate_candidates(
+    args: &cli::CommitArgs,
+    config: &Config,
+    n: u32,
+) -> anyhow::Result<Vec<String>> {
+    let (diff, dependency_message) = get_diff(args, config)?;
+    if let Some(message) = dependency_message {
+        log::info!("Only dependencies changed, skipping the model");
+        return Ok(vec![message]);
+    }
+    let config = &config.for_task(Task::Commit)?;
+    let git = GitContext::collect(config, &diff);
+    let diff = prepare_prompt_diff(&diff, config).await?;
+    let prompt = build_commit_prompt(
+        &diff,
+        config,
+        TemplateKind::Commit,
+        &git,
+        args.common.prompt.as_deref(),
+    )
+    .await?;
+    let system_prompt = system_prompt(config, COMMIT_SYSTEM_PROMPT)?;
+    let mut failures = Vec::new();
+    for (name, backend) in backend_chain(config)? {
+        // 提高温度以获得差异更大的候选
+        let replies =
+            match request_completions(&backend, &system_prompt, prompt.clone(), 0.8, n).await {
+                Ok(replies) => replies,
+                Err(e) if provider::is_transient(&e) => {
+                    log::warn!("{} failed: {}", name, e);
+                    failures.push(format!("{name}: {e}"));
+                    continue;
+                }
+                Err(e) => return Err(e),
+            };
+        let messages = replies
+            .iter()
+            .filter_map(|reply| match extract_aicommit_message(reply) {
+                Ok(msg) => Some(msg),
+                Err(e) => {
+                    log::warn!("Skipping candidate: {}", e);
+                    None
+                }
+            })
+            .collect();
 
-    let prompt = BRANCH_NAME_PROMPT.replace("{{diff}}", &sanitized_diff);
-    let messages = vec![
-        Message {
-            role: Role::System,
-            content: "你是一个代码版本控制专家，擅长创建描述性的分支名。".to_string(),
-        },
-        Message {
-            role: Role::User,
-            content: prompt,
-        },
-    ];
+        let candidates = dedupe_candidates(messages);
+        if !candidates.is_empty() {
+            report_backend(config, "Candidates", &name);
+            return Ok(candidates);
+        }
+        failures.push(format!(
+            "{name}: No valid commit message candidates generated"
+        ));
+    }
+    Err(all_failed(failures))
+}
 
-    let chat = ChatBody {
-        model: config
-            .model
-            .as_deref()
-            .unwrap_or(DEFAULT_OPENAI_MODEL)
-            .to_owned(),
-        messages,
-        temperature: Some(0.2f32),
-        top_p: None,
-        n: None,
-        stream: Some(false),
-        stop: None,
-        max_tokens: Some(DEFAULT_MAX_TOKENS as i32),
-        presence_penalty: None,
-        frequency_penalty: None,
-        logit_bias: None,
-        user: None,
-    };
+/// Drop candidates that only differ in whitespace or case from an earlier one.
+fn dedupe_candidates(messages: Vec<String>) -> Vec<String> {
+    let mut seen = std::collections::HashSet::new();
+    messages
+        .into_iter()
+        .map(|m| m.trim().to_string())
+        .filter(|m| !m.is_empty())
+        .filter(|m| {
+            seen.insert(
+                m.split_whitespace()
+                    .collect::<Vec<_>>()
+                    .join(" ")
+                    .to_lowercase(),
+            )
+        })
+        .collect()
+}
 
-    let response = openai
-        .chat_completion_create(&chat)
-        .map_err(|e| anyhow::anyhow!("Failed to create chat completion: {}", e))?;
-    let msg = response
-        .choices
-        .first()
-        .ok_or(anyhow::anyhow!("No choices in response"))?
-        .message
-        .as_ref()
-        .ok_or(anyhow::anyhow!("No message in response"))?
-        .content
-        .trim()
-        .to_string();
-
-    let branch_name = extract_aicommit_message(&msg)?;
+/// `diff` is the output of [`prepare_prompt_diff`].
+async fn generate_branch_name_with_ai(
+    diff: &str,
+    prefix: Option<&str>,
+    config: &Config,
+    git: &GitContext,
+) -> anyhow::Result<String> {
+    let template = Template::from_config(config, TemplateKind::Branch, BRANCH_NAME_PROMPT)?;
+    let mut template_ctx = TemplateContext::from_config(config, diff, None);
+    git.apply(&mut template_ctx);
+    let prompt = render_template(&template, template_ctx)?;
+    let system_prompt = system_prompt(config, BRANCH_SYSTEM_PROMPT)?;
+    let (branch_name, backend) =
+        request_aicommit(config, &system_prompt, prompt, 0.2, None).await?;
+    report_backend(config, "Branch name", &backend);
 
     let branch_name = if let Some(prefix) = prefix {
         format!("{}{}", prefix.trim(), branch_name.trim())
@@ -241,27 +639,49 @@ async fn generate_branch_name_with_ai(
 }
 
 pub async fn generate_branch(args: &cli::CommitArgs, config: &Config) -> anyhow::Result<String> {
-    let diff = get_diff(args.diff_file.as_deref(), args.range.as_deref())?;
+    let (diff, _) = get_diff(args, config)?;
+    let config = &config.for_task(Task::Branch)?;
     let prefix = args
         .branch_prefix
         .as_deref()
         .or(config.branch_prefix.as_deref());
-    let branch_name = generate_branch_name_with_ai(&diff, prefix, config).await?;
+    let git = GitContext::collect(config, &diff);
+    let diff = prepare_prompt_diff(&diff, config).await?;
+    let branch_name = generate_branch_name_with_ai(&diff, prefix, config, &git).await?;
     Ok(branch_name)
 }
 
 pub async fn generate_both(
     args: &cli::CommitArgs,
     config: &Config,
+    stream: Option<&mut StreamPrinter>,
 ) -> anyhow::Result<(String, String)> {
-    let diff = get_diff(args.diff_file.as_deref(), args.range.as_deref())?;
+    let (diff, dependency_message) = get_diff(args, config)?;
+    let commit_config = &config.for_task(Task::Commit)?;
+    let branch_config = &config.for_task(Task::Branch)?;
+    // 文件列表等信息需要从原始 diff 中解析
+    let git = GitContext::collect(commit_config, &diff);
+    // Prepare (and if needed summarize) the diff once for both prompts
+    let diff = prepare_prompt_diff(&diff, commit_config).await?;
     let prefix = args
         .branch_prefix
         .as_deref()
         .or(config.branch_prefix.as_deref());
-    let branch_name = generate_branch_name_with_ai(&diff, prefix, config).await?;
-    let commit_message =
-        generate_commit_message(&diff, config, args.common.prompt.as_deref()).await?;
+    let branch_name = generate_branch_name_with_ai(&diff, prefix, branch_config, &git).await?;
+    let commit_message = match dependency_message {
+        Some(message) => message,
+        None => {
+            generate_commit_message(
+                &diff,
+                commit_config,
+                TemplateKind::Commit,
+                &git,
+                args.common.prompt.as_deref(),
+                stream,
+            )
+            .await?
+        }
+    };
     Ok((branch_name, commit_message))
 }
 
@@ -285,3 +705,163 @@ pub fn execute_git_commit(message: &str, extra_args: &[String]) -> anyhow::Resul
         Err(anyhow::anyhow!("git commit failed:\n{}", stderr.trim()))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diff_args() {
+        let args = |f: fn(&mut cli::CommitArgs)| {
+            let mut args = cli::CommitArgs::default();
+            f(&mut args);
+            args
+        };
+        assert_eq!(
+            diff_args(&args(|_| {}), true, None),
+            vec!["diff", "--cached"]
+        );
+        assert_eq!(
+            diff_args(&args(|a| a.unstaged = true), true, None),
+            vec!["diff"]
+        );
+        assert_eq!(
+            diff_args(&args(|a| a.all = true), true, None),
+            vec!["diff", "HEAD"]
+        );
+        assert_eq!(
+            diff_args(&args(|a| a.all = true), false, None),
+            vec!["diff", EMPTY_TREE]
+        );
+        assert_eq!(
+            diff_args(
+                &args(|a| {
+                    a.base = Some("origin/main".into());
+                    a.pathspecs = vec!["src/parser".into(), "docs".into()];
+                }),
+                true,
+                None
+            ),
+            vec!["diff", "origin/main...HEAD", "--", "src/parser", "docs"]
+        );
+        assert_eq!(
+            diff_args(&args(|_| {}), true, Some(1)),
+            vec!["diff", "-U1", "--cached"]
+        );
+    }
+
+    #[test]
+    fn test_dedupe_candidates() {
+        let candidates = dedupe_candidates(vec![
+            "feat: add x".into(),
+            " feat:  add X\n".into(),
+            "".into(),
+            "fix: y".into(),
+        ]);
+        assert_eq!(candidates, vec!["feat: add x", "fix: y"]);
+    }
+
+    #[test]
+    fn test_chat_request_generation_params() {
+        let mut config = Config::default();
+        config.max_tokens = Some(100);
+        config.generation.params.top_p = Some(0.5);
+        let request = chat_request(&config, "s", "u".into(), 0.3).unwrap();
+        assert_eq!(request.max_tokens, 100);
+        assert_eq!(request.temperature, Some(0.3));
+        assert_eq!(request.top_p, Some(0.5));
+
+        config.generation.params.top_p = None;
+        config.generation.params.max_tokens = Some(4000);
+        config.generation.params.reasoning_effort = Some(config::ReasoningEffort::Low);
+        let request = chat_request(&config, "s", "u".into(), 0.3).unwrap();
+        assert_eq!(request.max_tokens, 4000);
+        assert_eq!(request.temperature, None);
+
+        config.generation.params.temperature = Some(0.7);
+        let err = chat_request(&config, "s", "u".into(), 0.3).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "provider 'openai' does not support temperature together with reasoning_effort; \
+             remove it or set generation.omit_unsupported = true"
+        );
+        config.generation.omit_unsupported = true;
+        let request = chat_request(&config, "s", "u".into(), 0.3).unwrap();
+        assert_eq!(request.temperature, None);
+
+        config.provider = crate::provider::ProviderKind::Anthropic;
+        config.generation.params.reasoning_effort = None;
+        config.generation.params.temperature = Some(1.5);
+        let err = chat_request(&config, "s", "u".into(), 0.3).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "temperature 1.5 is out of range for provider 'anthropic' (0 to 1)"
+        );
+    }
+
+    fn config_for(url: &str, fallback: &[&str]) -> Config {
+        let mut table = toml::Table::try_from(Config::default()).unwrap();
+        table.insert("api_base".into(), url.into());
+        table.insert("fallback".into(), fallback.to_vec().into());
+        table["http"]
+            .as_table_mut()
+            .unwrap()
+            .insert("max_retries".into(), 0.into());
+        table.try_into().unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_request_aicommit_falls_back() {
+        use crate::provider::mock_server::{MockResponse, MockServer};
+
+        let server = MockServer::with_responses(vec![
+            MockResponse::new(503, "overloaded"),
+            MockResponse::new(200, r#"{"choices":[{"message":{"content":"no tags"}}]}"#),
+            MockResponse::new(
+                200,
+                r#"{"choices":[{"message":{"content":"<aicommit>fix: x</aicommit>"}}]}"#,
+            ),
+        ])
+        .await;
+        let config = config_for(&server.url(), &["m2", "m3"]);
+        let (message, backend) = request_aicommit(&config, "s", "u".into(), 0.2, None)
+            .await
+            .unwrap();
+        assert_eq!(message, "fix: x");
+        assert_eq!(backend, "openai/m3");
+        let models: Vec<String> = server
+            .requests()
+            .iter()
+            .map(|r| {
+                serde_json::from_str::<serde_json::Value>(r.body()).unwrap()["model"].to_string()
+            })
+            .collect();
+        assert_eq!(models, vec![r#""gpt-3.5-turbo""#, r#""m2""#, r#""m3""#]);
+
+        // 非临时性错误不会切换后端
+        let server = MockServer::start(401, "bad key").await;
+        let config = config_for(&server.url(), &["m2"]);
+        let err = request_aicommit(&config, "s", "u".into(), 0.2, None)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("401"));
+        assert_eq!(server.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_request_completions_fills_ignored_n() {
+        use crate::provider::mock_server::MockServer;
+
+        // 服务端忽略 n，只返回一个回复
+        let server = MockServer::start(200, r#"{"choices":[{"message":{"content":"a"}}]}"#).await;
+        let config = config_for(&server.url(), &[]);
+        let replies = request_completions(&config, "s", "u".into(), 0.2, 3)
+            .await
+            .unwrap();
+        assert_eq!(replies, vec!["a", "a", "a"]);
+        let requests = server.requests();
+        assert_eq!(requests.len(), 3);
+        assert!(requests[0].body().contains(r#""n":3"#));
+        assert!(!requests[1].body().contains(r#""n":"#));
+    }
+}
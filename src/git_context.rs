@@ -0,0 +1,337 @@
+//! Git information for prompt templates: branch, changed files, diffstat,
+//! repository name and recent commit subjects, each enabled in `[context]`.
+
+use std::collections::BTreeMap;
+use std::process::Command;
+
+use crate::config::Config;
+use crate::style::{Convention, StyleGuide};
+use crate::template_engine::{TemplateContext, Value};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileStatus {
+    Added,
+    Modified,
+    Deleted,
+    Renamed,
+}
+
+impl FileStatus {
+    /// The letter `git diff --name-status` uses.
+    pub fn letter(self) -> &'static str {
+        match self {
+            FileStatus::Added => "A",
+            FileStatus::Modified => "M",
+            FileStatus::Deleted => "D",
+            FileStatus::Renamed => "R",
+        }
+    }
+}
+
+/// A file touched by a diff, with its added and removed line counts.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChangedFile {
+    pub path: String,
+    /// The path before a rename
+    pub old_path: Option<String>,
+    pub status: FileStatus,
+    pub insertions: usize,
+    pub deletions: usize,
+}
+
+/// Parse the files of a unified diff as produced by `git diff`.
+pub fn changed_files(diff: &str) -> Vec<ChangedFile> {
+    let mut files: Vec<ChangedFile> = Vec::new();
+    let mut in_hunk = false;
+    for line in diff.lines() {
+        if let Some(header) = line.strip_prefix("diff --git ") {
+            // 文件名可能含空格，之后的 +++/rename 行会给出准确路径
+            let path = header
+                .rsplit_once(" b/")
+                .map_or(header, |(_, path)| path)
+                .to_string();
+            files.push(ChangedFile {
+                path,
+                old_path: None,
+                status: FileStatus::Modified,
+                insertions: 0,
+                deletions: 0,
+            });
+            in_hunk = false;
+            continue;
+        }
+        let Some(file) = files.last_mut() else {
+            continue;
+        };
+        if line.starts_with("@@") {
+            in_hunk = true;
+        } else if in_hunk {
+            if line.starts_with('+') {
+                file.insertions += 1;
+            } else if line.starts_with('-') {
+                file.deletions += 1;
+            }
+        } else if line.starts_with("new file mode") {
+            file.status = FileStatus::Added;
+        } else if line.starts_with("deleted file mode") {
+            file.status = FileStatus::Deleted;
+        } else if let Some(old) = line.strip_prefix("rename from ") {
+            file.status = FileStatus::Renamed;
+            file.old_path = Some(old.to_string());
+        } else if let Some(new) = line.strip_prefix("rename to ") {
+            file.path = new.to_string();
+        } else if let Some(path) = line.strip_prefix("+++ b/") {
+            file.path = path.to_string();
+        } else if let Some(path) = line.strip_prefix("--- a/") {
+            if file.status == FileStatus::Deleted {
+                file.path = path.to_string();
+            }
+        }
+    }
+    files
+}
+
+/// `git diff --stat` style summary of `files`, one line per file and a total.
+pub fn diffstat(files: &[ChangedFile]) -> String {
+    let width = files
+        .iter()
+        .map(|f| f.path.chars().count())
+        .max()
+        .unwrap_or(0);
+    let mut lines: Vec<String> = files
+        .iter()
+        .map(|f| {
+            format!(
+                " {:<width$} | {} (+{} -{})",
+                f.path,
+                f.insertions + f.deletions,
+                f.insertions,
+                f.deletions
+            )
+        })
+        .collect();
+
+    let plural =
+        |n: usize, one: &str, many: &str| format!("{n} {}", if n == 1 { one } else { many });
+    let insertions: usize = files.iter().map(|f| f.insertions).sum();
+    let deletions: usize = files.iter().map(|f| f.deletions).sum();
+    let mut total = format!(" {}", plural(files.len(), "file changed", "files changed"));
+    if insertions > 0 || deletions == 0 {
+        total.push_str(&format!(
+            ", {}",
+            plural(insertions, "insertion(+)", "insertions(+)")
+        ));
+    }
+    if deletions > 0 {
+        total.push_str(&format!(
+            ", {}",
+            plural(deletions, "deletion(-)", "deletions(-)")
+        ));
+    }
+    lines.push(total);
+    lines.join("\n")
+}
+
+/// Run git with `args` in the current directory, returning trimmed stdout on success.
+pub(crate) fn git(args: &[&str]) -> Option<String> {
+    git_output(args).map(|out| out.trim().to_string())
+}
+
+/// Like [`git`], but keeps stdout exactly as printed, e.g. for file contents.
+pub(crate) fn git_output(args: &[&str]) -> Option<String> {
+    let output = Command::new("git").args(args).output().ok()?;
+    if !output.status.success() {
+        log::debug!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+        return None;
+    }
+    Some(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// The checked out branch, or `None` on a detached HEAD.
+pub fn current_branch() -> Option<String> {
+    git(&["symbolic-ref", "--short", "-q", "HEAD"]).filter(|b| !b.is_empty())
+}
+
+/// The name of the repository's top-level directory.
+pub fn repo_name() -> Option<String> {
+    let toplevel = git(&["rev-parse", "--show-toplevel"])?;
+    std::path::Path::new(&toplevel)
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+}
+
+/// Subjects of the last `n` non-merge commits, newest first.
+pub fn recent_commit_subjects(n: usize) -> Vec<String> {
+    git(&["log", &format!("-n{n}"), "--no-merges", "--format=%s"])
+        .map(|out| out.lines().map(str::to_string).collect())
+        .unwrap_or_default()
+}
+
+/// The git information enabled in `[context]`, gathered once per run.
+#[derive(Debug)]
+pub struct GitContext {
+    pub branch: Option<String>,
+    pub repo: Option<String>,
+    pub files: Vec<ChangedFile>,
+    pub stat: Option<String>,
+    pub recent_commits: Vec<String>,
+    pub style: Option<StyleGuide>,
+}
+
+impl GitContext {
+    /// Collect what `config` enables; file information comes from `diff`
+    /// itself, so it also works for `--diff-file` and PR diffs.
+    pub fn collect(config: &Config, diff: &str) -> Self {
+        let style = config
+            .style
+            .learn
+            .then(|| StyleGuide::learn(config))
+            .flatten();
+        let config = &config.context;
+        let files = if config.files || config.stat {
+            changed_files(diff)
+        } else {
+            Vec::new()
+        };
+        Self {
+            branch: config.branch.then(current_branch).flatten(),
+            repo: config.repo.then(repo_name).flatten(),
+            stat: (config.stat && !files.is_empty()).then(|| diffstat(&files)),
+            files: if config.files { files } else { Vec::new() },
+            recent_commits: if config.recent_commits > 0 {
+                recent_commit_subjects(config.recent_commits)
+            } else {
+                Vec::new()
+            },
+            style,
+        }
+    }
+
+    /// Set the template variables this context has values for.
+    pub fn apply(&self, context: &mut TemplateContext) {
+        if let Some(branch) = &self.branch {
+            context.insert("branch", branch.as_str());
+        }
+        if let Some(repo) = &self.repo {
+            context.insert("repo", repo.as_str());
+        }
+        if let Some(stat) = &self.stat {
+            context.insert("stat", stat.as_str());
+        }
+        let files: Vec<Value> = self
+            .files
+            .iter()
+            .map(|f| {
+                Value::Map(BTreeMap::from([
+                    ("path".to_string(), f.path.as_str().into()),
+                    (
+                        "old_path".to_string(),
+                        f.old_path.as_deref().unwrap_or("").into(),
+                    ),
+                    ("status".to_string(), f.status.letter().into()),
+                    ("insertions".to_string(), Value::Int(f.insertions as i64)),
+                    ("deletions".to_string(), Value::Int(f.deletions as i64)),
+                ]))
+            })
+            .collect();
+        context.insert("files", files);
+        context.insert("recent_commits", self.recent_commits.clone());
+        if let Some(style) = &self.style {
+            // 学到的仓库风格优先于 conventional 配置
+            let conventional = style.convention == Convention::Conventional;
+            context.insert("conventional", conventional);
+            context.insert("conventional_commit", conventional);
+            context.insert("style", style.description.as_str());
+            context.insert("style_examples", style.examples.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DIFF: &str = "\
+diff --git a/src/main.rs b/src/main.rs
+index 1111111..2222222 100644
+--- a/src/main.rs
++++ b/src/main.rs
+@@ -1,3 +1,4 @@
+-fn main() {}
++fn main() {
++    run();
++}
+--- not a header inside a hunk
+diff --git a/docs/new file.md b/docs/new file.md
+new file mode 100644
+--- /dev/null
++++ b/docs/new file.md
+@@ -0,0 +1 @@
++hello
+diff --git a/old.txt b/old.txt
+deleted file mode 100644
+--- a/old.txt
++++ /dev/null
+@@ -1 +0,0 @@
+-bye
+diff --git a/a.rs b/b.rs
+similarity index 100%
+rename from a.rs
+rename to b.rs
+";
+
+    #[test]
+    fn test_changed_files() {
+        let files = changed_files(DIFF);
+        let summary: Vec<(&str, &str, usize, usize)> = files
+            .iter()
+            .map(|f| {
+                (
+                    f.status.letter(),
+                    f.path.as_str(),
+                    f.insertions,
+                    f.deletions,
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("M", "src/main.rs", 3, 2),
+                ("A", "docs/new file.md", 1, 0),
+                ("D", "old.txt", 0, 1),
+                ("R", "b.rs", 0, 0),
+            ]
+        );
+        assert_eq!(files[3].old_path.as_deref(), Some("a.rs"));
+    }
+
+    #[test]
+    fn test_diffstat() {
+        assert_eq!(
+            diffstat(&changed_files(DIFF)),
+            " src/main.rs      | 5 (+3 -2)\n docs/new file.md | 1 (+1 -0)\n old.txt          | 1 (+0 -1)\n b.rs             | 0 (+0 -0)\n 4 files changed, 4 insertions(+), 3 deletions(-)"
+        );
+        assert_eq!(
+            diffstat(&changed_files(DIFF)[3..]).lines().last(),
+            Some(" 1 file changed, 0 insertions(+)")
+        );
+    }
+
+    #[test]
+    fn test_disabled_context_collects_nothing() {
+        let context = GitContext::collect(&Config::default(), DIFF);
+        assert!(context.branch.is_none() && context.repo.is_none() && context.stat.is_none());
+        assert!(context.files.is_empty() && context.recent_commits.is_empty());
+
+        let mut config = Config::default();
+        config.context.files = true;
+        let context = GitContext::collect(&config, DIFF);
+        assert_eq!(context.files.len(), 4);
+        assert!(context.stat.is_none());
+    }
+}
@@ -0,0 +1,261 @@
+//! Git hooks: `prepare-commit-msg` pre-fills `git commit` with a generated message,
+//! `commit-msg` lints hand-written ones.
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::cli::{CommitArgs, HookCommand};
+use crate::config::Config;
+use crate::generate;
+
+/// Marks hooks written by fastcommit so they are never chained or removed by mistake.
+const HOOK_MARKER: &str = "# installed by fastcommit";
+
+/// The git hooks fastcommit can install.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum HookKind {
+    /// Pre-fills the message with a generated one.
+    PrepareCommitMsg,
+    /// Rejects messages that don't pass `fastcommit lint`.
+    CommitMsg,
+}
+
+impl HookKind {
+    fn from_flag(commit_msg: bool) -> Self {
+        if commit_msg {
+            HookKind::CommitMsg
+        } else {
+            HookKind::PrepareCommitMsg
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            HookKind::PrepareCommitMsg => "prepare-commit-msg",
+            HookKind::CommitMsg => "commit-msg",
+        }
+    }
+
+    /// An existing hook is moved here on install and called before fastcommit.
+    fn chained_name(self) -> String {
+        format!("{}.pre-fastcommit", self.name())
+    }
+
+    /// The fastcommit invocation, followed by the arguments git passes to the hook.
+    fn command(self) -> &'static str {
+        match self {
+            // 生成失败不应阻止提交
+            HookKind::PrepareCommitMsg => "hook run \"$@\" || true",
+            HookKind::CommitMsg => "lint \"$1\"",
+        }
+    }
+}
+
+pub async fn handle_hook_command(command: &HookCommand, config: &Config) -> anyhow::Result<()> {
+    match command {
+        HookCommand::Install { commit_msg } => {
+            let kind = HookKind::from_flag(*commit_msg);
+            let path = install(kind, &hooks_dir()?, &std::env::current_exe()?)?;
+            eprintln!("Installed {} hook at {}", kind.name(), path.display());
+        }
+        HookCommand::Uninstall { commit_msg } => {
+            let kind = HookKind::from_flag(*commit_msg);
+            if uninstall(kind, &hooks_dir()?)? {
+                eprintln!("Removed fastcommit {} hook", kind.name());
+            } else {
+                eprintln!("No fastcommit {} hook installed", kind.name());
+            }
+        }
+        HookCommand::Run {
+            message_file,
+            source,
+            ..
+        } => {
+            if !should_generate(source.as_deref()) {
+                log::debug!("Skipping commit message generation for source {:?}", source);
+                return Ok(());
+            }
+            // 生成失败时不阻止提交，git 会照常打开编辑器
+            match generate::generate(&CommitArgs::default(), config, None).await {
+                Ok(msg) => prepend_message(Path::new(message_file), &msg)?,
+                Err(e) => eprintln!("fastcommit: {e}"),
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Merges, squashes, amends/`-c`/`-C` and `-m`/`-F` already come with a message.
+fn should_generate(source: Option<&str>) -> bool {
+    matches!(source, None | Some("") | Some("template"))
+}
+
+fn hooks_dir() -> anyhow::Result<PathBuf> {
+    let output = Command::new("git")
+        .args(["rev-parse", "--git-path", "hooks"])
+        .output()?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(anyhow::anyhow!(
+            "Failed to locate git hooks directory: {}",
+            stderr.trim()
+        ));
+    }
+    Ok(PathBuf::from(
+        String::from_utf8_lossy(&output.stdout).trim(),
+    ))
+}
+
+fn hook_script(kind: HookKind, exe: &Path) -> String {
+    let exe = exe.display().to_string().replace('\'', r"'\''");
+    let chained = kind.chained_name();
+    let command = kind.command();
+    format!(
+        r#"#!/bin/sh
+{HOOK_MARKER}
+chained="$(dirname "$0")/{chained}"
+if [ -x "$chained" ]; then
+    "$chained" "$@" || exit $?
+fi
+'{exe}' {command}
+"#
+    )
+}
+
+fn is_fastcommit_hook(path: &Path) -> bool {
+    std::fs::read_to_string(path)
+        .map(|s| s.contains(HOOK_MARKER))
+        .unwrap_or(false)
+}
+
+/// Write the hook into `hooks_dir`, keeping a foreign hook as the chained one.
+fn install(kind: HookKind, hooks_dir: &Path, exe: &Path) -> anyhow::Result<PathBuf> {
+    std::fs::create_dir_all(hooks_dir)?;
+    let hook = hooks_dir.join(kind.name());
+    let chained = hooks_dir.join(kind.chained_name());
+    if hook.exists() && !is_fastcommit_hook(&hook) {
+        if chained.exists() {
+            return Err(anyhow::anyhow!(
+                "Both {} and {} exist, refusing to overwrite either",
+                hook.display(),
+                chained.display()
+            ));
+        }
+        std::fs::rename(&hook, &chained)?;
+    }
+    std::fs::write(&hook, hook_script(kind, exe))?;
+    set_executable(&hook)?;
+    Ok(hook)
+}
+
+/// Remove the hook and restore the chained one; `false` if no fastcommit hook was found.
+fn uninstall(kind: HookKind, hooks_dir: &Path) -> anyhow::Result<bool> {
+    let hook = hooks_dir.join(kind.name());
+    if !is_fastcommit_hook(&hook) {
+        return Ok(false);
+    }
+    std::fs::remove_file(&hook)?;
+    let chained = hooks_dir.join(kind.chained_name());
+    if chained.exists() {
+        std::fs::rename(&chained, &hook)?;
+    }
+    Ok(true)
+}
+
+#[cfg(unix)]
+fn set_executable(path: &Path) -> anyhow::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o755))?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn set_executable(_path: &Path) -> anyhow::Result<()> {
+    Ok(())
+}
+
+/// Put `message` above what git already wrote (status comments or a template).
+fn prepend_message(path: &Path, message: &str) -> anyhow::Result<()> {
+    let existing = std::fs::read_to_string(path).unwrap_or_default();
+    std::fs::write(path, format!("{}\n\n{}", message.trim_end(), existing))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_hooks_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("fastcommit-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn test_should_generate() {
+        assert!(should_generate(None));
+        assert!(should_generate(Some("template")));
+        for source in ["message", "merge", "squash", "commit"] {
+            assert!(!should_generate(Some(source)), "{source}");
+        }
+    }
+
+    #[test]
+    fn test_install_chains_and_uninstall_restores_existing_hook() {
+        let kind = HookKind::PrepareCommitMsg;
+        let dir = temp_hooks_dir("hooks");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join(kind.name()), "#!/bin/sh\necho original\n").unwrap();
+
+        let hook = install(kind, &dir, Path::new("/usr/bin/fastcommit")).unwrap();
+        assert!(is_fastcommit_hook(&hook));
+        assert!(std::fs::read_to_string(&hook)
+            .unwrap()
+            .contains("'/usr/bin/fastcommit' hook run \"$@\""));
+        assert_eq!(
+            std::fs::read_to_string(dir.join(kind.chained_name())).unwrap(),
+            "#!/bin/sh\necho original\n"
+        );
+
+        // 重复安装不会把自己当成需要串联的钩子
+        install(kind, &dir, Path::new("/usr/bin/fastcommit")).unwrap();
+        assert!(!is_fastcommit_hook(&dir.join(kind.chained_name())));
+
+        assert!(uninstall(kind, &dir).unwrap());
+        assert_eq!(
+            std::fs::read_to_string(dir.join(kind.name())).unwrap(),
+            "#!/bin/sh\necho original\n"
+        );
+        assert!(!dir.join(kind.chained_name()).exists());
+        assert!(!uninstall(kind, &dir).unwrap());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_commit_msg_hook_runs_lint() {
+        let dir = temp_hooks_dir("lint-hooks");
+        let hook = install(HookKind::CommitMsg, &dir, Path::new("/usr/bin/fastcommit")).unwrap();
+        assert!(hook.ends_with("commit-msg"));
+        let script = std::fs::read_to_string(&hook).unwrap();
+        assert!(script.contains("'/usr/bin/fastcommit' lint \"$1\"\n"));
+        // 两种钩子互不影响
+        assert!(!uninstall(HookKind::PrepareCommitMsg, &dir).unwrap());
+        assert!(uninstall(HookKind::CommitMsg, &dir).unwrap());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_prepend_message_keeps_git_comments() {
+        let dir = temp_hooks_dir("msg");
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("COMMIT_EDITMSG");
+        std::fs::write(&file, "# Please enter the commit message\n").unwrap();
+
+        prepend_message(&file, "feat: add hook\n").unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&file).unwrap(),
+            "feat: add hook\n\n# Please enter the commit message\n"
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
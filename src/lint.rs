@@ -0,0 +1,368 @@
+//! Check commit messages written by hand against the configured convention.
+
+use std::fmt::{self, Display};
+use std::io::Read;
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use unicode_width::UnicodeWidthStr;
+
+use crate::config::Config;
+use crate::git_context::git;
+
+/// Subjects longer than this are truncated by most git tools.
+const MAX_SUBJECT_LENGTH: usize = 72;
+
+/// Git drops everything below this line, after the comment character, when
+/// `commit.cleanup = scissors`.
+const SCISSORS: &str = " ------------------------ >8 ------------------------";
+
+lazy_static! {
+    static ref FOOTER: Regex =
+        Regex::new(r"^(BREAKING CHANGE|[A-Za-z][A-Za-z0-9-]*)(: | #)\S").unwrap();
+    static ref BREAKING: Regex = Regex::new(r"(?i)^breaking[ -]change\s*:").unwrap();
+}
+
+/// One problem found in a commit message. `line` and `column` are 1-based
+/// positions in the original file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl Diagnostic {
+    pub fn new(line: usize, column: usize, message: impl Into<String>) -> Self {
+        Self {
+            line,
+            column,
+            message: message.into(),
+        }
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LintOptions {
+    pub conventional: bool,
+    pub max_subject_length: usize,
+    pub max_line_width: usize,
+    /// Lines starting with this are comments, git's `core.commentChar`
+    pub comment_char: String,
+}
+
+impl LintOptions {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            conventional: config.conventional,
+            max_subject_length: MAX_SUBJECT_LENGTH,
+            max_line_width: config.text_wrap.default_width,
+            comment_char: comment_char(),
+        }
+    }
+}
+
+/// git's `core.commentChar`, `#` when unset. `auto` picks a character per
+/// message that can't be known afterwards, so it falls back to `#` as well.
+fn comment_char() -> String {
+    git(&["config", "--get", "core.commentChar"])
+        .filter(|c| !c.is_empty() && c != "auto")
+        .unwrap_or_else(|| "#".to_string())
+}
+
+/// Lint the message in `file` (`-` for stdin), print diagnostics to stderr
+/// and return whether it passed.
+pub fn run(file: &str, config: &Config) -> anyhow::Result<bool> {
+    let (name, text) = if file == "-" {
+        let mut text = String::new();
+        std::io::stdin().read_to_string(&mut text)?;
+        ("<stdin>", text)
+    } else {
+        let text = std::fs::read_to_string(file)
+            .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", file, e))?;
+        (file, text)
+    };
+
+    let diagnostics = lint_message(&text, &LintOptions::from_config(config));
+    for d in &diagnostics {
+        eprintln!("{name}:{d}");
+    }
+    if !diagnostics.is_empty() {
+        eprintln!(
+            "\x1b[31m{} problem(s) found in commit message\x1b[0m",
+            diagnostics.len()
+        );
+    }
+    Ok(diagnostics.is_empty())
+}
+
+pub fn lint_message(text: &str, options: &LintOptions) -> Vec<Diagnostic> {
+    // 与 git 的默认清理规则一致：去掉注释行、剪刀线之后的内容以及首尾空行
+    let comment = options.comment_char.as_str();
+    let scissors = format!("{comment}{SCISSORS}");
+    let mut lines: Vec<(usize, &str)> = text
+        .lines()
+        .enumerate()
+        .take_while(|(_, l)| *l != scissors)
+        .filter(|(_, l)| !l.starts_with(comment))
+        .map(|(i, l)| (i + 1, l.trim_end()))
+        .skip_while(|(_, l)| l.is_empty())
+        .collect();
+    while lines.last().is_some_and(|(_, l)| l.is_empty()) {
+        lines.pop();
+    }
+
+    let Some(&(subject_line, subject)) = lines.first() else {
+        return vec![Diagnostic::new(1, 1, "commit message is empty")];
+    };
+
+    // git 自动生成的合并、回滚及 fixup 提交不做检查
+    if ["Merge ", "Revert \"", "fixup! ", "squash! ", "amend! "]
+        .iter()
+        .any(|p| subject.starts_with(p))
+    {
+        return Vec::new();
+    }
+
+    let mut diagnostics = Vec::new();
+    let subject_width = subject.width();
+    if subject_width > options.max_subject_length {
+        diagnostics.push(Diagnostic::new(
+            subject_line,
+            options.max_subject_length + 1,
+            format!(
+                "subject is {} characters long, at most {} allowed",
+                subject_width, options.max_subject_length
+            ),
+        ));
+    }
+    if options.conventional {
+        diagnostics.extend(check_conventional_subject(subject, subject_line));
+    }
+
+    if let Some(&(line, text)) = lines.get(1) {
+        if !text.is_empty() {
+            diagnostics.push(Diagnostic::new(
+                line,
+                1,
+                "expected a blank line between subject and body",
+            ));
+        }
+    }
+
+    let body = lines.get(1..).unwrap_or_default();
+    for &(line, text) in body {
+        let width = text.width();
+        // 不含空白的超长行（例如 URL）无法换行，不做要求
+        if width > options.max_line_width && text.trim().contains(char::is_whitespace) {
+            diagnostics.push(Diagnostic::new(
+                line,
+                options.max_line_width + 1,
+                format!(
+                    "line is {} columns wide, at most {} allowed (text_wrap.default_width)",
+                    width, options.max_line_width
+                ),
+            ));
+        }
+    }
+
+    if options.conventional {
+        diagnostics.extend(check_footers(body));
+    }
+    diagnostics.sort_by_key(|d| (d.line, d.column));
+    diagnostics
+}
+
+/// Check `type(scope)!: description`, reporting the first problem found.
+fn check_conventional_subject(subject: &str, line: usize) -> Option<Diagnostic> {
+    let column = |byte: usize| subject[..byte].chars().count() + 1;
+    let error = |byte: usize, message: &str| Some(Diagnostic::new(line, column(byte), message));
+
+    let type_end = subject
+        .find(|c: char| !c.is_ascii_alphanumeric() && c != '-')
+        .unwrap_or(subject.len());
+    if type_end == 0 || !subject.starts_with(|c: char| c.is_ascii_alphabetic()) {
+        return error(
+            0,
+            "expected a type such as 'feat' or 'fix' at the start of the subject",
+        );
+    }
+
+    let mut pos = type_end;
+    if subject[pos..].starts_with('(') {
+        let Some(close) = subject[pos..].find(')') else {
+            return error(pos, "scope is missing its closing ')'");
+        };
+        if subject[pos + 1..pos + close].trim().is_empty() {
+            return error(pos + 1, "scope must not be empty");
+        }
+        pos += close + 1;
+    }
+    if subject[pos..].starts_with('!') {
+        pos += 1;
+    }
+
+    if !subject[pos..].starts_with(':') {
+        return error(pos, "expected ':' after the type and optional scope");
+    }
+    pos += 1;
+    let description = &subject[pos..];
+    if description.trim().is_empty() {
+        return error(pos, "description after ':' is empty");
+    }
+    if !description.starts_with(' ') || description.starts_with("  ") {
+        return error(pos, "expected a single space after ':'");
+    }
+    None
+}
+
+/// Footers live in the last paragraph of the body, one `Token: value` or
+/// `Token #value` per line. Indented lines continue the previous footer.
+fn check_footers(body: &[(usize, &str)]) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    for &(line, text) in body {
+        if BREAKING.is_match(text)
+            && !text.starts_with("BREAKING CHANGE:")
+            && !text.starts_with("BREAKING-CHANGE:")
+        {
+            diagnostics.push(Diagnostic::new(
+                line,
+                1,
+                "'BREAKING CHANGE' must be uppercase",
+            ));
+        }
+    }
+
+    let start = body
+        .iter()
+        .rposition(|(_, l)| l.is_empty())
+        .map_or(0, |i| i + 1);
+    let last_paragraph = &body[start..];
+    let is_footer_block = last_paragraph
+        .first()
+        .is_some_and(|(_, l)| FOOTER.is_match(l));
+    if !is_footer_block {
+        return diagnostics;
+    }
+    for &(line, text) in last_paragraph {
+        if FOOTER.is_match(text) || text.starts_with(char::is_whitespace) || BREAKING.is_match(text)
+        {
+            continue;
+        }
+        let message = match text.split_once(':') {
+            Some((token, _)) if token.contains(' ') => {
+                format!("footer token '{token}' must use '-' instead of spaces")
+            }
+            _ => "expected a footer like 'Token: value' or 'Token #value'".to_string(),
+        };
+        diagnostics.push(Diagnostic::new(line, 1, message));
+    }
+    diagnostics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lint(text: &str) -> Vec<String> {
+        let options = LintOptions {
+            conventional: true,
+            max_subject_length: 50,
+            max_line_width: 40,
+            comment_char: "#".to_string(),
+        };
+        lint_message(text, &options)
+            .iter()
+            .map(ToString::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn test_valid_conventional_message() {
+        let text = "feat(lint)!: check commit messages\n\nAdds a lint mode for the commit-msg\nhook.\n\nBREAKING CHANGE: hooks are rewritten\nRefs #42\n# Please enter the commit message\n";
+        assert_eq!(lint(text), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_subject_structure() {
+        assert_eq!(
+            lint("add lint"),
+            vec!["1:4: expected ':' after the type and optional scope"]
+        );
+        assert_eq!(lint("feat(): x"), vec!["1:6: scope must not be empty"]);
+        assert_eq!(
+            lint("fix:x"),
+            vec!["1:5: expected a single space after ':'"]
+        );
+        assert_eq!(
+            lint(": x"),
+            vec!["1:1: expected a type such as 'feat' or 'fix' at the start of the subject"]
+        );
+        assert_eq!(
+            lint("# only a comment\n"),
+            vec!["1:1: commit message is empty"]
+        );
+        assert!(lint("Merge branch 'main' into dev").is_empty());
+        assert!(lint("fixup! feat: x").is_empty());
+    }
+
+    #[test]
+    fn test_layout_and_width() {
+        let text =
+            "# comment\nfix: a subject that is clearly longer than fifty chars\nbody right after\n";
+        assert_eq!(
+            lint(text),
+            vec![
+                "2:51: subject is 54 characters long, at most 50 allowed",
+                "3:1: expected a blank line between subject and body",
+            ]
+        );
+
+        let text = "fix: x\n\nthis body line is definitely wider than forty columns\nhttps://example.com/a/very/long/url/that/cannot/be/wrapped\n";
+        assert_eq!(
+            lint(text),
+            vec!["3:41: line is 53 columns wide, at most 40 allowed (text_wrap.default_width)"]
+        );
+    }
+
+    #[test]
+    fn test_footers() {
+        let text = "fix: x\n\nbody\n\nRefs #1\nReviewed by: someone\nbreaking change: y\n";
+        assert_eq!(
+            lint(text),
+            vec![
+                "6:1: footer token 'Reviewed by' must use '-' instead of spaces",
+                "7:1: 'BREAKING CHANGE' must be uppercase",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_comment_char() {
+        let options = LintOptions {
+            conventional: true,
+            max_subject_length: 50,
+            max_line_width: 40,
+            comment_char: ";".to_string(),
+        };
+        let text = "; Please enter the commit message\nfix: x\n\n#123 is fixed\n; ------------------------ >8 ------------------------\nnot a footer: at all\n";
+        assert!(lint_message(text, &options).is_empty());
+    }
+
+    #[test]
+    fn test_non_conventional_ignores_structure() {
+        let options = LintOptions {
+            conventional: false,
+            max_subject_length: 72,
+            max_line_width: 80,
+            comment_char: "#".to_string(),
+        };
+        assert!(lint_message("Add lint mode", &options).is_empty());
+    }
+}
@@ -0,0 +1,452 @@
+//! Dependency changes read from lockfile diffs, so that thousands of lines of
+//! hashes reach the model as a handful of "bump serde 1.0.218 → 1.0.219" lines.
+
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+use lazy_static::lazy_static;
+use regex::Regex;
+
+use crate::config::CommitLanguage;
+use crate::git_context::changed_files;
+use crate::summarize::split_before;
+
+/// At most this many changes per lockfile are listed in the prompt.
+const MAX_LISTED_CHANGES: usize = 50;
+
+lazy_static! {
+    static ref CARGO_NAME: Regex = Regex::new(r#"^name = "([^"]+)"$"#).unwrap();
+    static ref CARGO_VERSION: Regex = Regex::new(r#"^version = "([^"]+)"$"#).unwrap();
+    static ref NPM_KEY: Regex = Regex::new(r#"^"([^"]*)": \{$"#).unwrap();
+    static ref NPM_VERSION: Regex = Regex::new(r#"^"version": "([^"]+)",?$"#).unwrap();
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Cargo,
+    Npm,
+    GoSum,
+}
+
+impl Format {
+    fn of(path: &str) -> Option<Self> {
+        match path.rsplit('/').next().unwrap_or(path) {
+            "Cargo.lock" => Some(Format::Cargo),
+            "package-lock.json" | "npm-shrinkwrap.json" => Some(Format::Npm),
+            "go.sum" => Some(Format::GoSum),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum DependencyChange {
+    Bump {
+        name: String,
+        from: String,
+        to: String,
+    },
+    Add {
+        name: String,
+        version: String,
+    },
+    Remove {
+        name: String,
+        version: String,
+    },
+}
+
+impl fmt::Display for DependencyChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DependencyChange::Bump { name, from, to } => write!(f, "bump {name} {from} → {to}"),
+            DependencyChange::Add { name, version } => write!(f, "add {name} {version}"),
+            DependencyChange::Remove { name, version } => write!(f, "remove {name} {version}"),
+        }
+    }
+}
+
+/// A diff with its lockfile sections replaced by the dependency changes they contain.
+#[derive(Debug)]
+pub struct LockfileSummary {
+    pub diff: String,
+    pub changes: Vec<DependencyChange>,
+    /// Every file in the diff is a lockfile with dependency changes
+    pub dependency_only: bool,
+}
+
+/// Replace the hunks of `Cargo.lock`, `package-lock.json` and `go.sum` in
+/// `diff` with one line per added, removed or bumped dependency.
+pub fn summarize_lockfiles(diff: &str) -> LockfileSummary {
+    let mut text = String::new();
+    let mut changes: BTreeSet<DependencyChange> = BTreeSet::new();
+    let mut dependency_only = true;
+    for section in split_before(diff, |l| l.starts_with("diff --git ")) {
+        let path = changed_files(&section).into_iter().next().map(|f| f.path);
+        let Some((path, format)) = path.and_then(|p| Format::of(&p).map(|f| (p, f))) else {
+            dependency_only &= section.trim().is_empty();
+            text.push_str(&section);
+            continue;
+        };
+
+        let parsed = parse_changes(format, &section);
+        dependency_only &= !parsed.is_empty();
+        text.push_str(section.lines().next().unwrap_or_default());
+        text.push('\n');
+        if parsed.is_empty() {
+            text.push_str(&format!("{path}: no dependency version changes\n"));
+        } else {
+            text.push_str(&format!("{path}: dependency changes\n"));
+            for change in parsed.iter().take(MAX_LISTED_CHANGES) {
+                text.push_str(&format!("  {change}\n"));
+            }
+            if parsed.len() > MAX_LISTED_CHANGES {
+                text.push_str(&format!(
+                    "  ... and {} more\n",
+                    parsed.len() - MAX_LISTED_CHANGES
+                ));
+            }
+        }
+        changes.extend(parsed);
+    }
+    LockfileSummary {
+        diff: text,
+        dependency_only: dependency_only && !changes.is_empty(),
+        changes: changes.into_iter().collect(),
+    }
+}
+
+/// Collect the versions each side of the hunks mentions, then pair the
+/// versions only one side has into bumps, additions and removals.
+fn parse_changes(format: Format, section: &str) -> Vec<DependencyChange> {
+    let mut old: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+    let mut new: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+    // 当前所在的包名，旧版本和新版本两侧分别跟踪
+    let mut old_name: Option<String> = None;
+    let mut new_name: Option<String> = None;
+    let mut in_hunk = false;
+    for line in section.lines() {
+        if line.starts_with("@@") {
+            in_hunk = true;
+            old_name = None;
+            new_name = None;
+            continue;
+        }
+        if !in_hunk {
+            continue;
+        }
+        let (text, in_old, in_new) = match line.chars().next() {
+            Some('+') => (&line[1..], false, true),
+            Some('-') => (&line[1..], true, false),
+            Some(' ') => (&line[1..], true, true),
+            _ => continue,
+        };
+        let text = text.trim();
+
+        if format == Format::GoSum {
+            // module version[/go.mod] h1:hash
+            let mut fields = text.split_whitespace();
+            if let (Some(module), Some(version)) = (fields.next(), fields.next()) {
+                let version = version.trim_end_matches("/go.mod");
+                for (side, present) in [(&mut old, in_old), (&mut new, in_new)] {
+                    if present {
+                        side.entry(module.to_string())
+                            .or_default()
+                            .insert(version.to_string());
+                    }
+                }
+            }
+            continue;
+        }
+
+        if let Some(name) = package_key(format, text) {
+            if in_old {
+                old_name = Some(name.clone());
+            }
+            if in_new {
+                new_name = Some(name);
+            }
+        } else if let Some(version) = package_version(format, text) {
+            for (side, name, present) in
+                [(&mut old, &old_name, in_old), (&mut new, &new_name, in_new)]
+            {
+                if let Some(name) = name.as_ref().filter(|n| present && !n.is_empty()) {
+                    side.entry(name.clone())
+                        .or_default()
+                        .insert(version.clone());
+                }
+            }
+        }
+    }
+
+    let names: BTreeSet<&String> = old.keys().chain(new.keys()).collect();
+    let mut changes = Vec::new();
+    for name in names {
+        let empty = BTreeSet::new();
+        let (before, after) = (
+            old.get(name).unwrap_or(&empty),
+            new.get(name).unwrap_or(&empty),
+        );
+        let mut removed: Vec<&String> = before.difference(after).collect();
+        let mut added: Vec<&String> = after.difference(before).collect();
+        removed.sort_by(|a, b| compare_versions(a, b));
+        added.sort_by(|a, b| compare_versions(a, b));
+        for (from, to) in removed.iter().zip(&added) {
+            changes.push(DependencyChange::Bump {
+                name: name.clone(),
+                from: from.to_string(),
+                to: to.to_string(),
+            });
+        }
+        let paired = removed.len().min(added.len());
+        changes.extend(removed[paired..].iter().map(|v| DependencyChange::Remove {
+            name: name.clone(),
+            version: v.to_string(),
+        }));
+        changes.extend(added[paired..].iter().map(|v| DependencyChange::Add {
+            name: name.clone(),
+            version: v.to_string(),
+        }));
+    }
+    changes
+}
+
+/// The package a following `version` line belongs to; an empty name starts
+/// a block that isn't a dependency (`[[package]]` header, npm root package).
+fn package_key(format: Format, line: &str) -> Option<String> {
+    match format {
+        Format::Cargo if line == "[[package]]" => Some(String::new()),
+        Format::Cargo => Some(CARGO_NAME.captures(line)?[1].to_string()),
+        Format::Npm => {
+            let key = &NPM_KEY.captures(line)?[1];
+            // "node_modules/a/node_modules/b" 是嵌套安装的 b
+            Some(
+                key.rsplit("node_modules/")
+                    .next()
+                    .unwrap_or(key)
+                    .to_string(),
+            )
+        }
+        Format::GoSum => None,
+    }
+}
+
+fn package_version(format: Format, line: &str) -> Option<String> {
+    let regex = match format {
+        Format::Cargo => &*CARGO_VERSION,
+        Format::Npm => &*NPM_VERSION,
+        Format::GoSum => return None,
+    };
+    Some(regex.captures(line)?[1].to_string())
+}
+
+/// Compare dotted versions numerically where possible, so that 1.10 > 1.9.
+fn compare_versions(a: &str, b: &str) -> Ordering {
+    let parts = |v: &str| -> Vec<Result<u64, String>> {
+        v.trim_start_matches('v')
+            .split(['.', '-', '+'])
+            .map(|p| p.parse::<u64>().map_err(|_| p.to_string()))
+            .collect()
+    };
+    parts(a).cmp(&parts(b))
+}
+
+/// A `build(deps)` commit message describing `changes`, written without the model.
+pub fn commit_message(
+    changes: &[DependencyChange],
+    conventional: bool,
+    language: CommitLanguage,
+) -> String {
+    let chinese = language == CommitLanguage::Chinese;
+    let describe = |change: &DependencyChange| match (change, chinese) {
+        (DependencyChange::Bump { name, from, to }, false) => {
+            format!("bump {name} from {from} to {to}")
+        }
+        (DependencyChange::Bump { name, from, to }, true) => {
+            format!("将 {name} 从 {from} 升级到 {to}")
+        }
+        (DependencyChange::Add { name, version }, false) => format!("add {name} {version}"),
+        (DependencyChange::Add { name, version }, true) => format!("添加 {name} {version}"),
+        (DependencyChange::Remove { name, version }, false) => {
+            format!("remove {name} {version}")
+        }
+        (DependencyChange::Remove { name, version }, true) => format!("移除 {name} {version}"),
+    };
+
+    let (subject, body) = match changes {
+        [change] => (describe(change), None),
+        _ => {
+            let all_bumps = changes
+                .iter()
+                .all(|c| matches!(c, DependencyChange::Bump { .. }));
+            let subject = match (all_bumps, chinese) {
+                (true, false) => format!("bump {} dependencies", changes.len()),
+                (false, false) => format!("update {} dependencies", changes.len()),
+                (true, true) => format!("升级 {} 个依赖", changes.len()),
+                (false, true) => format!("更新 {} 个依赖", changes.len()),
+            };
+            let body = changes
+                .iter()
+                .map(|c| format!("- {}", describe(c)))
+                .collect::<Vec<_>>()
+                .join("\n");
+            (subject, Some(body))
+        }
+    };
+
+    let subject = if conventional {
+        format!("build(deps): {subject}")
+    } else {
+        let mut chars = subject.chars();
+        chars
+            .next()
+            .map(|c| c.to_uppercase().chain(chars).collect())
+            .unwrap_or_default()
+    };
+    match body {
+        Some(body) => format!("{subject}\n\n{body}"),
+        None => subject,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CARGO_DIFF: &str = r#"diff --git a/Cargo.lock b/Cargo.lock
+index 1111111..2222222 100644
+--- a/Cargo.lock
++++ b/Cargo.lock
+@@ -10,9 +10,9 @@ dependencies = [
+
+ [[package]]
+ name = "serde"
+-version = "1.0.218"
++version = "1.0.219"
+ source = "registry+https://github.com/rust-lang/crates.io-index"
+-checksum = "aaaa"
++checksum = "bbbb"
+@@ -40,12 +40,6 @@ dependencies = [
+  "serde",
+ ]
+
+-[[package]]
+-name = "old-crate"
+-version = "0.3.1"
+-source = "registry+https://github.com/rust-lang/crates.io-index"
+-checksum = "cccc"
+-
+ [[package]]
+ name = "syn"
+ version = "2.0.90"
+@@ -60,3 +54,9 @@ version = "2.0.90"
++
++[[package]]
++name = "new-crate"
++version = "1.9.0"
++source = "registry+https://github.com/rust-lang/crates.io-index"
++checksum = "dddd"
+"#;
+
+    const NPM_DIFF: &str = r#"diff --git a/web/package-lock.json b/web/package-lock.json
+--- a/web/package-lock.json
++++ b/web/package-lock.json
+@@ -1,6 +1,6 @@
+   "packages": {
+     "": {
+-      "version": "1.0.0",
++      "version": "1.1.0",
+@@ -100,8 +100,8 @@
+     "node_modules/lodash": {
+-      "version": "4.17.9",
+-      "resolved": "https://registry.npmjs.org/lodash/-/lodash-4.17.9.tgz",
++      "version": "4.17.21",
++      "resolved": "https://registry.npmjs.org/lodash/-/lodash-4.17.21.tgz",
+       "dev": true
+     },
+"#;
+
+    const GO_DIFF: &str = "diff --git a/go.sum b/go.sum
+--- a/go.sum
++++ b/go.sum
+@@ -1,4 +1,4 @@
+-golang.org/x/net v0.9.0 h1:aaaa=
+-golang.org/x/net v0.9.0/go.mod h1:bbbb=
++golang.org/x/net v0.10.0 h1:cccc=
++golang.org/x/net v0.10.0/go.mod h1:dddd=
+ golang.org/x/text v0.9.0 h1:eeee=
+";
+
+    fn bump(name: &str, from: &str, to: &str) -> DependencyChange {
+        DependencyChange::Bump {
+            name: name.into(),
+            from: from.into(),
+            to: to.into(),
+        }
+    }
+
+    #[test]
+    fn test_parse_lockfiles() {
+        assert_eq!(
+            parse_changes(Format::Cargo, CARGO_DIFF),
+            vec![
+                DependencyChange::Add {
+                    name: "new-crate".into(),
+                    version: "1.9.0".into()
+                },
+                DependencyChange::Remove {
+                    name: "old-crate".into(),
+                    version: "0.3.1".into()
+                },
+                bump("serde", "1.0.218", "1.0.219"),
+            ]
+        );
+        assert_eq!(
+            parse_changes(Format::Npm, NPM_DIFF),
+            vec![bump("lodash", "4.17.9", "4.17.21")]
+        );
+        assert_eq!(
+            parse_changes(Format::GoSum, GO_DIFF),
+            vec![bump("golang.org/x/net", "v0.9.0", "v0.10.0")]
+        );
+    }
+
+    #[test]
+    fn test_summarize_lockfiles() {
+        let summary = summarize_lockfiles(&format!("{NPM_DIFF}{GO_DIFF}"));
+        assert!(summary.dependency_only);
+        assert_eq!(
+            summary.diff,
+            "diff --git a/web/package-lock.json b/web/package-lock.json\n\
+             web/package-lock.json: dependency changes\n  bump lodash 4.17.9 → 4.17.21\n\
+             diff --git a/go.sum b/go.sum\n\
+             go.sum: dependency changes\n  bump golang.org/x/net v0.9.0 → v0.10.0\n"
+        );
+
+        let code = "diff --git a/src/lib.rs b/src/lib.rs\n@@ -1 +1 @@\n-a\n+b\n";
+        let summary = summarize_lockfiles(&format!("{code}{GO_DIFF}"));
+        assert!(!summary.dependency_only);
+        assert!(summary.diff.starts_with(code));
+        assert_eq!(summarize_lockfiles(code).diff, code);
+    }
+
+    #[test]
+    fn test_commit_message() {
+        let one = [bump("serde", "1.0.218", "1.0.219")];
+        assert_eq!(
+            commit_message(&one, true, CommitLanguage::English),
+            "build(deps): bump serde from 1.0.218 to 1.0.219"
+        );
+        assert_eq!(
+            commit_message(&one, false, CommitLanguage::Chinese),
+            "将 serde 从 1.0.218 升级到 1.0.219"
+        );
+
+        let changes = parse_changes(Format::Cargo, CARGO_DIFF);
+        assert_eq!(
+            commit_message(&changes, false, CommitLanguage::English),
+            "Update 3 dependencies\n\n- add new-crate 1.9.0\n- remove old-crate 0.3.1\n- bump serde from 1.0.218 to 1.0.219"
+        );
+    }
+}
@@ -6,9 +6,21 @@ mod animation;
 mod cli;
 mod config;
 mod constants;
+mod diff_filter;
 mod generate;
+mod git_context;
+mod hook;
+mod lint;
+mod lockfile;
+mod patch;
 mod pr;
+mod provider;
+mod review;
 mod sanitizer;
+mod streaming;
+mod style;
+mod summarize;
+mod symbols;
 mod template_engine;
 mod text_wrapper;
 mod update_checker;
@@ -22,19 +34,60 @@ async fn main() -> anyhow::Result<()> {
     spinner.start_with_random_messages().await;
 
     let args = cli::Args::parse();
-    let mut config = config::load_config().await?;
+    let cli_overrides = args
+        .common()
+        .map(cli::CommonArgs::config_overrides)
+        .unwrap_or_default();
+    let layered = config::load_layered_config(cli_overrides).await;
 
-    // Handle subcommands
-    match args.command {
-        Some(cli::Commands::Commit(commit_args)) => {
-            handle_commit_command(&commit_args, &mut config, &spinner).await
+    // config 子命令需要在配置无效时也能运行，以便检查和修复配置文件
+    if let Some(cli::Commands::Config { command }) = &args.command {
+        spinner.finish();
+        if !config::handle_config_command(command, layered)? {
+            std::process::exit(1);
         }
-        Some(cli::Commands::Pr(pr_args)) => {
-            handle_pr_command(&pr_args, &mut config, &spinner).await
+        return Ok(());
+    }
+    let mut config = layered?.config()?;
+
+    // Handle subcommands. Ctrl-C drops the in-flight request and stops the spinner.
+    let run = async {
+        match args.command {
+            Some(cli::Commands::Commit(commit_args)) => {
+                handle_commit_command(&commit_args, &mut config, &spinner).await
+            }
+            Some(cli::Commands::Pr(pr_args)) => {
+                handle_pr_command(&pr_args, &mut config, &spinner).await
+            }
+            Some(cli::Commands::Hook { command }) => {
+                if !matches!(command, cli::HookCommand::Run { .. }) {
+                    spinner.finish();
+                }
+                let result = hook::handle_hook_command(&command, &config).await;
+                spinner.finish();
+                result
+            }
+            Some(cli::Commands::Config { .. }) => unreachable!("handled before loading the config"),
+            Some(cli::Commands::Lint { file }) => {
+                spinner.finish();
+                if !lint::run(&file, &config)? {
+                    std::process::exit(1);
+                }
+                Ok(())
+            }
+            None => {
+                // No subcommand specified, use top-level commit args
+                handle_commit_command(&args.commit_args, &mut config, &spinner).await
+            }
         }
-        None => {
-            // No subcommand specified, use top-level commit args
-            handle_commit_command(&args.commit_args, &mut config, &spinner).await
+    };
+
+    tokio::select! {
+        result = run => result,
+        _ = tokio::signal::ctrl_c() => {
+            spinner.finish();
+            eprintln!("Cancelled.");
+            std::process::exit(130);
         }
     }
 }
@@ -44,31 +97,13 @@ async fn handle_commit_command(
     config: &mut config::Config,
     spinner: &animation::Spinner,
 ) -> anyhow::Result<()> {
-    // 合并命令行参数和配置文件
-    if let Some(c) = args.common.conventional {
-        config.conventional = c;
-    }
-    if let Some(l) = args.common.language {
-        config.language = l;
-    }
-    if let Some(v) = args.common.verbosity {
-        config.verbosity = v;
-    }
-    if args.common.no_sanitize {
-        // CLI override to disable sanitizer
-        config.sanitize_secrets = false;
-    }
-
-    // 合并 auto_commit 参数
-    let auto_commit = args.common.commit || config.auto_commit;
-    let commit_args = if args.common.commit_args.is_empty() {
-        &config.commit_args
-    } else {
-        &args.common.commit_args
-    };
+    // 命令行参数已作为最高优先级的配置层合并
+    // git commit 只会记录暂存区，描述的是其他变更时不自动提交
+    let skip_commit = config.auto_commit && args.differs_from_staged();
+    let auto_commit = config.auto_commit && !skip_commit;
+    let commit_args = config.commit_args.clone();
 
-    // 确定是否启用文本包装 (CLI 参数优先级高于配置)
-    let enable_wrapping = !args.common.no_wrap && config.text_wrap.enabled;
+    let enable_wrapping = config.text_wrap.enabled;
 
     // 预创建统一的包装配置和包装器 (如果需要)
     let wrapper = if enable_wrapping {
@@ -90,54 +125,92 @@ async fn handle_commit_command(
         None
     };
 
+    // 流式输出时实时打印提交信息
+    let mut printer = config
+        .stream
+        .then(|| streaming::StreamPrinter::new(spinner.progress_bar()));
+
     // 根据参数决定生成内容
     if args.generate_branch && args.generate_message {
         // 生成分支名 + 提交信息
-        let (branch_name, msg) = generate::generate_both(args, config).await?;
+        let (branch_name, msg) = generate::generate_both(args, config, printer.as_mut()).await?;
         spinner.finish();
         print_wrapped_content(&wrapper, &branch_name, Some("Generated branch name:"));
         print_wrapped_content(&commit_wrapper, &msg, None);
         if auto_commit {
-            generate::execute_git_commit(&msg, commit_args)?;
+            if let Some(msg) = confirm_commit_message(msg, args, config, &commit_wrapper).await? {
+                generate::execute_git_commit(&msg, &commit_args)?;
+            }
         }
     } else if args.generate_branch {
         // 仅生成分支名
         let branch_name = generate::generate_branch(args, config).await?;
         spinner.finish();
         print_wrapped_content(&wrapper, &branch_name, Some("Generated branch name:"));
+    } else if let Some(n) = args.candidates.filter(|n| *n > 1) {
+        // 生成多个候选提交信息
+        let candidates = generate::generate_candidates(args, config, n).await?;
+        spinner.finish();
+        if !review::is_interactive() {
+            println!("{}", serde_json::to_string_pretty(&candidates)?);
+            if auto_commit {
+                eprintln!("Not committing: pick one of the candidates in a terminal.");
+            }
+            return Ok(());
+        }
+        let picked = review::pick_candidate(&candidates, |m| {
+            print_wrapped_content(&commit_wrapper, m, None)
+        })
+        .await?;
+        match picked {
+            Some(msg) if auto_commit => generate::execute_git_commit(&msg, &commit_args)?,
+            Some(msg) => print_wrapped_content(&commit_wrapper, &msg, None),
+            None => eprintln!("Aborted."),
+        }
     } else {
         // 仅生成提交信息（默认行为）
-        let msg = generate::generate(args, config).await?;
+        let msg = generate::generate(args, config, printer.as_mut()).await?;
         spinner.finish();
         print_wrapped_content(&commit_wrapper, &msg, None);
         if auto_commit {
-            generate::execute_git_commit(&msg, commit_args)?;
+            if let Some(msg) = confirm_commit_message(msg, args, config, &commit_wrapper).await? {
+                generate::execute_git_commit(&msg, &commit_args)?;
+            }
         }
     }
+    if skip_commit {
+        eprintln!("Not committing: the message doesn't describe the staged changes.");
+    }
     Ok(())
 }
 
+/// 提交前让用户确认提交信息；非 TTY 或指定 `--yes` 时直接返回原信息
+async fn confirm_commit_message(
+    msg: String,
+    args: &cli::CommitArgs,
+    config: &mut config::Config,
+    wrapper: &Option<TextWrapper>,
+) -> anyhow::Result<Option<String>> {
+    if args.common.yes || !review::is_interactive() {
+        return Ok(Some(msg));
+    }
+    let accepted = review::review_commit_message(msg, args, config, |m| {
+        print_wrapped_content(wrapper, m, None)
+    })
+    .await?;
+    if accepted.is_none() {
+        eprintln!("Aborted, nothing was committed.");
+    }
+    Ok(accepted)
+}
+
 async fn handle_pr_command(
     args: &cli::PrArgs,
     config: &mut config::Config,
     spinner: &animation::Spinner,
 ) -> anyhow::Result<()> {
-    // 合并命令行参数和配置文件
-    if let Some(c) = args.common.conventional {
-        config.conventional = c;
-    }
-    if let Some(l) = args.common.language {
-        config.language = l;
-    }
-    if let Some(v) = args.common.verbosity {
-        config.verbosity = v;
-    }
-    if args.common.no_sanitize {
-        config.sanitize_secrets = false;
-    }
-
     // 确定是否启用文本包装
-    let enable_wrapping = !args.common.no_wrap && config.text_wrap.enabled;
+    let enable_wrapping = config.text_wrap.enabled;
     let commit_wrapper = if enable_wrapping {
         let wrap_config =
             WrapConfig::from_config_and_args(&config.text_wrap, args.common.wrap_width, true);
@@ -146,8 +219,12 @@ async fn handle_pr_command(
         None
     };
 
+    let mut printer = config
+        .stream
+        .then(|| streaming::StreamPrinter::new(spinner.progress_bar()));
+
     // Generate PR commit message
-    let msg = pr::generate_pr_message(args, config).await?;
+    let msg = pr::generate_pr_message(args, config, printer.as_mut()).await?;
     spinner.finish();
     print_wrapped_content(&commit_wrapper, &msg, None);
 
@@ -0,0 +1,583 @@
+//! A file/hunk model of unified diffs, used to compact the diff put in the
+//! prompt: less context around changes, one-line notes for renames, mode
+//! changes and binary files, and only hunk headers for oversized files.
+
+use lazy_static::lazy_static;
+use regex::Regex;
+
+use crate::config::DiffConfig;
+use crate::git_context::{changed_files, ChangedFile, FileStatus};
+use crate::summarize::{estimate_tokens, split_before};
+use crate::symbols::{annotate_hunks, FileContents};
+
+/// At most this many hunk headers are listed for an oversized file.
+const MAX_LISTED_HUNKS: usize = 20;
+
+lazy_static! {
+    static ref HUNK_HEADER: Regex =
+        Regex::new(r"^@@ -(\d+)(?:,(\d+))? \+(\d+)(?:,(\d+))? @@(.*)$").unwrap();
+}
+
+/// The kind of a hunk line: ` `, `+`, `-` or `\`. Editors sometimes strip the
+/// space of empty context lines, so an empty line is context too.
+pub(crate) fn line_kind(line: &str) -> char {
+    line.chars().next().unwrap_or(' ')
+}
+
+/// One `@@` hunk. Start lines are those of the first line in the hunk, also
+/// when it is empty, unlike the header where an empty range names the line before.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hunk {
+    pub old_start: usize,
+    pub old_len: usize,
+    pub new_start: usize,
+    pub new_len: usize,
+    /// Text after the closing `@@`, usually the enclosing function
+    pub section: String,
+    /// Lines including their ` `, `+`, `-` or `\` prefix
+    pub lines: Vec<String>,
+}
+
+impl Hunk {
+    fn parse_header(line: &str) -> Option<Self> {
+        let captures = HUNK_HEADER.captures(line)?;
+        let number = |i: usize, default: usize| {
+            captures
+                .get(i)
+                .map_or(Some(default), |m| m.as_str().parse().ok())
+        };
+        let (old_len, new_len) = (number(2, 1)?, number(4, 1)?);
+        let start = |i: usize, len: usize| number(i, 0).map(|s| if len == 0 { s + 1 } else { s });
+        Some(Self {
+            old_start: start(1, old_len)?,
+            old_len,
+            new_start: start(3, new_len)?,
+            new_len,
+            section: captures[5].to_string(),
+            lines: Vec::new(),
+        })
+    }
+
+    pub fn header(&self) -> String {
+        let range = |start: usize, len: usize| match len {
+            0 => format!("{},0", start.saturating_sub(1)),
+            1 => start.to_string(),
+            _ => format!("{start},{len}"),
+        };
+        format!(
+            "@@ -{} +{} @@{}",
+            range(self.old_start, self.old_len),
+            range(self.new_start, self.new_len),
+            self.section
+        )
+    }
+
+    /// Split the hunk so that at most `context` unchanged lines surround each change.
+    fn with_context(&self, context: usize) -> Vec<Hunk> {
+        let is_change = |line: &String| matches!(line_kind(line), '+' | '-');
+        if !self.lines.iter().any(is_change) {
+            return vec![self.clone()];
+        }
+
+        // 每行到最近一处改动的距离，取前后两次扫描的较小值
+        let mut distance = vec![usize::MAX; self.lines.len()];
+        let mut last = None;
+        for (i, line) in self.lines.iter().enumerate() {
+            if is_change(line) {
+                last = Some(i);
+            }
+            if let Some(last) = last {
+                distance[i] = i - last;
+            }
+        }
+        last = None;
+        for (i, line) in self.lines.iter().enumerate().rev() {
+            if is_change(line) {
+                last = Some(i);
+            }
+            if let Some(last) = last {
+                distance[i] = distance[i].min(last - i);
+            }
+        }
+
+        let mut hunks = Vec::new();
+        let mut current: Option<Hunk> = None;
+        let (mut old_line, mut new_line) = (self.old_start, self.new_start);
+        let mut kept_previous = false;
+        for (i, line) in self.lines.iter().enumerate() {
+            let kind = line_kind(line);
+            // "\ No newline at end of file" 跟随它所说明的那一行
+            let keep = if kind == '\\' {
+                kept_previous
+            } else {
+                distance[i] <= context
+            };
+            if keep {
+                let hunk = current.get_or_insert_with(|| Hunk {
+                    old_start: old_line,
+                    old_len: 0,
+                    new_start: new_line,
+                    new_len: 0,
+                    section: self.section.clone(),
+                    lines: Vec::new(),
+                });
+                hunk.old_len += usize::from(matches!(kind, ' ' | '-'));
+                hunk.new_len += usize::from(matches!(kind, ' ' | '+'));
+                hunk.lines.push(line.clone());
+            } else if let Some(hunk) = current.take() {
+                hunks.push(hunk);
+            }
+            old_line += usize::from(matches!(kind, ' ' | '-'));
+            new_line += usize::from(matches!(kind, ' ' | '+'));
+            kept_previous = keep;
+        }
+        hunks.extend(current);
+        hunks
+    }
+
+    /// Added and removed line counts.
+    fn changes(&self) -> (usize, usize) {
+        self.lines
+            .iter()
+            .fold((0, 0), |(added, removed), line| match line_kind(line) {
+                '+' => (added + 1, removed),
+                '-' => (added, removed + 1),
+                _ => (added, removed),
+            })
+    }
+}
+
+/// One file of a diff.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileDiff {
+    /// `diff --git`, mode, index and `---`/`+++` lines
+    pub header: Vec<String>,
+    pub hunks: Vec<Hunk>,
+    /// Lines after the last hunk that aren't part of it, such as notes added
+    /// while preparing the diff
+    pub trailer: Vec<String>,
+}
+
+impl FileDiff {
+    /// Parse the section of one file, or `None` if its hunks are malformed.
+    pub fn parse(section: &str) -> Option<Self> {
+        let mut file = FileDiff {
+            header: Vec::new(),
+            hunks: Vec::new(),
+            trailer: Vec::new(),
+        };
+        let (mut old_left, mut new_left) = (0usize, 0usize);
+        for line in section.lines() {
+            if line.starts_with("@@") {
+                if !file.trailer.is_empty() {
+                    return None;
+                }
+                let hunk = Hunk::parse_header(line)?;
+                (old_left, new_left) = (hunk.old_len, hunk.new_len);
+                file.hunks.push(hunk);
+                continue;
+            }
+            let Some(hunk) = file.hunks.last_mut() else {
+                file.header.push(line.to_string());
+                continue;
+            };
+            let kind = line_kind(line);
+            let in_hunk = old_left > 0 || new_left > 0 || kind == '\\';
+            if !in_hunk || !file.trailer.is_empty() {
+                file.trailer.push(line.to_string());
+                continue;
+            }
+            match kind {
+                ' ' if old_left > 0 && new_left > 0 => {
+                    old_left -= 1;
+                    new_left -= 1;
+                }
+                '-' if old_left > 0 => old_left -= 1,
+                '+' if new_left > 0 => new_left -= 1,
+                '\\' => {}
+                _ => return None,
+            }
+            hunk.lines.push(line.to_string());
+        }
+        Some(file)
+    }
+
+    pub fn render(&self) -> String {
+        let mut text = String::new();
+        for line in &self.header {
+            text.push_str(line);
+            text.push('\n');
+        }
+        for hunk in &self.hunks {
+            text.push_str(&hunk.header());
+            text.push('\n');
+            for line in &hunk.lines {
+                text.push_str(line);
+                text.push('\n');
+            }
+        }
+        for line in &self.trailer {
+            text.push_str(line);
+            text.push('\n');
+        }
+        text
+    }
+}
+
+/// Compact `diff` for the prompt as configured in `[diff]`, naming the
+/// symbols each hunk touches. Files that are already in that form are left
+/// byte for byte as they were.
+pub fn compact_diff(diff: &str, config: &DiffConfig) -> String {
+    let reads_files = config.symbols || config.full_file_tokens > 0;
+    if !config.compact && !reads_files {
+        return diff.to_string();
+    }
+    let sections = split_before(diff, |l| l.starts_with("diff --git "));
+    let files: Vec<Option<CompactFile>> = sections
+        .iter()
+        .map(|section| compact_file(section, config))
+        .collect();
+    // 一次读出所有需要的文件内容，已改写为说明的文件不必读取
+    let contents = reads_files.then(|| {
+        let paths: Vec<&str> = files
+            .iter()
+            .flatten()
+            .filter_map(CompactFile::content_path)
+            .collect();
+        FileContents::load(&paths)
+    });
+
+    let mut budget = config.full_file_tokens;
+    sections
+        .into_iter()
+        .zip(files)
+        .map(|(section, file)| match file {
+            Some(file) => file
+                .finish(config, contents.as_ref(), &mut budget)
+                .unwrap_or(section),
+            None => section,
+        })
+        .collect()
+}
+
+/// One file's section after the first compaction step.
+enum CompactFile {
+    Note(String),
+    Hunks {
+        original: FileDiff,
+        compacted: FileDiff,
+        info: ChangedFile,
+    },
+}
+
+impl CompactFile {
+    /// The path whose content is needed for symbols and full content.
+    fn content_path(&self) -> Option<&str> {
+        match self {
+            CompactFile::Hunks {
+                compacted, info, ..
+            } if !compacted.hunks.is_empty() && info.status != FileStatus::Deleted => {
+                Some(&info.path)
+            }
+            _ => None,
+        }
+    }
+
+    /// The final text of the section, or `None` to keep it as it is.
+    fn finish(
+        self,
+        config: &DiffConfig,
+        contents: Option<&FileContents>,
+        budget: &mut usize,
+    ) -> Option<String> {
+        let path = self.content_path().map(str::to_string);
+        let (original, mut compacted, info) = match self {
+            CompactFile::Note(note) => return Some(note),
+            CompactFile::Hunks {
+                original,
+                compacted,
+                info,
+            } => (original, compacted, info),
+        };
+        let content = path.and_then(|p| contents?.matching(&p, &compacted.hunks));
+        if let Some(content) = content {
+            if config.symbols {
+                annotate_hunks(&mut compacted.hunks, &content);
+            }
+            // 新增文件的 diff 已经包含完整内容
+            if info.status != FileStatus::Added {
+                append_content(&mut compacted, &info.path, &content, budget);
+            }
+        }
+        (compacted != original).then(|| compacted.render())
+    }
+}
+
+/// Parse one file's section and apply the notes and context reduction, or
+/// `None` to keep it as it is.
+fn compact_file(section: &str, config: &DiffConfig) -> Option<CompactFile> {
+    if !section.starts_with("diff --git ") {
+        return None;
+    }
+    let file = FileDiff::parse(section)?;
+    let info = changed_files(section).into_iter().next()?;
+    if config.compact {
+        if let Some(note) = note_file(&file, &info, config) {
+            return Some(CompactFile::Note(note));
+        }
+    }
+
+    let mut compacted = file.clone();
+    if config.compact {
+        compacted.hunks = file
+            .hunks
+            .iter()
+            .flat_map(|h| h.with_context(config.context))
+            .collect();
+    }
+    Some(CompactFile::Hunks {
+        original: file,
+        compacted,
+        info,
+    })
+}
+
+/// One-line notes for binary files, pure renames and mode changes, and only
+/// the hunk headers of files with more than `max_file_lines` changed lines.
+fn note_file(file: &FileDiff, info: &ChangedFile, config: &DiffConfig) -> Option<String> {
+    let header_value = |prefix: &str| file.header.iter().find_map(|l| l.strip_prefix(prefix));
+    let with_trailer = |lines: Vec<String>| {
+        FileDiff {
+            header: lines,
+            hunks: Vec::new(),
+            trailer: file.trailer.clone(),
+        }
+        .render()
+    };
+
+    let binary = file
+        .header
+        .iter()
+        .any(|l| l.starts_with("Binary files ") || l == "GIT binary patch");
+    if binary {
+        let what = match info.status {
+            FileStatus::Added => "added",
+            FileStatus::Deleted => "deleted",
+            _ => "changed",
+        };
+        return Some(with_trailer(vec![format!(
+            "binary file {what}: {}",
+            info.path
+        )]));
+    }
+
+    if file.hunks.is_empty() {
+        let note = if let Some(old_path) = &info.old_path {
+            let similarity = header_value("similarity index ").unwrap_or("100%");
+            format!("rename {old_path} => {} ({similarity})", info.path)
+        } else if let (Some(old), Some(new)) =
+            (header_value("old mode "), header_value("new mode "))
+        {
+            format!("mode change {old} => {new} {}", info.path)
+        } else {
+            return None;
+        };
+        return Some(with_trailer(vec![note]));
+    }
+
+    let (added, removed) = file
+        .hunks
+        .iter()
+        .map(Hunk::changes)
+        .fold((0, 0), |(a, r), (ha, hr)| (a + ha, r + hr));
+    if config.max_file_lines == 0 || added + removed <= config.max_file_lines {
+        return None;
+    }
+    let mut lines = file.header.clone();
+    lines.push(format!(
+        "{} changed lines (+{added} -{removed}) not shown, hunks:",
+        added + removed
+    ));
+    lines.extend(file.hunks.iter().take(MAX_LISTED_HUNKS).map(Hunk::header));
+    if file.hunks.len() > MAX_LISTED_HUNKS {
+        lines.push(format!(
+            "... and {} more hunks",
+            file.hunks.len() - MAX_LISTED_HUNKS
+        ));
+    }
+    Some(with_trailer(lines))
+}
+
+/// Add the whole new `content` after the hunks if it fits the remaining `budget`.
+fn append_content(file: &mut FileDiff, path: &str, content: &[String], budget: &mut usize) {
+    if *budget == 0 {
+        return;
+    }
+    let tokens = estimate_tokens(&content.join("\n"));
+    if tokens > *budget {
+        return;
+    }
+    *budget -= tokens;
+    file.trailer
+        .push(format!("full content of {path} after the change:"));
+    file.trailer.extend(content.iter().cloned());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DIFF: &str = "\
+diff --git a/src/lib.rs b/src/lib.rs
+index 1111111..2222222 100644
+--- a/src/lib.rs
++++ b/src/lib.rs
+@@ -1,10 +1,10 @@ mod parser;
+ line 1
+-line 2
++line two
+ line 3
+ line 4
+ line 5
+ line 6
+ line 7
+ line 8
+-line 9
++line nine
+ line 10
+@@ -20,0 +21,2 @@ fn run() {
++added 1
++added 2
+";
+
+    fn config(context: usize) -> DiffConfig {
+        DiffConfig {
+            context,
+            symbols: false,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_parse_and_render() {
+        let file = FileDiff::parse(DIFF).unwrap();
+        assert_eq!(file.header.len(), 4);
+        assert_eq!(file.hunks.len(), 2);
+        assert_eq!(file.hunks[1].old_start, 21);
+        assert_eq!(file.hunks[1].section, " fn run() {");
+        assert_eq!(file.render(), DIFF);
+
+        let noted = format!("{DIFF}2 files changed (excluded): a, b\n");
+        let file = FileDiff::parse(&noted).unwrap();
+        assert_eq!(file.trailer, vec!["2 files changed (excluded): a, b"]);
+        assert_eq!(file.render(), noted);
+        assert!(FileDiff::parse("diff --git a/x b/x\n@@ -1 +1 @@\n?\n").is_none());
+    }
+
+    #[test]
+    fn test_context() {
+        assert_eq!(compact_diff(DIFF, &config(3)), DIFF);
+        let compacted = compact_diff(DIFF, &config(1));
+        assert!(compacted.contains(
+            "@@ -1,3 +1,3 @@ mod parser;\n line 1\n-line 2\n+line two\n line 3\n\
+             @@ -8,3 +8,3 @@ mod parser;\n line 8\n-line 9\n+line nine\n line 10\n\
+             @@ -20,0 +21,2 @@ fn run() {\n"
+        ));
+        assert!(compact_diff(DIFF, &config(0)).contains("@@ -2 +2 @@ mod parser;\n-line 2\n"));
+        assert_eq!(compact_diff(&compacted, &config(1)), compacted);
+    }
+
+    #[test]
+    fn test_notes() {
+        let diff = "\
+diff --git a/old.rs b/new.rs
+similarity index 100%
+rename from old.rs
+rename to new.rs
+diff --git a/run.sh b/run.sh
+old mode 100644
+new mode 100755
+diff --git a/logo.png b/logo.png
+new file mode 100644
+index 0000000..3333333
+Binary files /dev/null and b/logo.png differ
+";
+        assert_eq!(
+            compact_diff(diff, &config(3)),
+            "rename old.rs => new.rs (100%)\nmode change 100644 => 100755 run.sh\nbinary file added: logo.png\n"
+        );
+
+        let huge = DiffConfig {
+            max_file_lines: 3,
+            ..config(3)
+        };
+        assert_eq!(
+            compact_diff(DIFF, &huge),
+            "diff --git a/src/lib.rs b/src/lib.rs\nindex 1111111..2222222 100644\n--- a/src/lib.rs\n+++ b/src/lib.rs\n\
+             6 changed lines (+4 -2) not shown, hunks:\n@@ -1,10 +1,10 @@ mod parser;\n@@ -20,0 +21,2 @@ fn run() {\n"
+        );
+    }
+
+    #[test]
+    fn test_default_reduces_context() {
+        let config = DiffConfig {
+            symbols: false,
+            ..Default::default()
+        };
+        let compacted = compact_diff(DIFF, &config);
+        assert!(compacted.starts_with(
+            "diff --git a/src/lib.rs b/src/lib.rs\nindex 1111111..2222222 100644\n--- a/src/lib.rs\n+++ b/src/lib.rs\n\
+             @@ -1,3 +1,3 @@ mod parser;\n line 1\n-line 2\n+line two\n line 3\n"
+        ));
+        assert!(compacted.len() < DIFF.len());
+    }
+
+    #[test]
+    fn test_compact_is_idempotent() {
+        let diff = format!(
+            "{DIFF}\
+diff --git a/old.rs b/new.rs
+similarity index 100%
+rename from old.rs
+rename to new.rs
+diff --git a/logo.png b/logo.png
+new file mode 100644
+index 0000000..3333333
+Binary files /dev/null and b/logo.png differ
+2 files changed (excluded): a, b
+"
+        );
+        for config in [
+            config(0),
+            config(1),
+            config(3),
+            DiffConfig {
+                max_file_lines: 3,
+                ..config(1)
+            },
+        ] {
+            let once = compact_diff(&diff, &config);
+            assert_eq!(compact_diff(&once, &config), once, "{config:?}");
+        }
+    }
+
+    #[test]
+    fn test_append_content() {
+        let content: Vec<String> = vec!["fn main() {}".into()];
+        let mut file = FileDiff::parse(DIFF).unwrap();
+        let mut budget = 2;
+        append_content(&mut file, "src/lib.rs", &content, &mut budget);
+        assert!(file.trailer.is_empty());
+
+        budget = 100;
+        append_content(&mut file, "src/lib.rs", &content, &mut budget);
+        assert_eq!(
+            file.trailer,
+            vec![
+                "full content of src/lib.rs after the change:",
+                "fn main() {}"
+            ]
+        );
+        assert_eq!(budget, 97);
+    }
+}
@@ -1,8 +1,11 @@
 use std::process::Command;
 
 use crate::cli::PrArgs;
-use crate::config::Config;
-use crate::generate::generate_commit_message;
+use crate::config::{Config, Task};
+use crate::generate::{generate_commit_message, prepare_diff, prepare_prompt_diff};
+use crate::git_context::GitContext;
+use crate::streaming::StreamPrinter;
+use crate::template_engine::TemplateKind;
 
 /// Get PR diff using gh CLI
 fn get_pr_diff_from_gh(pr_number: Option<u32>, repo: Option<&str>) -> anyhow::Result<String> {
@@ -31,11 +34,25 @@ fn get_pr_diff_from_gh(pr_number: Option<u32>, repo: Option<&str>) -> anyhow::Re
     Ok(diff)
 }
 
-/// Detect current branch's associated PR number
-fn detect_current_pr(repo: Option<&str>) -> anyhow::Result<u32> {
+/// What `gh pr view` tells about a PR, used as prompt context instead of the
+/// local checkout, which may be on an unrelated branch.
+#[derive(Debug, PartialEq, Eq)]
+struct PrView {
+    number: u32,
+    head_ref: Option<String>,
+    /// Commit subjects, newest first
+    commits: Vec<String>,
+}
+
+/// View the PR `pr_number`, or the one associated with the current branch
+fn view_pr(pr_number: Option<u32>, repo: Option<&str>) -> anyhow::Result<PrView> {
     let mut cmd = Command::new("gh");
-    cmd.args(["pr", "view", "--json", "number"]);
+    cmd.args(["pr", "view"]);
 
+    if let Some(num) = pr_number {
+        cmd.arg(num.to_string());
+    }
+    cmd.args(["--json", "number,headRefName,commits"]);
     if let Some(r) = repo {
         cmd.args(["--repo", r]);
     }
@@ -44,21 +61,43 @@ fn detect_current_pr(repo: Option<&str>) -> anyhow::Result<u32> {
 
     if !output.status.success() {
         let stderr = String::from_utf8_lossy(&output.stderr);
-        return Err(anyhow::anyhow!(
-            "Failed to detect current PR. Please specify PR number explicitly. Error: {}",
-            stderr.trim()
-        ));
+        return Err(match pr_number {
+            Some(num) => anyhow::anyhow!("gh pr view {} failed: {}", num, stderr.trim()),
+            None => anyhow::anyhow!(
+                "Failed to detect current PR. Please specify PR number explicitly. Error: {}",
+                stderr.trim()
+            ),
+        });
     }
 
-    let json_output = String::from_utf8_lossy(&output.stdout);
-    let parsed: serde_json::Value = serde_json::from_str(&json_output)
+    parse_pr_view(&String::from_utf8_lossy(&output.stdout))
+}
+
+fn parse_pr_view(json_output: &str) -> anyhow::Result<PrView> {
+    let parsed: serde_json::Value = serde_json::from_str(json_output)
         .map_err(|e| anyhow::anyhow!("Failed to parse gh output: {}", e))?;
 
     let pr_number = parsed["number"]
         .as_u64()
         .ok_or(anyhow::anyhow!("Could not find PR number in gh output"))?;
-
-    Ok(pr_number as u32)
+    // gh 按时间顺序列出提交，最早的在前
+    let commits = parsed["commits"]
+        .as_array()
+        .map(|commits| {
+            commits
+                .iter()
+                .rev()
+                .filter_map(|c| c["messageHeadline"].as_str())
+                .map(str::to_string)
+                .collect()
+        })
+        .unwrap_or_default();
+
+    Ok(PrView {
+        number: pr_number as u32,
+        head_ref: parsed["headRefName"].as_str().map(str::to_string),
+        commits,
+    })
 }
 
 /// Check if gh CLI is available
@@ -71,7 +110,11 @@ fn is_gh_available() -> bool {
 }
 
 /// Generate commit message for a PR
-pub async fn generate_pr_message(args: &PrArgs, config: &Config) -> anyhow::Result<String> {
+pub async fn generate_pr_message(
+    args: &PrArgs,
+    config: &Config,
+    stream: Option<&mut StreamPrinter>,
+) -> anyhow::Result<String> {
     // Check if gh is available
     if !is_gh_available() {
         return Err(anyhow::anyhow!(
@@ -79,21 +122,72 @@ pub async fn generate_pr_message(args: &PrArgs, config: &Config) -> anyhow::Resu
         ));
     }
 
-    // Detect PR number if not specified
-    let pr_number = match args.pr_number {
-        Some(num) => num,
-        None => detect_current_pr(args.repo.as_deref())?,
-    };
+    let pr = view_pr(args.pr_number, args.repo.as_deref())?;
+    let pr_number = pr.number;
 
     log::info!("Getting diff for PR #{}...", pr_number);
 
     // Get PR diff
     let diff = get_pr_diff_from_gh(Some(pr_number), args.repo.as_deref())?;
+    // --repo 指定的是其他仓库时，本地检出的文件、配置规则和提交风格都与它无关
+    let in_checkout = args.repo.is_none();
+    let (diff, _) = prepare_diff(&diff, config, in_checkout);
 
     log::info!("Generating commit message...");
 
     // Generate commit message using existing logic
-    let message = generate_commit_message(&diff, config, args.common.prompt.as_deref()).await?;
+    let mut config = config.for_task(Task::Pr)?;
+    if !in_checkout {
+        config.style.learn = false;
+        config.diff.symbols = false;
+        config.diff.full_file_tokens = 0;
+    }
+    let config = &config;
+    let mut context = GitContext::collect(config, &diff);
+    // 分支和最近提交取自 PR 本身，而不是本地当前检出的分支
+    context.branch = pr.head_ref.filter(|_| config.context.branch);
+    if let Some(repo) = args.repo.as_deref() {
+        // [HOST/]OWNER/REPO
+        let name = repo.rsplit('/').next().unwrap_or(repo);
+        context.repo = config.context.repo.then(|| name.to_string());
+    }
+    context.recent_commits = pr
+        .commits
+        .into_iter()
+        .take(config.context.recent_commits)
+        .collect();
+    let diff = prepare_prompt_diff(&diff, config).await?;
+    let message = generate_commit_message(
+        &diff,
+        config,
+        TemplateKind::Pr,
+        &context,
+        args.common.prompt.as_deref(),
+        stream,
+    )
+    .await?;
 
     Ok(message)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_pr_view() {
+        let output = r#"{"number":42,"headRefName":"feat/parser","commits":[
+            {"oid":"a1","messageHeadline":"feat: add parser","messageBody":""},
+            {"oid":"b2","messageHeadline":"fix: handle empty input","messageBody":"x"}
+        ]}"#;
+        assert_eq!(
+            parse_pr_view(output).unwrap(),
+            PrView {
+                number: 42,
+                head_ref: Some("feat/parser".into()),
+                commits: vec!["fix: handle empty input".into(), "feat: add parser".into()],
+            }
+        );
+        assert!(parse_pr_view(r#"{"headRefName":"x"}"#).is_err());
+    }
+}
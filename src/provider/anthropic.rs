@@ -0,0 +1,366 @@
+use serde::{Deserialize, Serialize};
+
+use super::http::{status_error, wrap_error, HttpClient};
+use super::sse::SseDecoder;
+use super::{BoxFuture, ChatRequest, ChatResponse, ChatRole, DeltaSink, Provider, Usage};
+use crate::constants::ANTHROPIC_API_VERSION;
+
+/// Native Anthropic Messages API (`/v1/messages`) backend.
+pub struct AnthropicProvider {
+    http: HttpClient,
+    api_key: String,
+    api_base: String,
+}
+
+#[derive(Debug, Serialize)]
+struct MessagesBody<'a> {
+    model: &'a str,
+    max_tokens: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    system: Option<String>,
+    messages: Vec<MessageParam<'a>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    stop_sequences: &'a [String],
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    stream: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct MessageParam<'a> {
+    role: &'static str,
+    content: &'a str,
+}
+
+#[derive(Debug, Deserialize)]
+struct MessagesResponse {
+    model: Option<String>,
+    #[serde(default)]
+    content: Vec<ContentBlock>,
+    usage: Option<AnthropicUsage>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type")]
+enum ContentBlock {
+    #[serde(rename = "text")]
+    Text { text: String },
+    /// `thinking`, `tool_use` and other blocks carry no commit text.
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Deserialize)]
+struct AnthropicUsage {
+    input_tokens: Option<u32>,
+    output_tokens: Option<u32>,
+}
+
+/// Events of a streamed Messages response that carry text or usage.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type")]
+enum StreamEvent {
+    #[serde(rename = "message_start")]
+    MessageStart { message: StreamMessage },
+    #[serde(rename = "content_block_delta")]
+    ContentBlockDelta { delta: StreamDelta },
+    #[serde(rename = "message_delta")]
+    MessageDelta { usage: Option<AnthropicUsage> },
+    #[serde(rename = "error")]
+    Error { error: ErrorDetail },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Deserialize)]
+struct StreamMessage {
+    model: Option<String>,
+    usage: Option<AnthropicUsage>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type")]
+enum StreamDelta {
+    #[serde(rename = "text_delta")]
+    Text { text: String },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Deserialize)]
+struct ErrorResponse {
+    error: ErrorDetail,
+}
+
+#[derive(Debug, Deserialize)]
+struct ErrorDetail {
+    #[serde(rename = "type")]
+    kind: String,
+    message: String,
+}
+
+impl AnthropicProvider {
+    pub fn new(http: HttpClient, api_key: &str, api_base: &str) -> Self {
+        Self {
+            http,
+            api_key: api_key.to_owned(),
+            api_base: api_base.to_owned(),
+        }
+    }
+
+    async fn send(&self, request: &ChatRequest, stream: bool) -> anyhow::Result<reqwest::Response> {
+        let url = format!("{}messages", self.api_base);
+        let builder = self
+            .http
+            .post(&url)
+            .header("x-api-key", &self.api_key)
+            .header("anthropic-version", ANTHROPIC_API_VERSION)
+            .json(&Self::build_body(request, stream));
+        let response = self
+            .http
+            .send(builder)
+            .await
+            .map_err(|e| wrap_error(e, format!("Failed to send request to {url}")))?;
+
+        let status = response.status();
+        if status.is_success() {
+            return Ok(response);
+        }
+        let text = response.text().await.unwrap_or_default();
+        let message = match serde_json::from_str::<ErrorResponse>(&text) {
+            Ok(err) => format!(
+                "Anthropic API error ({}): {}: {}",
+                status, err.error.kind, err.error.message
+            ),
+            Err(_) => format!("Anthropic API error ({status}): {text}"),
+        };
+        Err(status_error(status, message))
+    }
+
+    fn build_body(request: &ChatRequest, stream: bool) -> MessagesBody<'_> {
+        // Anthropic takes the system prompt as a top level field instead of a message.
+        let system = request
+            .messages
+            .iter()
+            .filter(|m| m.role == ChatRole::System)
+            .map(|m| m.content.as_str())
+            .collect::<Vec<_>>();
+        let messages = request
+            .messages
+            .iter()
+            .filter(|m| m.role != ChatRole::System)
+            .map(|m| MessageParam {
+                role: "user",
+                content: &m.content,
+            })
+            .collect();
+
+        MessagesBody {
+            model: &request.model,
+            max_tokens: request.max_tokens,
+            system: (!system.is_empty()).then(|| system.join("\n\n")),
+            messages,
+            temperature: request.temperature,
+            top_p: request.top_p,
+            stop_sequences: &request.stop,
+            stream,
+        }
+    }
+}
+
+impl Provider for AnthropicProvider {
+    fn name(&self) -> &'static str {
+        "anthropic"
+    }
+
+    fn chat<'a>(&'a self, request: &'a ChatRequest) -> BoxFuture<'a, anyhow::Result<ChatResponse>> {
+        Box::pin(async move {
+            let text = self.send(request, false).await?.text().await?;
+            let parsed: MessagesResponse = serde_json::from_str(&text)
+                .map_err(|e| anyhow::anyhow!("Failed to parse Anthropic response: {}", e))?;
+            let content = parsed
+                .content
+                .into_iter()
+                .filter_map(|block| match block {
+                    ContentBlock::Text { text } => Some(text),
+                    ContentBlock::Other => None,
+                })
+                .collect::<String>();
+            if content.is_empty() {
+                return Err(anyhow::anyhow!("No text content in response"));
+            }
+
+            Ok(ChatResponse {
+                content,
+                alternatives: Vec::new(),
+                model: parsed.model,
+                usage: parsed.usage.map(|u| Usage {
+                    prompt_tokens: u.input_tokens,
+                    completion_tokens: u.output_tokens,
+                    total_tokens: u.input_tokens.zip(u.output_tokens).map(|(i, o)| i + o),
+                }),
+            })
+        })
+    }
+
+    fn chat_stream<'a>(
+        &'a self,
+        request: &'a ChatRequest,
+        on_delta: DeltaSink<'a>,
+    ) -> BoxFuture<'a, anyhow::Result<ChatResponse>> {
+        Box::pin(async move {
+            let mut response = self.send(request, true).await?;
+            let mut decoder = SseDecoder::new();
+            let mut content = String::new();
+            let mut model = None;
+            let mut input_tokens = None;
+            let mut output_tokens = None;
+
+            while let Some(bytes) = response.chunk().await? {
+                for event in decoder.feed(&bytes) {
+                    let event: StreamEvent = serde_json::from_str(&event.data)
+                        .map_err(|e| anyhow::anyhow!("Failed to parse Anthropic event: {}", e))?;
+                    match event {
+                        StreamEvent::MessageStart { message } => {
+                            model = message.model;
+                            input_tokens = message.usage.and_then(|u| u.input_tokens);
+                        }
+                        StreamEvent::ContentBlockDelta {
+                            delta: StreamDelta::Text { text },
+                        } => {
+                            on_delta(&text);
+                            content.push_str(&text);
+                        }
+                        StreamEvent::MessageDelta { usage } => {
+                            output_tokens = usage.and_then(|u| u.output_tokens).or(output_tokens);
+                        }
+                        StreamEvent::Error { error } => {
+                            return Err(anyhow::anyhow!(
+                                "Anthropic API error: {}: {}",
+                                error.kind,
+                                error.message
+                            ));
+                        }
+                        StreamEvent::ContentBlockDelta { .. } | StreamEvent::Other => {}
+                    }
+                }
+            }
+
+            Ok(ChatResponse {
+                content,
+                alternatives: Vec::new(),
+                model,
+                usage: Some(Usage {
+                    prompt_tokens: input_tokens,
+                    completion_tokens: output_tokens,
+                    total_tokens: input_tokens.zip(output_tokens).map(|(i, o)| i + o),
+                }),
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::HttpConfig;
+    use crate::provider::mock_server::MockServer;
+    use crate::provider::ChatMessage;
+
+    fn provider(api_key: &str, url: &str) -> AnthropicProvider {
+        AnthropicProvider::new(
+            HttpClient::new(&HttpConfig::default()).unwrap(),
+            api_key,
+            url,
+        )
+    }
+
+    fn request() -> ChatRequest {
+        ChatRequest {
+            model: "claude-test".to_string(),
+            messages: vec![
+                ChatMessage::system("system prompt"),
+                ChatMessage::user("user prompt"),
+            ],
+            max_tokens: 256,
+            temperature: Some(0.3),
+            top_p: None,
+            stop: vec!["</aicommit>".into()],
+            reasoning_effort: None,
+            n: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_messages_request_and_response() {
+        let server = MockServer::start(
+            200,
+            r#"{"model":"claude-test","content":[{"type":"thinking","thinking":"hmm"},{"type":"text","text":"<aicommit>fix: x</aicommit>"}],"usage":{"input_tokens":10,"output_tokens":5}}"#,
+        )
+        .await;
+        let provider = provider("secret", &server.url());
+
+        let response = provider.chat(&request()).await.unwrap();
+        assert_eq!(response.content, "<aicommit>fix: x</aicommit>");
+        assert_eq!(response.usage.unwrap().total_tokens, Some(15));
+
+        let captured = server.request();
+        assert!(captured.starts_with("POST /messages "));
+        assert!(captured.contains("x-api-key: secret"));
+        assert!(captured.contains(&format!("anthropic-version: {ANTHROPIC_API_VERSION}")));
+        let body: serde_json::Value = serde_json::from_str(captured.body()).unwrap();
+        assert_eq!(body["system"], "system prompt");
+        assert_eq!(body["messages"][0]["role"], "user");
+        assert_eq!(body["messages"][0]["content"], "user prompt");
+        assert_eq!(body["max_tokens"], 256);
+        assert_eq!(body["stop_sequences"][0], "</aicommit>");
+        assert!(body.get("top_p").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_error_response() {
+        let server = MockServer::start(
+            401,
+            r#"{"type":"error","error":{"type":"authentication_error","message":"invalid x-api-key"}}"#,
+        )
+        .await;
+        let provider = provider("bad", &server.url());
+
+        let err = provider.chat(&request()).await.unwrap_err().to_string();
+        assert!(err.contains("authentication_error"));
+        assert!(err.contains("invalid x-api-key"));
+    }
+
+    #[tokio::test]
+    async fn test_streamed_messages() {
+        let body = concat!(
+            "event: message_start\n",
+            "data: {\"type\":\"message_start\",\"message\":{\"model\":\"claude-test\",\"usage\":{\"input_tokens\":9}}}\n\n",
+            "event: content_block_delta\n",
+            "data: {\"type\":\"content_block_delta\",\"index\":0,\"delta\":{\"type\":\"thinking_delta\",\"thinking\":\"hmm\"}}\n\n",
+            "event: content_block_delta\n",
+            "data: {\"type\":\"content_block_delta\",\"index\":1,\"delta\":{\"type\":\"text_delta\",\"text\":\"<aicommit>fix\"}}\n\n",
+            "event: content_block_delta\n",
+            "data: {\"type\":\"content_block_delta\",\"index\":1,\"delta\":{\"type\":\"text_delta\",\"text\":\"</aicommit>\"}}\n\n",
+            "event: message_delta\n",
+            "data: {\"type\":\"message_delta\",\"usage\":{\"output_tokens\":4}}\n\n",
+            "event: message_stop\n",
+            "data: {\"type\":\"message_stop\"}\n\n",
+        );
+        let server = MockServer::start(200, body).await;
+        let provider = provider("secret", &server.url());
+
+        let mut deltas = Vec::new();
+        let mut sink = |d: &str| deltas.push(d.to_string());
+        let response = provider.chat_stream(&request(), &mut sink).await.unwrap();
+        assert_eq!(response.content, "<aicommit>fix</aicommit>");
+        assert_eq!(response.usage.unwrap().total_tokens, Some(13));
+        assert_eq!(deltas, vec!["<aicommit>fix", "</aicommit>"]);
+
+        let body: serde_json::Value = serde_json::from_str(server.request().body()).unwrap();
+        assert_eq!(body["stream"], true);
+    }
+}
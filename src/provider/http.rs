@@ -0,0 +1,218 @@
+use std::fmt::{self, Display};
+use std::time::Duration;
+
+use rand::Rng;
+use reqwest::{RequestBuilder, Response, StatusCode};
+
+use crate::config::HttpConfig;
+
+/// Upper bound for a single backoff or `Retry-After` wait.
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
+
+/// A failure another backend may not have: connection errors, timeouts, 429
+/// and 5xx responses that persisted through the retries.
+#[derive(Debug)]
+pub struct TransientError(String);
+
+impl Display for TransientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for TransientError {}
+
+/// Whether `e` is worth trying again with a fallback backend.
+pub fn is_transient(e: &anyhow::Error) -> bool {
+    e.chain().any(|cause| {
+        cause.is::<TransientError>()
+            || cause
+                .downcast_ref::<reqwest::Error>()
+                .is_some_and(|e| e.is_timeout() || e.is_connect() || e.is_body())
+    })
+}
+
+/// Prefix `e` with `context` like `anyhow!("{context}: {e}")`, keeping it transient.
+pub fn wrap_error(e: anyhow::Error, context: impl Display) -> anyhow::Error {
+    if is_transient(&e) {
+        TransientError(format!("{context}: {e}")).into()
+    } else {
+        anyhow::anyhow!("{}: {}", context, e)
+    }
+}
+
+/// Error for an unsuccessful response, transient for 429 and 5xx.
+pub fn status_error(status: StatusCode, message: String) -> anyhow::Error {
+    if is_retryable_status(status) {
+        TransientError(message).into()
+    } else {
+        anyhow::anyhow!(message)
+    }
+}
+
+/// Async HTTP client shared by the providers, with timeouts and retry/backoff.
+#[derive(Clone)]
+pub struct HttpClient {
+    client: reqwest::Client,
+    max_retries: u32,
+    base_delay: Duration,
+}
+
+impl HttpClient {
+    pub fn new(config: &HttpConfig) -> anyhow::Result<Self> {
+        Self::with_read_timeout(config, config.read_timeout_secs)
+    }
+
+    /// Like [`HttpClient::new`] but with a different read timeout, e.g. for slow local models.
+    pub fn with_read_timeout(config: &HttpConfig, read_timeout_secs: u64) -> anyhow::Result<Self> {
+        let client = reqwest::Client::builder()
+            .connect_timeout(Duration::from_secs(config.connect_timeout_secs))
+            .read_timeout(Duration::from_secs(read_timeout_secs))
+            .build()
+            .map_err(|e| anyhow::anyhow!("Failed to create HTTP client: {}", e))?;
+        Ok(Self {
+            client,
+            max_retries: config.max_retries,
+            base_delay: Duration::from_millis(config.retry_base_delay_ms),
+        })
+    }
+
+    pub fn post(&self, url: &str) -> RequestBuilder {
+        self.client.post(url)
+    }
+
+    /// Send `request`, retrying connection errors, timeouts, 429 and 5xx responses.
+    ///
+    /// Non-retryable error responses are returned as-is for the provider to report.
+    pub async fn send(&self, request: RequestBuilder) -> anyhow::Result<Response> {
+        let mut attempt = 0;
+        loop {
+            let can_retry = attempt < self.max_retries;
+            // JSON bodies can always be cloned; anything else is sent only once.
+            let Some(current) = request.try_clone() else {
+                return request.send().await.map_err(describe_error);
+            };
+
+            let delay = match current.send().await {
+                Ok(response) if can_retry && is_retryable_status(response.status()) => {
+                    let delay = retry_after(&response).unwrap_or_else(|| self.backoff(attempt));
+                    log::warn!(
+                        "Request failed with {}, retrying in {:.1}s ({}/{})",
+                        response.status(),
+                        delay.as_secs_f32(),
+                        attempt + 1,
+                        self.max_retries
+                    );
+                    delay
+                }
+                Ok(response) => return Ok(response),
+                Err(e) if can_retry && (e.is_timeout() || e.is_connect()) => {
+                    let delay = self.backoff(attempt);
+                    log::warn!(
+                        "Request failed: {}, retrying in {:.1}s ({}/{})",
+                        e,
+                        delay.as_secs_f32(),
+                        attempt + 1,
+                        self.max_retries
+                    );
+                    delay
+                }
+                Err(e) => return Err(describe_error(e)),
+            };
+
+            tokio::time::sleep(delay.min(MAX_RETRY_DELAY)).await;
+            attempt += 1;
+        }
+    }
+
+    /// Exponential backoff with up to 25% jitter.
+    fn backoff(&self, attempt: u32) -> Duration {
+        let delay = self.base_delay.saturating_mul(2u32.saturating_pow(attempt));
+        let jitter = rand::thread_rng().gen_range(0.0..0.25);
+        delay.mul_f64(1.0 + jitter).min(MAX_RETRY_DELAY)
+    }
+}
+
+pub fn is_retryable_status(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+}
+
+/// Parse `Retry-After` as delay seconds or an HTTP date.
+fn retry_after(response: &Response) -> Option<Duration> {
+    let value = response
+        .headers()
+        .get(reqwest::header::RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim();
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+    let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    let secs = (date.timestamp() - chrono::Utc::now().timestamp()).max(0);
+    Some(Duration::from_secs(secs as u64))
+}
+
+fn describe_error(e: reqwest::Error) -> anyhow::Error {
+    if e.is_timeout() {
+        TransientError(format!("Request timed out: {e}")).into()
+    } else if e.is_connect() {
+        TransientError(format!("Failed to connect: {e}")).into()
+    } else {
+        anyhow::anyhow!("Request failed: {}", e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::mock_server::{MockResponse, MockServer};
+
+    fn config() -> HttpConfig {
+        HttpConfig {
+            retry_base_delay_ms: 1,
+            ..HttpConfig::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_retries_on_429_with_retry_after() {
+        let mut limited = MockResponse::new(429, "{}");
+        limited.headers.push(("retry-after".into(), "0".into()));
+        let server = MockServer::with_responses(vec![
+            limited,
+            MockResponse::new(503, "{}"),
+            MockResponse::new(200, "ok"),
+        ])
+        .await;
+        let client = HttpClient::new(&config()).unwrap();
+
+        let response = client.send(client.post(&server.url())).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(server.requests().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_gives_up_after_max_retries() {
+        let server = MockServer::start(500, "{}").await;
+        let client = HttpClient::new(&HttpConfig {
+            max_retries: 2,
+            ..config()
+        })
+        .unwrap();
+
+        let response = client.send(client.post(&server.url())).await.unwrap();
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(server.requests().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_client_errors_are_not_retried() {
+        let server = MockServer::start(400, "{}").await;
+        let client = HttpClient::new(&config()).unwrap();
+
+        let response = client.send(client.post(&server.url())).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(server.requests().len(), 1);
+    }
+}
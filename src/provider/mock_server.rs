@@ -0,0 +1,133 @@
+//! Minimal HTTP/1.1 server used to exercise providers in tests.
+
+use std::ops::Deref;
+use std::sync::{Arc, Mutex};
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+/// A canned response returned by [`MockServer`].
+#[derive(Debug, Clone)]
+pub struct MockResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+impl MockResponse {
+    pub fn new(status: u16, body: impl Into<String>) -> Self {
+        Self {
+            status,
+            headers: vec![("content-type".into(), "application/json".into())],
+            body: body.into(),
+        }
+    }
+}
+
+/// Raw request (head and body) received by the server.
+#[derive(Debug, Clone)]
+pub struct CapturedRequest(String);
+
+impl CapturedRequest {
+    pub fn body(&self) -> &str {
+        self.0.split_once("\r\n\r\n").map(|(_, b)| b).unwrap_or("")
+    }
+}
+
+impl Deref for CapturedRequest {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+pub struct MockServer {
+    addr: std::net::SocketAddr,
+    requests: Arc<Mutex<Vec<CapturedRequest>>>,
+}
+
+impl MockServer {
+    /// Serve the same response to every request.
+    pub async fn start(status: u16, body: &str) -> Self {
+        Self::with_responses(vec![MockResponse::new(status, body)]).await
+    }
+
+    /// Serve `responses` in order; the last one is repeated once exhausted.
+    pub async fn with_responses(responses: Vec<MockResponse>) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let captured = requests.clone();
+
+        tokio::spawn(async move {
+            let mut served = 0usize;
+            loop {
+                let Ok((mut stream, _)) = listener.accept().await else {
+                    break;
+                };
+                let raw = read_request(&mut stream).await;
+                captured.lock().unwrap().push(CapturedRequest(raw));
+
+                let response = &responses[served.min(responses.len() - 1)];
+                served += 1;
+                let mut head = format!("HTTP/1.1 {} Mock\r\n", response.status);
+                for (name, value) in &response.headers {
+                    head.push_str(&format!("{name}: {value}\r\n"));
+                }
+                head.push_str(&format!(
+                    "content-length: {}\r\nconnection: close\r\n\r\n",
+                    response.body.len()
+                ));
+                let _ = stream.write_all(head.as_bytes()).await;
+                let _ = stream.write_all(response.body.as_bytes()).await;
+                let _ = stream.shutdown().await;
+            }
+        });
+
+        Self { addr, requests }
+    }
+
+    /// Base URL with a trailing slash, as returned by `Config::api_base`.
+    pub fn url(&self) -> String {
+        format!("http://{}/", self.addr)
+    }
+
+    /// The first request received.
+    pub fn request(&self) -> CapturedRequest {
+        self.requests.lock().unwrap()[0].clone()
+    }
+
+    /// All requests received so far, in arrival order.
+    pub fn requests(&self) -> Vec<CapturedRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+async fn read_request(stream: &mut tokio::net::TcpStream) -> String {
+    let mut buf = Vec::new();
+    let mut chunk = [0u8; 4096];
+    loop {
+        let n = stream.read(&mut chunk).await.unwrap_or(0);
+        if n == 0 {
+            break;
+        }
+        buf.extend_from_slice(&chunk[..n]);
+
+        let text = String::from_utf8_lossy(&buf);
+        if let Some((head, body)) = text.split_once("\r\n\r\n") {
+            let content_length = head
+                .lines()
+                .find_map(|l| {
+                    let (name, value) = l.split_once(':')?;
+                    name.eq_ignore_ascii_case("content-length")
+                        .then(|| value.trim().parse::<usize>().ok())?
+                })
+                .unwrap_or(0);
+            if body.len() >= content_length {
+                break;
+            }
+        }
+    }
+    String::from_utf8_lossy(&buf).into_owned()
+}
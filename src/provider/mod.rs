@@ -0,0 +1,302 @@
+use std::fmt::Display;
+use std::future::Future;
+use std::pin::Pin;
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::{Config, ReasoningEffort};
+use crate::constants::{
+    DEFAULT_ANTHROPIC_API_BASE, DEFAULT_LLAMACPP_API_BASE, DEFAULT_OLLAMA_API_BASE,
+    DEFAULT_OPENAI_API_BASE,
+};
+
+mod anthropic;
+mod http;
+#[cfg(test)]
+pub(crate) mod mock_server;
+mod ollama;
+mod openai;
+mod sse;
+
+pub use anthropic::AnthropicProvider;
+pub use http::{is_transient, HttpClient};
+pub use ollama::OllamaProvider;
+pub use openai::OpenAiProvider;
+
+/// Boxed future returned by [`Provider`] methods so the trait stays object safe.
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// Callback receiving generated text fragments as they arrive.
+pub type DeltaSink<'a> = &'a mut (dyn FnMut(&str) + Send);
+
+/// Which backend is used to talk to the model.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ProviderKind {
+    /// OpenAI compatible `/chat/completions` endpoint.
+    #[serde(rename = "openai")]
+    #[default]
+    OpenAi,
+    /// Native Anthropic Messages API.
+    #[serde(rename = "anthropic")]
+    Anthropic,
+    /// Local Ollama server (`/api/chat`), no API key needed.
+    #[serde(rename = "ollama")]
+    Ollama,
+    /// Local llama.cpp `llama-server`, no API key needed.
+    #[serde(rename = "llamacpp")]
+    LlamaCpp,
+}
+
+impl ProviderKind {
+    /// API base used when `api_base` is not set in the config.
+    pub fn default_api_base(self) -> &'static str {
+        match self {
+            ProviderKind::OpenAi => DEFAULT_OPENAI_API_BASE,
+            ProviderKind::Anthropic => DEFAULT_ANTHROPIC_API_BASE,
+            ProviderKind::Ollama => DEFAULT_OLLAMA_API_BASE,
+            ProviderKind::LlamaCpp => DEFAULT_LLAMACPP_API_BASE,
+        }
+    }
+
+    /// Whether the backend is a model server on the user's machine.
+    pub fn is_local(self) -> bool {
+        matches!(self, ProviderKind::Ollama | ProviderKind::LlamaCpp)
+    }
+
+    /// Highest temperature the backend accepts, if it enforces one.
+    fn max_temperature(self) -> Option<f32> {
+        match self {
+            ProviderKind::OpenAi => Some(2.0),
+            ProviderKind::Anthropic => Some(1.0),
+            ProviderKind::Ollama | ProviderKind::LlamaCpp => None,
+        }
+    }
+
+    /// Most stop sequences the backend accepts, if it limits them.
+    fn max_stop_sequences(self) -> Option<usize> {
+        match self {
+            ProviderKind::OpenAi => Some(4),
+            _ => None,
+        }
+    }
+
+    /// Check `request` against the parameters this backend accepts.
+    ///
+    /// Out of range values are always an error. Parameters the backend doesn't
+    /// support at all are an error too, unless `omit_unsupported` is set, in which
+    /// case they are removed from the request.
+    pub fn check_params(
+        self,
+        request: &mut ChatRequest,
+        omit_unsupported: bool,
+    ) -> anyhow::Result<()> {
+        if let (Some(t), Some(max)) = (request.temperature, self.max_temperature()) {
+            if !(0.0..=max).contains(&t) {
+                return Err(anyhow::anyhow!(
+                    "temperature {} is out of range for provider '{}' (0 to {})",
+                    t,
+                    self,
+                    max
+                ));
+            }
+        }
+        if let Some(p) = request.top_p {
+            if !(0.0..=1.0).contains(&p) {
+                return Err(anyhow::anyhow!("top_p {} is out of range (0 to 1)", p));
+            }
+        }
+        if let Some(max) = self.max_stop_sequences() {
+            if request.stop.len() > max {
+                return Err(anyhow::anyhow!(
+                    "provider '{}' accepts at most {} stop sequences, got {}",
+                    self,
+                    max,
+                    request.stop.len()
+                ));
+            }
+        }
+
+        if request.reasoning_effort.is_none() {
+            return Ok(());
+        }
+        // OpenAI 的推理模型不接受采样参数，其它后端不支持 reasoning_effort
+        let unsupported: Vec<&str> = if self == ProviderKind::OpenAi {
+            [
+                ("temperature", request.temperature.is_some()),
+                ("top_p", request.top_p.is_some()),
+            ]
+            .into_iter()
+            .filter_map(|(name, set)| set.then_some(name))
+            .collect()
+        } else {
+            vec!["reasoning_effort"]
+        };
+        if unsupported.is_empty() {
+            return Ok(());
+        }
+        if !omit_unsupported {
+            return Err(anyhow::anyhow!(
+                "provider '{}' does not support {}{}; remove it or set generation.omit_unsupported = true",
+                self,
+                unsupported.join(" and "),
+                if self == ProviderKind::OpenAi {
+                    " together with reasoning_effort"
+                } else {
+                    ""
+                }
+            ));
+        }
+        log::debug!(
+            "Omitting {} not supported by provider '{}'",
+            unsupported.join(", "),
+            self
+        );
+        if self == ProviderKind::OpenAi {
+            request.temperature = None;
+            request.top_p = None;
+        } else {
+            request.reasoning_effort = None;
+        }
+        Ok(())
+    }
+}
+
+impl Display for ProviderKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ProviderKind::OpenAi => write!(f, "openai"),
+            ProviderKind::Anthropic => write!(f, "anthropic"),
+            ProviderKind::Ollama => write!(f, "ollama"),
+            ProviderKind::LlamaCpp => write!(f, "llamacpp"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChatRole {
+    System,
+    User,
+}
+
+#[derive(Debug, Clone)]
+pub struct ChatMessage {
+    pub role: ChatRole,
+    pub content: String,
+}
+
+impl ChatMessage {
+    pub fn system(content: impl Into<String>) -> Self {
+        Self {
+            role: ChatRole::System,
+            content: content.into(),
+        }
+    }
+
+    pub fn user(content: impl Into<String>) -> Self {
+        Self {
+            role: ChatRole::User,
+            content: content.into(),
+        }
+    }
+}
+
+/// A provider independent chat completion request.
+#[derive(Debug, Clone)]
+pub struct ChatRequest {
+    pub model: String,
+    pub messages: Vec<ChatMessage>,
+    pub max_tokens: u32,
+    pub temperature: Option<f32>,
+    pub top_p: Option<f32>,
+    /// Sequences that end generation, empty for none.
+    pub stop: Vec<String>,
+    pub reasoning_effort: Option<ReasoningEffort>,
+    /// Number of alternative replies, only sent to backends that [support it](Provider::supports_n).
+    pub n: Option<u32>,
+}
+
+/// Token accounting reported by the backend, if any.
+#[derive(Debug, Clone, Default)]
+pub struct Usage {
+    pub prompt_tokens: Option<u32>,
+    pub completion_tokens: Option<u32>,
+    pub total_tokens: Option<u32>,
+}
+
+/// The text produced by the model plus metadata about the call.
+#[derive(Debug, Clone)]
+pub struct ChatResponse {
+    pub content: String,
+    /// Replies beyond the first when more than one was requested with `n`.
+    pub alternatives: Vec<String>,
+    pub model: Option<String>,
+    pub usage: Option<Usage>,
+}
+
+/// A backend able to turn a chat request into generated text.
+pub trait Provider: Send + Sync {
+    /// Short name used in logs and diagnostics.
+    fn name(&self) -> &'static str;
+
+    /// Whether a single call honors [`ChatRequest::n`]; otherwise callers send parallel requests.
+    fn supports_n(&self) -> bool {
+        false
+    }
+
+    fn chat<'a>(&'a self, request: &'a ChatRequest) -> BoxFuture<'a, anyhow::Result<ChatResponse>>;
+
+    /// Like [`Provider::chat`], but feeds text to `on_delta` while it is generated.
+    ///
+    /// Backends without streaming support deliver the whole reply as a single delta.
+    fn chat_stream<'a>(
+        &'a self,
+        request: &'a ChatRequest,
+        on_delta: DeltaSink<'a>,
+    ) -> BoxFuture<'a, anyhow::Result<ChatResponse>> {
+        Box::pin(async move {
+            let response = self.chat(request).await?;
+            on_delta(&response.content);
+            Ok(response)
+        })
+    }
+}
+
+/// Build the provider selected by `config.provider`.
+pub fn from_config(config: &Config) -> anyhow::Result<Box<dyn Provider>> {
+    // OpenAI compatible endpoints other than the official one (e.g. a local vLLM) may run
+    // without auth, so only the hosted APIs insist on a key.
+    let requires_key = config.provider == ProviderKind::Anthropic
+        || (config.provider == ProviderKind::OpenAi
+            && config.api_base().trim_end_matches('/') == DEFAULT_OPENAI_API_BASE);
+    if requires_key && config.api_key.trim().is_empty() {
+        return Err(anyhow::anyhow!(
+            "api_key is not set for provider '{}'. Set it in ~/.fastcommit/config.toml \
+             or with FASTCOMMIT_API_KEY, or use provider = \"ollama\" / \"llamacpp\" for a local model",
+            config.provider
+        ));
+    }
+
+    let http = if config.provider.is_local() {
+        HttpClient::with_read_timeout(&config.http, config.local.timeout_secs)?
+    } else {
+        HttpClient::new(&config.http)?
+    };
+    let api_key = &config.api_key;
+    let api_base = &config.api_base();
+
+    let provider: Box<dyn Provider> = match config.provider {
+        ProviderKind::OpenAi => Box::new(OpenAiProvider::new("openai", http, api_key, api_base)),
+        ProviderKind::Anthropic => Box::new(AnthropicProvider::new(http, api_key, api_base)),
+        ProviderKind::Ollama => Box::new(OllamaProvider::new(
+            http,
+            api_key,
+            api_base,
+            config.local.auto_pull,
+        )),
+        // llama-server speaks the OpenAI chat completions protocol.
+        ProviderKind::LlamaCpp => {
+            Box::new(OpenAiProvider::new("llamacpp", http, api_key, api_base))
+        }
+    };
+    Ok(provider)
+}
@@ -0,0 +1,369 @@
+use serde::{Deserialize, Serialize};
+
+use super::http::{status_error, wrap_error, HttpClient};
+use super::sse::LineDecoder;
+use super::{BoxFuture, ChatRequest, ChatResponse, ChatRole, DeltaSink, Provider, Usage};
+
+/// Native Ollama `/api/chat` backend for locally hosted models.
+pub struct OllamaProvider {
+    http: HttpClient,
+    api_key: Option<String>,
+    api_base: String,
+    auto_pull: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct OllamaChatBody<'a> {
+    model: &'a str,
+    messages: Vec<OllamaMessage<'a>>,
+    stream: bool,
+    options: OllamaOptions<'a>,
+}
+
+#[derive(Debug, Serialize)]
+struct OllamaMessage<'a> {
+    role: &'static str,
+    content: &'a str,
+}
+
+#[derive(Debug, Serialize)]
+struct OllamaOptions<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    stop: &'a [String],
+    num_predict: u32,
+}
+
+#[derive(Debug, Deserialize)]
+struct OllamaChatResponse {
+    model: Option<String>,
+    message: Option<OllamaResponseMessage>,
+    prompt_eval_count: Option<u32>,
+    eval_count: Option<u32>,
+    /// Set instead of the other fields when generation fails mid-stream
+    error: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OllamaResponseMessage {
+    content: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct OllamaError {
+    error: String,
+}
+
+impl OllamaProvider {
+    pub fn new(http: HttpClient, api_key: &str, api_base: &str, auto_pull: bool) -> Self {
+        Self {
+            http,
+            api_key: (!api_key.is_empty()).then(|| api_key.to_owned()),
+            api_base: api_base.to_owned(),
+            auto_pull,
+        }
+    }
+
+    fn post(&self, path: &str) -> reqwest::RequestBuilder {
+        let builder = self.http.post(&format!("{}{}", self.api_base, path));
+        // Local servers normally run without auth, only send a key if one is configured.
+        match &self.api_key {
+            Some(key) => builder.bearer_auth(key),
+            None => builder,
+        }
+    }
+
+    fn build_body(request: &ChatRequest, stream: bool) -> OllamaChatBody<'_> {
+        OllamaChatBody {
+            model: &request.model,
+            messages: request
+                .messages
+                .iter()
+                .map(|m| OllamaMessage {
+                    role: match m.role {
+                        ChatRole::System => "system",
+                        ChatRole::User => "user",
+                    },
+                    content: &m.content,
+                })
+                .collect(),
+            stream,
+            options: OllamaOptions {
+                temperature: request.temperature,
+                top_p: request.top_p,
+                stop: &request.stop,
+                num_predict: request.max_tokens,
+            },
+        }
+    }
+
+    /// Send one `/api/chat` request, returning `Ok(None)` if the model is not pulled yet.
+    ///
+    /// With `on_delta` set the reply is streamed as newline delimited JSON.
+    async fn try_chat<'f>(
+        &self,
+        request: &ChatRequest,
+        mut on_delta: Option<&mut (dyn FnMut(&str) + Send + 'f)>,
+    ) -> anyhow::Result<Option<ChatResponse>> {
+        let builder = self
+            .post("api/chat")
+            .json(&Self::build_body(request, on_delta.is_some()));
+        let mut response =
+            self.http.send(builder).await.map_err(|e| {
+                wrap_error(e, format!("Failed to reach Ollama at {}", self.api_base))
+            })?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let text = response.text().await?;
+            let message = serde_json::from_str::<OllamaError>(&text)
+                .map(|e| e.error)
+                .unwrap_or(text);
+            if status == reqwest::StatusCode::NOT_FOUND && is_model_missing(&message) {
+                return Ok(None);
+            }
+            return Err(status_error(
+                status,
+                format!("Ollama error ({status}): {message}"),
+            ));
+        }
+
+        // Non streaming replies are a single object, streaming ones one object per line.
+        let mut decoder = LineDecoder::new();
+        let mut content = String::new();
+        let mut last = None;
+        while let Some(bytes) = response.chunk().await? {
+            for line in decoder.feed(&bytes).iter().filter(|l| !l.trim().is_empty()) {
+                last = Some(parse_line(line, &mut content, &mut on_delta)?);
+            }
+        }
+        for line in decoder.feed(b"\n").iter().filter(|l| !l.trim().is_empty()) {
+            last = Some(parse_line(line, &mut content, &mut on_delta)?);
+        }
+        let last: OllamaChatResponse = last.ok_or(anyhow::anyhow!("Empty response from Ollama"))?;
+
+        Ok(Some(ChatResponse {
+            content,
+            alternatives: Vec::new(),
+            model: last.model,
+            usage: Some(Usage {
+                prompt_tokens: last.prompt_eval_count,
+                completion_tokens: last.eval_count,
+                total_tokens: last
+                    .prompt_eval_count
+                    .zip(last.eval_count)
+                    .map(|(p, c)| p + c),
+            }),
+        }))
+    }
+
+    async fn chat_with_pull<'f>(
+        &self,
+        request: &ChatRequest,
+        mut on_delta: Option<&mut (dyn FnMut(&str) + Send + 'f)>,
+    ) -> anyhow::Result<ChatResponse> {
+        if let Some(response) = self.try_chat(request, on_delta.as_deref_mut()).await? {
+            return Ok(response);
+        }
+        if !self.auto_pull {
+            return Err(anyhow::anyhow!(
+                "Model '{}' is not available in Ollama. Run `ollama pull {}` or set `local.auto_pull = true`",
+                request.model,
+                request.model
+            ));
+        }
+        self.pull(&request.model).await?;
+        self.try_chat(request, on_delta)
+            .await?
+            .ok_or(anyhow::anyhow!(
+                "Model '{}' is still missing after pulling it",
+                request.model
+            ))
+    }
+
+    async fn pull(&self, model: &str) -> anyhow::Result<()> {
+        log::info!("Model '{model}' not found locally, pulling it via Ollama...");
+        let builder = self
+            .post("api/pull")
+            .json(&serde_json::json!({ "model": model, "stream": false }));
+        let response = self.http.send(builder).await?;
+        let status = response.status();
+        if !status.is_success() {
+            let text = response.text().await.unwrap_or_default();
+            return Err(anyhow::anyhow!(
+                "Failed to pull model '{}' ({}): {}",
+                model,
+                status,
+                text
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Parse one JSON line of a chat reply, appending its text to `content`.
+fn parse_line(
+    line: &str,
+    content: &mut String,
+    on_delta: &mut Option<&mut (dyn FnMut(&str) + Send + '_)>,
+) -> anyhow::Result<OllamaChatResponse> {
+    let parsed: OllamaChatResponse = serde_json::from_str(line)
+        .map_err(|e| anyhow::anyhow!("Failed to parse Ollama response: {}", e))?;
+    // 流式输出中途出错时 Ollama 仍返回 200，错误写在单独一行里
+    if let Some(error) = &parsed.error {
+        return Err(anyhow::anyhow!("Ollama failed while generating: {}", error));
+    }
+    if let Some(message) = &parsed.message {
+        if let Some(on_delta) = on_delta {
+            on_delta(&message.content);
+        }
+        content.push_str(&message.content);
+    }
+    Ok(parsed)
+}
+
+/// Ollama answers 404 with "model 'x' not found, try pulling it first".
+fn is_model_missing(message: &str) -> bool {
+    let message = message.to_lowercase();
+    message.contains("model") && message.contains("not found")
+}
+
+impl Provider for OllamaProvider {
+    fn name(&self) -> &'static str {
+        "ollama"
+    }
+
+    fn chat<'a>(&'a self, request: &'a ChatRequest) -> BoxFuture<'a, anyhow::Result<ChatResponse>> {
+        Box::pin(self.chat_with_pull(request, None))
+    }
+
+    fn chat_stream<'a>(
+        &'a self,
+        request: &'a ChatRequest,
+        on_delta: DeltaSink<'a>,
+    ) -> BoxFuture<'a, anyhow::Result<ChatResponse>> {
+        Box::pin(self.chat_with_pull(request, Some(on_delta)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::HttpConfig;
+    use crate::provider::mock_server::{MockResponse, MockServer};
+    use crate::provider::ChatMessage;
+
+    fn provider(url: &str, auto_pull: bool) -> OllamaProvider {
+        let http = HttpClient::new(&HttpConfig::default()).unwrap();
+        OllamaProvider::new(http, "", url, auto_pull)
+    }
+
+    fn request() -> ChatRequest {
+        ChatRequest {
+            model: "qwen2.5-coder".to_string(),
+            messages: vec![ChatMessage::system("sys"), ChatMessage::user("diff")],
+            max_tokens: 128,
+            temperature: Some(0.2),
+            top_p: Some(0.9),
+            stop: Vec::new(),
+            reasoning_effort: None,
+            n: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_chat_without_api_key() {
+        let server = MockServer::start(
+            200,
+            r#"{"model":"qwen2.5-coder","message":{"role":"assistant","content":"<aicommit>x</aicommit>"},"done":true,"prompt_eval_count":7,"eval_count":3}"#,
+        )
+        .await;
+        let provider = provider(&server.url(), false);
+
+        let response = provider.chat(&request()).await.unwrap();
+        assert_eq!(response.content, "<aicommit>x</aicommit>");
+        assert_eq!(response.usage.unwrap().total_tokens, Some(10));
+
+        let captured = server.request();
+        assert!(captured.starts_with("POST /api/chat "));
+        assert!(!captured.to_lowercase().contains("authorization:"));
+        let body: serde_json::Value = serde_json::from_str(captured.body()).unwrap();
+        assert_eq!(body["stream"], false);
+        assert_eq!(body["messages"][0]["role"], "system");
+        assert_eq!(body["options"]["num_predict"], 128);
+        assert_eq!(body["options"]["top_p"], 0.9f32);
+        assert!(body["options"].get("stop").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_missing_model_without_auto_pull() {
+        let server = MockServer::start(
+            404,
+            r#"{"error":"model 'qwen2.5-coder' not found, try pulling it first"}"#,
+        )
+        .await;
+        let provider = provider(&server.url(), false);
+
+        let err = provider.chat(&request()).await.unwrap_err().to_string();
+        assert!(err.contains("ollama pull qwen2.5-coder"));
+    }
+
+    #[tokio::test]
+    async fn test_missing_model_auto_pull() {
+        let server = MockServer::with_responses(vec![
+            MockResponse::new(404, r#"{"error":"model 'qwen2.5-coder' not found"}"#),
+            MockResponse::new(200, r#"{"status":"success"}"#),
+            MockResponse::new(
+                200,
+                r#"{"model":"qwen2.5-coder","message":{"role":"assistant","content":"ok"},"done":true}"#,
+            ),
+        ])
+        .await;
+        let provider = provider(&server.url(), true);
+
+        let response = provider.chat(&request()).await.unwrap();
+        assert_eq!(response.content, "ok");
+        assert!(server.requests()[1].starts_with("POST /api/pull "));
+    }
+
+    #[tokio::test]
+    async fn test_streamed_chat() {
+        let body = concat!(
+            "{\"model\":\"m\",\"message\":{\"role\":\"assistant\",\"content\":\"<aicommit>\"},\"done\":false}\n",
+            "{\"model\":\"m\",\"message\":{\"role\":\"assistant\",\"content\":\"fix</aicommit>\"},\"done\":false}\n",
+            "{\"model\":\"m\",\"message\":{\"role\":\"assistant\",\"content\":\"\"},\"done\":true,\"prompt_eval_count\":2,\"eval_count\":2}\n",
+        );
+        let server = MockServer::start(200, body).await;
+        let provider = provider(&server.url(), false);
+
+        let mut deltas = Vec::new();
+        let mut sink = |d: &str| deltas.push(d.to_string());
+        let response = provider.chat_stream(&request(), &mut sink).await.unwrap();
+        assert_eq!(response.content, "<aicommit>fix</aicommit>");
+        assert_eq!(response.usage.unwrap().total_tokens, Some(4));
+        assert_eq!(deltas, vec!["<aicommit>", "fix</aicommit>", ""]);
+    }
+
+    #[tokio::test]
+    async fn test_error_line_in_stream() {
+        let body = concat!(
+            "{\"model\":\"m\",\"message\":{\"role\":\"assistant\",\"content\":\"<aicommit>fix\"},\"done\":false}\n",
+            "{\"error\":\"out of memory\"}\n",
+        );
+        let server = MockServer::start(200, body).await;
+        let provider = provider(&server.url(), false);
+
+        let mut sink = |_: &str| {};
+        let err = provider
+            .chat_stream(&request(), &mut sink)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Ollama failed while generating: out of memory"
+        );
+    }
+}
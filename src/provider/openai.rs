@@ -0,0 +1,350 @@
+use serde::{Deserialize, Serialize};
+
+use super::http::{status_error, wrap_error, HttpClient};
+use super::sse::SseDecoder;
+use super::{BoxFuture, ChatRequest, ChatResponse, ChatRole, DeltaSink, Provider, Usage};
+use crate::config::ReasoningEffort;
+
+/// Backend for the OpenAI `/chat/completions` wire format.
+///
+/// Also used for llama.cpp's `llama-server`, which exposes the same API.
+pub struct OpenAiProvider {
+    name: &'static str,
+    http: HttpClient,
+    api_key: Option<String>,
+    api_base: String,
+}
+
+#[derive(Debug, Serialize)]
+struct ChatBody<'a> {
+    model: &'a str,
+    messages: Vec<ChatBodyMessage<'a>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    stop: &'a [String],
+    stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_tokens: Option<u32>,
+    /// Replaces `max_tokens` for reasoning models, where it also covers the reasoning.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_completion_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reasoning_effort: Option<ReasoningEffort>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    n: Option<u32>,
+}
+
+#[derive(Debug, Serialize)]
+struct ChatBodyMessage<'a> {
+    role: &'static str,
+    content: &'a str,
+}
+
+#[derive(Debug, Deserialize)]
+struct Completion {
+    model: Option<String>,
+    #[serde(default)]
+    choices: Vec<Choice>,
+    usage: Option<CompletionUsage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Choice {
+    message: Option<ChoiceMessage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChoiceMessage {
+    content: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct StreamChunk {
+    model: Option<String>,
+    #[serde(default)]
+    choices: Vec<StreamChoice>,
+    usage: Option<CompletionUsage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct StreamChoice {
+    delta: Option<StreamDelta>,
+}
+
+#[derive(Debug, Deserialize)]
+struct StreamDelta {
+    content: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CompletionUsage {
+    prompt_tokens: Option<u32>,
+    completion_tokens: Option<u32>,
+    total_tokens: Option<u32>,
+}
+
+impl From<CompletionUsage> for Usage {
+    fn from(u: CompletionUsage) -> Self {
+        Usage {
+            prompt_tokens: u.prompt_tokens,
+            completion_tokens: u.completion_tokens,
+            total_tokens: u.total_tokens,
+        }
+    }
+}
+
+impl OpenAiProvider {
+    /// An empty `api_key` sends no `Authorization` header, for servers running without auth.
+    pub fn new(name: &'static str, http: HttpClient, api_key: &str, api_base: &str) -> Self {
+        Self {
+            name,
+            http,
+            api_key: (!api_key.is_empty()).then(|| api_key.to_owned()),
+            api_base: api_base.to_owned(),
+        }
+    }
+
+    async fn send(&self, request: &ChatRequest, stream: bool) -> anyhow::Result<reqwest::Response> {
+        let body = ChatBody {
+            model: &request.model,
+            messages: request
+                .messages
+                .iter()
+                .map(|m| ChatBodyMessage {
+                    role: match m.role {
+                        ChatRole::System => "system",
+                        ChatRole::User => "user",
+                    },
+                    content: &m.content,
+                })
+                .collect(),
+            temperature: request.temperature,
+            top_p: request.top_p,
+            stop: &request.stop,
+            stream,
+            max_tokens: request
+                .reasoning_effort
+                .is_none()
+                .then_some(request.max_tokens),
+            max_completion_tokens: request
+                .reasoning_effort
+                .is_some()
+                .then_some(request.max_tokens),
+            reasoning_effort: request.reasoning_effort,
+            // llama-server ignores `n`, so it is only forwarded where it is honored
+            n: request.n.filter(|_| self.supports_n()),
+        };
+
+        let mut builder = self
+            .http
+            .post(&format!("{}chat/completions", self.api_base))
+            .json(&body);
+        if let Some(key) = &self.api_key {
+            builder = builder.bearer_auth(key);
+        }
+        let response = self
+            .http
+            .send(builder)
+            .await
+            .map_err(|e| wrap_error(e, "Failed to create chat completion"))?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let text = response.text().await.unwrap_or_default();
+            return Err(status_error(
+                status,
+                format!("Failed to create chat completion ({status}): {text}"),
+            ));
+        }
+        Ok(response)
+    }
+}
+
+impl Provider for OpenAiProvider {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn supports_n(&self) -> bool {
+        self.name == "openai"
+    }
+
+    fn chat<'a>(&'a self, request: &'a ChatRequest) -> BoxFuture<'a, anyhow::Result<ChatResponse>> {
+        Box::pin(async move {
+            let text = self.send(request, false).await?.text().await?;
+            let completion: Completion = serde_json::from_str(&text)
+                .map_err(|e| anyhow::anyhow!("Failed to parse chat completion: {}", e))?;
+            if completion.choices.is_empty() {
+                return Err(anyhow::anyhow!("No choices in response"));
+            }
+            let mut contents = completion
+                .choices
+                .into_iter()
+                .filter_map(|c| c.message.and_then(|m| m.content));
+            let content = contents
+                .next()
+                .ok_or(anyhow::anyhow!("No message in response"))?;
+
+            Ok(ChatResponse {
+                content,
+                alternatives: contents.collect(),
+                model: completion.model,
+                usage: completion.usage.map(Usage::from),
+            })
+        })
+    }
+
+    fn chat_stream<'a>(
+        &'a self,
+        request: &'a ChatRequest,
+        on_delta: DeltaSink<'a>,
+    ) -> BoxFuture<'a, anyhow::Result<ChatResponse>> {
+        Box::pin(async move {
+            let mut response = self.send(request, true).await?;
+            let mut decoder = SseDecoder::new();
+            let mut content = String::new();
+            let mut model = None;
+            let mut usage = None;
+
+            'read: while let Some(bytes) = response.chunk().await? {
+                for event in decoder.feed(&bytes) {
+                    if event.data.trim() == "[DONE]" {
+                        break 'read;
+                    }
+                    let chunk: StreamChunk = serde_json::from_str(&event.data)
+                        .map_err(|e| anyhow::anyhow!("Failed to parse stream chunk: {}", e))?;
+                    model = model.or(chunk.model);
+                    usage = chunk.usage.map(Usage::from).or(usage);
+                    for delta in chunk.choices.into_iter().filter_map(|c| c.delta?.content) {
+                        on_delta(&delta);
+                        content.push_str(&delta);
+                    }
+                }
+            }
+
+            Ok(ChatResponse {
+                content,
+                alternatives: Vec::new(),
+                model,
+                usage,
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::HttpConfig;
+    use crate::provider::mock_server::{MockResponse, MockServer};
+    use crate::provider::ChatMessage;
+
+    fn provider(api_key: &str, url: &str) -> OpenAiProvider {
+        let http = HttpClient::new(&HttpConfig::default()).unwrap();
+        OpenAiProvider::new("openai", http, api_key, url)
+    }
+
+    fn request() -> ChatRequest {
+        ChatRequest {
+            model: "m".to_string(),
+            messages: vec![ChatMessage::system("sys"), ChatMessage::user("diff")],
+            max_tokens: 64,
+            temperature: Some(0.3),
+            top_p: None,
+            stop: Vec::new(),
+            reasoning_effort: None,
+            n: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_chat_completion() {
+        let server = MockServer::start(
+            200,
+            r#"{"model":"m","choices":[{"message":{"role":"assistant","content":"<aicommit>x</aicommit>"}}],"usage":{"prompt_tokens":3,"completion_tokens":2,"total_tokens":5}}"#,
+        )
+        .await;
+
+        let response = provider("sk-test", &server.url())
+            .chat(&request())
+            .await
+            .unwrap();
+        assert_eq!(response.content, "<aicommit>x</aicommit>");
+        assert_eq!(response.usage.unwrap().total_tokens, Some(5));
+
+        let captured = server.request();
+        assert!(captured.starts_with("POST /chat/completions "));
+        assert!(captured.contains("authorization: Bearer sk-test"));
+    }
+
+    #[tokio::test]
+    async fn test_reasoning_request_uses_max_completion_tokens() {
+        let server = MockServer::start(200, r#"{"choices":[{"message":{"content":"a"}}]}"#).await;
+
+        let request = ChatRequest {
+            temperature: None,
+            reasoning_effort: Some(ReasoningEffort::High),
+            stop: vec!["\n\n".into()],
+            ..request()
+        };
+        provider("sk-test", &server.url())
+            .chat(&request)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_str(server.request().body()).unwrap();
+        assert_eq!(body["max_completion_tokens"], 64);
+        assert_eq!(body["reasoning_effort"], "high");
+        assert_eq!(body["stop"][0], "\n\n");
+        assert!(body.get("max_tokens").is_none());
+        assert!(body.get("temperature").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_chat_completion_with_n_choices() {
+        let server = MockServer::start(
+            200,
+            r#"{"choices":[{"message":{"content":"a"}},{"message":{"content":"b"}}]}"#,
+        )
+        .await;
+
+        let request = ChatRequest {
+            n: Some(2),
+            ..request()
+        };
+        let response = provider("sk-test", &server.url())
+            .chat(&request)
+            .await
+            .unwrap();
+        assert_eq!(response.content, "a");
+        assert_eq!(response.alternatives, vec!["b"]);
+        assert!(server.request().body().contains(r#""n":2"#));
+    }
+
+    #[tokio::test]
+    async fn test_stream_chat_completions_without_key() {
+        let body = concat!(
+            "data: {\"model\":\"m\",\"choices\":[{\"delta\":{\"role\":\"assistant\"}}]}\n\n",
+            "data: {\"choices\":[{\"delta\":{\"content\":\"<aicommit>fix\"}}]}\n\n",
+            "data: {\"choices\":[{\"delta\":{\"content\":\": x</aicommit>\"}}]}\n\n",
+            "data: [DONE]\n\n",
+        );
+        let mut response = MockResponse::new(200, body);
+        response.headers = vec![("content-type".into(), "text/event-stream".into())];
+        let server = MockServer::with_responses(vec![response]).await;
+
+        let mut deltas = Vec::new();
+        let mut sink = |d: &str| deltas.push(d.to_string());
+        let result = provider("", &server.url())
+            .chat_stream(&request(), &mut sink)
+            .await
+            .unwrap();
+
+        assert_eq!(result.content, "<aicommit>fix: x</aicommit>");
+        assert_eq!(result.model.as_deref(), Some("m"));
+        assert_eq!(deltas, vec!["<aicommit>fix", ": x</aicommit>"]);
+        assert!(!server.request().to_lowercase().contains("authorization:"));
+    }
+}
@@ -0,0 +1,102 @@
+//! Incremental decoders for streamed HTTP responses.
+
+/// One server-sent event.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SseEvent {
+    pub event: Option<String>,
+    pub data: String,
+}
+
+/// Splits a `text/event-stream` body into events, tolerating chunks cut anywhere.
+#[derive(Debug, Default)]
+pub struct SseDecoder {
+    lines: LineDecoder,
+    event: Option<String>,
+    data: Vec<String>,
+}
+
+impl SseDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn feed(&mut self, bytes: &[u8]) -> Vec<SseEvent> {
+        let mut events = Vec::new();
+        for line in self.lines.feed(bytes) {
+            if line.is_empty() {
+                // A blank line dispatches the pending event.
+                if !self.data.is_empty() {
+                    events.push(SseEvent {
+                        event: self.event.take(),
+                        data: self.data.join("\n"),
+                    });
+                    self.data.clear();
+                }
+                self.event = None;
+            } else if let Some(value) = line.strip_prefix("data:") {
+                self.data
+                    .push(value.strip_prefix(' ').unwrap_or(value).to_string());
+            } else if let Some(value) = line.strip_prefix("event:") {
+                self.event = Some(value.trim().to_string());
+            }
+            // Comments (":") and other fields (id, retry) are ignored.
+        }
+        events
+    }
+}
+
+/// Splits a byte stream into complete UTF-8 lines (used directly for NDJSON).
+#[derive(Debug, Default)]
+pub struct LineDecoder {
+    buf: Vec<u8>,
+}
+
+impl LineDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn feed(&mut self, bytes: &[u8]) -> Vec<String> {
+        self.buf.extend_from_slice(bytes);
+        let mut lines = Vec::new();
+        while let Some(pos) = self.buf.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = self.buf.drain(..=pos).collect();
+            let line = String::from_utf8_lossy(&line);
+            lines.push(line.trim_end_matches(['\n', '\r']).to_string());
+        }
+        lines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_events_split_across_chunks() {
+        let mut decoder = SseDecoder::new();
+        assert!(decoder.feed(b"event: delta\r\ndata: {\"a\":").is_empty());
+        let events = decoder.feed(b"1}\r\n\r\ndata: [DONE]\n\n");
+        assert_eq!(
+            events,
+            vec![
+                SseEvent {
+                    event: Some("delta".into()),
+                    data: "{\"a\":1}".into()
+                },
+                SseEvent {
+                    event: None,
+                    data: "[DONE]".into()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_multibyte_char_split_across_chunks() {
+        let mut decoder = LineDecoder::new();
+        let bytes = "修复\n".as_bytes();
+        assert!(decoder.feed(&bytes[..2]).is_empty());
+        assert_eq!(decoder.feed(&bytes[2..]), vec!["修复".to_string()]);
+    }
+}
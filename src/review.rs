@@ -0,0 +1,226 @@
+//! Interactive review of a generated commit message before it is committed.
+
+use std::io::{IsTerminal, Write};
+use std::path::Path;
+use std::process::Command;
+
+use crate::cli::CommitArgs;
+use crate::config::{CommitLanguage, Config, Verbosity};
+use crate::generate;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Action {
+    Accept,
+    Regenerate(Option<String>),
+    Edit,
+    SwitchLanguage,
+    SwitchVerbosity,
+    Abort,
+}
+
+/// The review loop only runs when there is a user to answer it.
+pub fn is_interactive() -> bool {
+    std::io::stdout().is_terminal()
+}
+
+/// Ask the user what to do with `message` until it is accepted or aborted.
+///
+/// Returns the accepted text, or `None` if the user aborted. `show` prints a
+/// (re)generated message. Language/verbosity switches are kept in `config`.
+pub async fn review_commit_message(
+    mut message: String,
+    args: &CommitArgs,
+    config: &mut Config,
+    show: impl Fn(&str),
+) -> anyhow::Result<Option<String>> {
+    loop {
+        eprint!(
+            "\n[a]ccept  [r]egenerate (r <hint>)  [e]dit  [l]anguage ({})  [v]erbosity ({})  [q]uit: ",
+            config.language,
+            verbosity_name(config.verbosity)
+        );
+        // EOF: nobody can answer, don't commit
+        let Some(line) = read_line().await? else {
+            return Ok(None);
+        };
+        let Some(action) = parse_action(&line) else {
+            eprintln!("Unknown choice: {}", line.trim());
+            continue;
+        };
+
+        let hint = match action {
+            Action::Accept => return Ok(Some(message)),
+            Action::Abort => return Ok(None),
+            Action::Edit => {
+                match edit_message(&message)? {
+                    Some(edited) => message = edited,
+                    None => eprintln!("Edited message is empty, keeping the previous one."),
+                }
+                show(&message);
+                continue;
+            }
+            Action::Regenerate(hint) => hint,
+            Action::SwitchLanguage => {
+                config.language = match config.language {
+                    CommitLanguage::Chinese => CommitLanguage::English,
+                    CommitLanguage::English => CommitLanguage::Chinese,
+                };
+                None
+            }
+            Action::SwitchVerbosity => {
+                config.verbosity = match config.verbosity {
+                    Verbosity::Quiet => Verbosity::Normal,
+                    Verbosity::Normal => Verbosity::Verbose,
+                    Verbosity::Verbose => Verbosity::Quiet,
+                };
+                None
+            }
+        };
+
+        eprintln!("Regenerating...");
+        let description = combine_hint(args.common.prompt.as_deref(), hint.as_deref());
+        message = generate::regenerate(args, config, description.as_deref()).await?;
+        show(&message);
+    }
+}
+
+/// Show numbered `candidates` and return the one the user picks, `None` on quit.
+pub async fn pick_candidate(
+    candidates: &[String],
+    show: impl Fn(&str),
+) -> anyhow::Result<Option<String>> {
+    for (i, candidate) in candidates.iter().enumerate() {
+        eprintln!("\x1b[1m[{}]\x1b[0m", i + 1);
+        show(candidate);
+    }
+    loop {
+        eprint!("\nPick a message [1-{}] or [q]uit: ", candidates.len());
+        let Some(line) = read_line().await? else {
+            return Ok(None);
+        };
+        match line.trim() {
+            "q" | "quit" => return Ok(None),
+            choice => match choice.parse::<usize>() {
+                Ok(i) if (1..=candidates.len()).contains(&i) => {
+                    return Ok(Some(candidates[i - 1].clone()))
+                }
+                _ => eprintln!("Unknown choice: {choice}"),
+            },
+        }
+    }
+}
+
+/// Read one line from stdin, `None` at EOF.
+async fn read_line() -> anyhow::Result<Option<String>> {
+    let _ = std::io::stderr().flush();
+    // stdin 为阻塞读取，放到单独线程中以便 Ctrl-C 仍能取消
+    let (n, line) = tokio::task::spawn_blocking(|| {
+        let mut line = String::new();
+        std::io::stdin().read_line(&mut line).map(|n| (n, line))
+    })
+    .await??;
+    Ok((n > 0).then_some(line))
+}
+
+fn parse_action(input: &str) -> Option<Action> {
+    let input = input.trim();
+    let (choice, rest) = input
+        .split_once(char::is_whitespace)
+        .map(|(c, r)| (c, r.trim()))
+        .unwrap_or((input, ""));
+    let action = match choice.to_lowercase().as_str() {
+        // 直接回车视为接受
+        "" | "a" | "accept" | "y" | "yes" => Action::Accept,
+        "r" | "regenerate" => Action::Regenerate((!rest.is_empty()).then(|| rest.to_string())),
+        "e" | "edit" => Action::Edit,
+        "l" | "language" => Action::SwitchLanguage,
+        "v" | "verbosity" => Action::SwitchVerbosity,
+        "q" | "quit" | "abort" | "n" | "no" => Action::Abort,
+        _ => return None,
+    };
+    Some(action)
+}
+
+/// Append a regeneration hint to the user's original description.
+fn combine_hint(description: Option<&str>, hint: Option<&str>) -> Option<String> {
+    match (description, hint) {
+        (Some(d), Some(h)) => Some(format!("{d}\n{h}")),
+        (Some(d), None) => Some(d.to_string()),
+        (None, Some(h)) => Some(h.to_string()),
+        (None, None) => None,
+    }
+}
+
+fn verbosity_name(verbosity: Verbosity) -> &'static str {
+    match verbosity {
+        Verbosity::Verbose => "verbose",
+        Verbosity::Normal => "normal",
+        Verbosity::Quiet => "quiet",
+    }
+}
+
+/// Open `message` in `$VISUAL`/`$EDITOR`; `None` if the result is empty.
+fn edit_message(message: &str) -> anyhow::Result<Option<String>> {
+    let path = std::env::temp_dir().join(format!("fastcommit-{}.txt", std::process::id()));
+    std::fs::write(&path, message)?;
+    let status = open_editor(&path);
+    let edited = std::fs::read_to_string(&path);
+    let _ = std::fs::remove_file(&path);
+
+    status?;
+    let edited = edited?.trim().to_string();
+    Ok((!edited.is_empty()).then_some(edited))
+}
+
+/// Open `path` in `$VISUAL`/`$EDITOR` (falling back to `vi`) and wait for it to exit.
+pub fn open_editor(path: &Path) -> anyhow::Result<()> {
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".to_string());
+
+    // 通过 shell 执行，以支持 "code --wait" 这类带参数的编辑器
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(format!("{editor} \"$1\""))
+        .arg("sh")
+        .arg(path)
+        .status()
+        .map_err(|e| anyhow::anyhow!("Failed to run editor '{}': {}", editor, e))?;
+    if !status.success() {
+        return Err(anyhow::anyhow!(
+            "Editor '{}' exited with {}",
+            editor,
+            status
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_action() {
+        assert_eq!(parse_action("\n"), Some(Action::Accept));
+        assert_eq!(parse_action("A\n"), Some(Action::Accept));
+        assert_eq!(parse_action("r"), Some(Action::Regenerate(None)));
+        assert_eq!(
+            parse_action("r  mention the config change \n"),
+            Some(Action::Regenerate(Some("mention the config change".into())))
+        );
+        assert_eq!(parse_action("e"), Some(Action::Edit));
+        assert_eq!(parse_action("l"), Some(Action::SwitchLanguage));
+        assert_eq!(parse_action("v"), Some(Action::SwitchVerbosity));
+        assert_eq!(parse_action("q"), Some(Action::Abort));
+        assert_eq!(parse_action("x"), None);
+    }
+
+    #[test]
+    fn test_combine_hint() {
+        assert_eq!(combine_hint(None, None), None);
+        assert_eq!(combine_hint(Some("a"), None).as_deref(), Some("a"));
+        assert_eq!(combine_hint(None, Some("b")).as_deref(), Some("b"));
+        assert_eq!(combine_hint(Some("a"), Some("b")).as_deref(), Some("a\nb"));
+    }
+}
@@ -131,7 +131,11 @@ fn compile_custom_patterns(
                 regex: re,
             }),
             Err(e) => {
-                log::warn!("Skip invalid custom sanitize regex '{}': {}", item.regex, e);
+                log::warn!(
+                    "Skip invalid custom sanitize regex '{}': {} (run `fastcommit config validate`)",
+                    item.regex,
+                    e
+                );
             }
         }
     }
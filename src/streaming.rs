@@ -0,0 +1,180 @@
+use std::io::{IsTerminal, Write};
+
+use indicatif::ProgressBar;
+use unicode_width::UnicodeWidthStr;
+
+const AICOMMIT_START: &str = "<aicommit>";
+const AICOMMIT_END: &str = "</aicommit>";
+const THINK_START: &str = "<think>";
+const THINK_END: &str = "</think>";
+
+/// Incrementally extracts the text inside `<aicommit>` from a streamed reply.
+///
+/// `<think>` blocks are skipped, and a partially received closing tag is held
+/// back until it is known not to be one.
+#[derive(Debug, Default)]
+pub struct AicommitStreamFilter {
+    raw: String,
+    emitted: usize,
+}
+
+impl AicommitStreamFilter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feed the next fragment and return the newly visible commit text.
+    pub fn push(&mut self, delta: &str) -> String {
+        self.raw.push_str(delta);
+        let visible = visible_commit_text(&self.raw);
+        match visible.get(self.emitted..) {
+            Some(new) if !new.is_empty() => {
+                self.emitted = visible.len();
+                new.to_string()
+            }
+            _ => String::new(),
+        }
+    }
+}
+
+fn visible_commit_text(raw: &str) -> String {
+    // 删除已闭合的思考内容，未闭合的思考内容之后的文本暂不可见
+    let mut text = String::new();
+    let mut rest = raw;
+    while let Some(start) = rest.find(THINK_START) {
+        text.push_str(&rest[..start]);
+        match rest[start..].find(THINK_END) {
+            Some(end) => rest = &rest[start + end + THINK_END.len()..],
+            None => {
+                rest = "";
+                break;
+            }
+        }
+    }
+    text.push_str(rest);
+
+    let Some(start) = text.find(AICOMMIT_START) else {
+        return String::new();
+    };
+    let content = text[start + AICOMMIT_START.len()..].trim_start();
+    if let Some(end) = content.find(AICOMMIT_END) {
+        return content[..end].trim_end().to_string();
+    }
+
+    // Hold back a trailing prefix of the closing tag, e.g. "</aic".
+    let held = (1..AICOMMIT_END.len())
+        .rev()
+        .find(|&n| content.ends_with(&AICOMMIT_END[..n]))
+        .unwrap_or(0);
+    content[..content.len() - held].to_string()
+}
+
+/// Prints the commit message to stderr while it is generated.
+///
+/// The preview is erased again on `finish` when stderr is a terminal, so the
+/// wrapped final message can be printed in its place.
+pub struct StreamPrinter {
+    filter: AicommitStreamFilter,
+    spinner: ProgressBar,
+    printed: String,
+    /// The last preview is still on screen because stderr isn't a terminal
+    left_on_screen: bool,
+}
+
+impl StreamPrinter {
+    pub fn new(spinner: ProgressBar) -> Self {
+        Self {
+            filter: AicommitStreamFilter::new(),
+            spinner,
+            printed: String::new(),
+            left_on_screen: false,
+        }
+    }
+
+    pub fn on_delta(&mut self, delta: &str) {
+        let text = self.filter.push(delta);
+        if text.is_empty() {
+            return;
+        }
+        self.left_on_screen = false;
+        if self.printed.is_empty() {
+            self.spinner.finish_and_clear();
+        }
+        let mut stderr = std::io::stderr();
+        let _ = write!(stderr, "{text}");
+        let _ = stderr.flush();
+        self.printed.push_str(&text);
+    }
+
+    /// End the preview, clearing it from the terminal if possible.
+    ///
+    /// The printer can then be reused for another reply, e.g. from a fallback backend.
+    pub fn finish(&mut self) {
+        self.filter = AicommitStreamFilter::new();
+        if self.printed.is_empty() {
+            return;
+        }
+        let mut stderr = std::io::stderr();
+        if stderr.is_terminal() {
+            let columns = terminal_size::terminal_size()
+                .map(|(w, _)| w.0 as usize)
+                .unwrap_or(80)
+                .max(1);
+            let rows: usize = self
+                .printed
+                .split('\n')
+                .map(|line| line.width().div_ceil(columns).max(1))
+                .sum();
+            if rows > 1 {
+                let _ = write!(stderr, "\x1b[{}A", rows - 1);
+            }
+            let _ = write!(stderr, "\r\x1b[J");
+        } else {
+            let _ = writeln!(stderr);
+            self.left_on_screen = true;
+        }
+        let _ = stderr.flush();
+        self.printed.clear();
+    }
+
+    /// Mark the last preview as discarded before the printer is reused for
+    /// another reply. It was already erased on a terminal; elsewhere `note`
+    /// is printed so the two replies aren't read as one.
+    pub fn discard(&mut self, note: &str) {
+        if std::mem::take(&mut self.left_on_screen) {
+            eprintln!("{note}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn feed(chunks: &[&str]) -> Vec<String> {
+        let mut filter = AicommitStreamFilter::new();
+        chunks.iter().map(|c| filter.push(c)).collect()
+    }
+
+    #[test]
+    fn test_only_aicommit_content_is_visible() {
+        let out = feed(&[
+            "Sure!\n<aic",
+            "ommit>\nfeat: add",
+            " x\n</aicom",
+            "mit> bye",
+        ]);
+        assert_eq!(out, vec!["", "feat: add", " x\n", ""]);
+    }
+
+    #[test]
+    fn test_think_block_is_hidden() {
+        let out = feed(&[
+            "<think>maybe <aicommit>wrong</aicommit>",
+            "</think><aicommit>fix: y",
+            "</aicommit>",
+        ]);
+        assert_eq!(out.concat(), "fix: y");
+        assert_eq!(out[0], "");
+    }
+}
@@ -0,0 +1,258 @@
+//! Map-reduce summarization for diffs that don't fit into the model context.
+
+use std::sync::Arc;
+
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+
+use crate::config::Config;
+use crate::constants::DIFF_CHUNK_SUMMARY_PROMPT;
+use crate::generate::request_aicommit;
+use crate::template_engine::{render_template, Template, TemplateContext, TemplateKind};
+
+/// How many times summaries are summarized again before giving up.
+const MAX_REDUCE_ROUNDS: usize = 3;
+
+/// Rough token estimate: ~4 ASCII characters per token, one token per other character.
+pub fn estimate_tokens(text: &str) -> usize {
+    let (ascii, other) = text.chars().fold((0usize, 0usize), |(a, o), c| {
+        if c.is_ascii() {
+            (a + 1, o)
+        } else {
+            (a, o + 1)
+        }
+    });
+    ascii.div_ceil(4) + other
+}
+
+/// Return `diff` unchanged if it fits `config.summarize.max_diff_tokens`, otherwise
+/// a concatenation of per-chunk summaries produced by the model.
+///
+/// `diff` must already be sanitized, the chunks are sent to the provider as-is.
+pub async fn fit_diff_to_budget(diff: &str, config: &Config) -> anyhow::Result<String> {
+    let settings = &config.summarize;
+    if !settings.enabled || estimate_tokens(diff) <= settings.max_diff_tokens {
+        return Ok(diff.to_string());
+    }
+
+    let mut text = diff.to_string();
+    for round in 1..=MAX_REDUCE_ROUNDS {
+        let chunks = split_diff(&text, settings.chunk_tokens);
+        log::info!(
+            "Diff has ~{} tokens, summarizing {} chunks (round {})",
+            estimate_tokens(&text),
+            chunks.len(),
+            round
+        );
+        let summaries = summarize_chunks(chunks, config).await?;
+        text = summaries
+            .iter()
+            .enumerate()
+            .map(|(i, s)| format!("### 变更摘要 {}\n\n{}", i + 1, s.trim()))
+            .collect::<Vec<_>>()
+            .join("\n\n");
+        if estimate_tokens(&text) <= settings.max_diff_tokens {
+            break;
+        }
+    }
+
+    // 多轮总结后仍然超出预算，只能截断
+    if estimate_tokens(&text) > settings.max_diff_tokens {
+        log::warn!(
+            "Diff summary still has ~{} tokens after {} rounds, truncating it to the {} token budget",
+            estimate_tokens(&text),
+            MAX_REDUCE_ROUNDS,
+            settings.max_diff_tokens
+        );
+        text = truncate_to_tokens(&text, settings.max_diff_tokens);
+    }
+
+    Ok(format!(
+        "（原始 diff 过大，以下为分块总结后的变更摘要）\n\n{text}"
+    ))
+}
+
+async fn summarize_chunks(chunks: Vec<String>, config: &Config) -> anyhow::Result<Vec<String>> {
+    let config = Arc::new(config.clone());
+    let semaphore = Arc::new(Semaphore::new(config.summarize.concurrency.max(1)));
+    let mut tasks = JoinSet::new();
+
+    for (index, chunk) in chunks.into_iter().enumerate() {
+        let config = config.clone();
+        let semaphore = semaphore.clone();
+        tasks.spawn(async move {
+            let _permit = semaphore.acquire_owned().await?;
+            let ctx = TemplateContext::from_config(&config, &chunk, None);
+            let prompt = render_template(
+                &Template::builtin(TemplateKind::Commit, DIFF_CHUNK_SUMMARY_PROMPT),
+                ctx,
+            )?;
+            let (summary, _) = request_aicommit(
+                &config,
+                "你是一个代码版本控制专家，擅长总结代码变更。",
+                prompt,
+                0.2,
+                None,
+            )
+            .await?;
+            anyhow::Ok((index, summary))
+        });
+    }
+
+    let mut summaries = Vec::new();
+    while let Some(result) = tasks.join_next().await {
+        summaries.push(result??);
+    }
+    summaries.sort_by_key(|(index, _)| *index);
+    Ok(summaries.into_iter().map(|(_, s)| s).collect())
+}
+
+/// Split a unified diff into chunks of at most `chunk_tokens`, cutting at file
+/// boundaries first, then at hunks (repeating the file header), then at lines.
+pub fn split_diff(diff: &str, chunk_tokens: usize) -> Vec<String> {
+    let chunk_tokens = chunk_tokens.max(1);
+    let mut pieces = Vec::new();
+    for file in split_before(diff, |l| l.starts_with("diff --git ")) {
+        if estimate_tokens(&file) <= chunk_tokens {
+            pieces.push(file);
+            continue;
+        }
+        let mut hunks = split_before(&file, |l| l.starts_with("@@"));
+        let header = if hunks.len() > 1 && !hunks[0].starts_with("@@") {
+            hunks.remove(0)
+        } else {
+            String::new()
+        };
+        for hunk in hunks {
+            if estimate_tokens(&header) + estimate_tokens(&hunk) <= chunk_tokens {
+                pieces.push(format!("{header}{hunk}"));
+            } else {
+                let budget = chunk_tokens.saturating_sub(estimate_tokens(&header)).max(1);
+                for part in split_lines(&hunk, budget) {
+                    pieces.push(format!("{header}{part}"));
+                }
+            }
+        }
+    }
+
+    // Pack small neighbouring pieces together
+    let mut chunks: Vec<String> = Vec::new();
+    for piece in pieces {
+        match chunks.last_mut() {
+            Some(last) if estimate_tokens(last) + estimate_tokens(&piece) <= chunk_tokens => {
+                last.push_str(&piece)
+            }
+            _ => chunks.push(piece),
+        }
+    }
+    chunks
+}
+
+/// Split `text` into pieces starting at every line matching `is_start`.
+pub(crate) fn split_before(text: &str, is_start: impl Fn(&str) -> bool) -> Vec<String> {
+    let mut pieces: Vec<String> = Vec::new();
+    for line in text.split_inclusive('\n') {
+        match pieces.last_mut() {
+            Some(last) if !is_start(line) => last.push_str(line),
+            _ => pieces.push(line.to_string()),
+        }
+    }
+    pieces
+}
+
+/// The longest prefix of `text` within `budget` tokens, cut at a line boundary
+/// when possible, with a marker noting that the rest was left out.
+fn truncate_to_tokens(text: &str, budget: usize) -> String {
+    const MARKER: &str = "\n（摘要过长，其余部分已截断）";
+    let budget = budget.saturating_sub(estimate_tokens(MARKER));
+    let mut kept = String::new();
+    for line in text.split_inclusive('\n') {
+        if estimate_tokens(&kept) + estimate_tokens(line) > budget {
+            if kept.is_empty() {
+                kept = line.chars().take(budget).collect();
+            }
+            break;
+        }
+        kept.push_str(line);
+    }
+    format!("{}{MARKER}", kept.trim_end())
+}
+
+fn split_lines(text: &str, budget: usize) -> Vec<String> {
+    let mut parts: Vec<String> = Vec::new();
+    let mut current = String::new();
+    for line in text.split_inclusive('\n') {
+        if !current.is_empty() && estimate_tokens(&current) + estimate_tokens(line) > budget {
+            parts.push(std::mem::take(&mut current));
+        }
+        current.push_str(line);
+    }
+    if !current.is_empty() {
+        parts.push(current);
+    }
+    parts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file_diff(name: &str, hunks: usize, lines_per_hunk: usize) -> String {
+        let mut s = format!("diff --git a/{name} b/{name}\n--- a/{name}\n+++ b/{name}\n");
+        for h in 0..hunks {
+            s.push_str(&format!("@@ -{h},3 +{h},3 @@\n"));
+            for l in 0..lines_per_hunk {
+                s.push_str(&format!("+line {l} of hunk {h} in {name}\n"));
+            }
+        }
+        s
+    }
+
+    #[test]
+    fn test_estimate_tokens() {
+        assert_eq!(estimate_tokens("abcdefgh"), 2);
+        assert_eq!(estimate_tokens("修复"), 2);
+    }
+
+    #[test]
+    fn test_truncate_to_tokens() {
+        let text = (0..100)
+            .map(|i| format!("summary line {i}"))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let truncated = truncate_to_tokens(&text, 50);
+        assert!(estimate_tokens(&truncated) <= 50);
+        assert!(truncated.starts_with("summary line 0\n"));
+        assert!(truncated.ends_with("其余部分已截断）"));
+        assert!(estimate_tokens(&truncate_to_tokens(&"x".repeat(1000), 20)) <= 20);
+    }
+
+    #[test]
+    fn test_small_files_are_packed_together() {
+        let diff = format!("{}{}", file_diff("a.rs", 1, 2), file_diff("b.rs", 1, 2));
+        let chunks = split_diff(&diff, 1000);
+        assert_eq!(chunks, vec![diff]);
+    }
+
+    #[test]
+    fn test_large_file_is_split_by_hunk_with_header() {
+        let diff = file_diff("big.rs", 3, 20);
+        let chunks = split_diff(&diff, 200);
+        assert!(chunks.len() >= 3);
+        for chunk in &chunks {
+            assert!(chunk.starts_with("diff --git a/big.rs b/big.rs\n"));
+            assert!(estimate_tokens(chunk) <= 200);
+        }
+        // Every changed line survives the split exactly once
+        let joined = chunks.concat();
+        assert_eq!(joined.matches("+line 19 of hunk 2").count(), 1);
+    }
+
+    #[test]
+    fn test_huge_hunk_is_split_by_lines() {
+        let diff = file_diff("huge.rs", 1, 200);
+        let chunks = split_diff(&diff, 300);
+        assert!(chunks.len() > 1);
+        assert!(chunks.iter().all(|c| estimate_tokens(c) <= 300));
+    }
+}
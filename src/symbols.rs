@@ -0,0 +1,351 @@
+//! The functions, impls and classes enclosing each hunk, found in the content
+//! of the changed file after the change.
+
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use lazy_static::lazy_static;
+use regex::Regex;
+
+use crate::git_context::git;
+use crate::patch::{line_kind, Hunk};
+
+/// Longest definition line put in a hunk header, in characters.
+const MAX_SYMBOL_CHARS: usize = 80;
+
+/// Diffs touching more files than this get no symbols or file content, as
+/// reading them all would delay the first request.
+const MAX_CONTENT_FILES: usize = 500;
+
+lazy_static! {
+    static ref DEFINITION: Regex = Regex::new(concat!(
+        // Rust
+        r#"^(?:pub(?:\([^)]*\))?\s+)?(?:(?:async|const|unsafe|default|extern\s+"[^"]*")\s+)*(?:fn|impl|struct|enum|trait|mod|union|macro_rules!)[\s<(]"#,
+        // JavaScript, TypeScript
+        r"|^(?:export\s+)?(?:default\s+)?(?:abstract\s+)?(?:async\s+)?(?:function\*?|class|interface|namespace)\s",
+        r"|^(?:export\s+)?(?:const|let|var)\s+\w+\s*=\s*(?:async\s+)?(?:function|\([^)]*\)\s*=>|\w+\s*=>)",
+        // Java, C#, Kotlin
+        r"|^(?:(?:public|private|protected|internal|static|final|abstract|sealed|partial|data|open)\s+)*(?:class|interface|enum|record|struct|object)\s",
+        // Python, Ruby
+        r"|^(?:async\s+)?(?:def|class|module)\s",
+        // Go
+        r"|^func\s|^type\s+\w+\s+(?:struct|interface)",
+        // C, C++, Java, C#: a signature with a parameter list and no semicolon
+        r"|^(?:[\w:<>,*&\[\]]+\s+)+\**[\w:~]+\s*\([^;]*$",
+    ))
+    .unwrap();
+    static ref STATEMENT: Regex = Regex::new(
+        r"^(?:if|else|for|while|switch|match|return|do|try|catch|case|loop|new|throw|await|yield|delete)\b"
+    )
+    .unwrap();
+}
+
+fn is_definition(line: &str) -> bool {
+    DEFINITION.is_match(line) && !STATEMENT.is_match(line)
+}
+
+/// The definitions enclosing line `index` of `lines`, outermost first. Walks up
+/// through less and less indented lines, so it works for any brace or
+/// indentation based language that is reasonably formatted.
+pub fn enclosing_symbols(lines: &[String], index: usize) -> Vec<String> {
+    let indent = |line: &str| line.len() - line.trim_start().len();
+    let mut limit = lines
+        .get(index..)
+        .and_then(|rest| rest.iter().find(|l| !l.trim().is_empty()))
+        .map_or(usize::MAX, |l| indent(l));
+
+    let mut symbols = Vec::new();
+    for line in lines[..index.min(lines.len())].iter().rev() {
+        let trimmed = line.trim();
+        // 空行、注释和闭合括号不代表进入了外层作用域
+        if trimmed.is_empty()
+            || trimmed.starts_with(['{', '}', ')', ']', '#', '*'])
+            || trimmed.starts_with("//")
+            || trimmed.starts_with("/*")
+        {
+            continue;
+        }
+        let depth = indent(line);
+        if depth >= limit {
+            continue;
+        }
+        limit = depth;
+        if is_definition(trimmed) {
+            symbols.push(shorten(trimmed));
+        }
+        if depth == 0 {
+            break;
+        }
+    }
+    symbols.reverse();
+    symbols
+}
+
+fn shorten(definition: &str) -> String {
+    let definition = definition.trim_end_matches(['{', '(', ':', ' ']);
+    if definition.chars().count() <= MAX_SYMBOL_CHARS {
+        return definition.to_string();
+    }
+    let mut short: String = definition.chars().take(MAX_SYMBOL_CHARS - 1).collect();
+    short.push('…');
+    short
+}
+
+/// Name the definitions enclosing the first change of each hunk in its header,
+/// e.g. `@@ -10,4 +10,6 @@ impl Hunk > fn header(&self) -> String`.
+pub fn annotate_hunks(hunks: &mut [Hunk], content: &[String]) {
+    for hunk in hunks {
+        let mut line = hunk.new_start;
+        let mut target = None;
+        for hunk_line in &hunk.lines {
+            match line_kind(hunk_line) {
+                '+' | '-' => {
+                    target = Some(line - 1);
+                    break;
+                }
+                ' ' => line += 1,
+                _ => {}
+            }
+        }
+        let Some(target) = target else {
+            continue;
+        };
+        let symbols = enclosing_symbols(content, target);
+        if !symbols.is_empty() {
+            hunk.section = format!(" {}", symbols.join(" > "));
+        }
+    }
+}
+
+/// Whether `content` has the new side of every hunk at the hunk's line numbers.
+pub fn matches_hunks(content: &[String], hunks: &[Hunk]) -> bool {
+    hunks.iter().all(|hunk| {
+        hunk.lines
+            .iter()
+            .filter(|l| matches!(line_kind(l), ' ' | '+'))
+            .enumerate()
+            .all(|(i, line)| {
+                content
+                    .get(hunk.new_start - 1 + i)
+                    .is_some_and(|c| c.trim_end() == line.get(1..).unwrap_or("").trim_end())
+            })
+    })
+}
+
+/// Content of the changed files in the index and `HEAD`, read with one
+/// `git cat-file --batch`, plus the work tree, read on demand.
+pub struct FileContents {
+    root: Option<PathBuf>,
+    index: HashMap<String, String>,
+    head: HashMap<String, String>,
+}
+
+impl FileContents {
+    /// Load `paths` from the index and `HEAD`. Diffs touching more than
+    /// [`MAX_CONTENT_FILES`] files are left without content.
+    pub fn load(paths: &[&str]) -> Self {
+        let mut contents = Self {
+            root: None,
+            index: HashMap::new(),
+            head: HashMap::new(),
+        };
+        if paths.is_empty() {
+            return contents;
+        }
+        if paths.len() > MAX_CONTENT_FILES {
+            log::info!(
+                "{} changed files, not reading their content for symbols",
+                paths.len()
+            );
+            return contents;
+        }
+        let Some(root) = git(&["rev-parse", "--show-toplevel"]).map(PathBuf::from) else {
+            return contents;
+        };
+        // 路径含换行时无法按行传给 cat-file
+        let paths: Vec<&str> = paths
+            .iter()
+            .copied()
+            .filter(|p| !p.contains('\n'))
+            .collect();
+        let objects: Vec<String> = paths
+            .iter()
+            .flat_map(|p| [format!(":{p}"), format!("HEAD:{p}")])
+            .collect();
+        match cat_file_batch(&root, &objects) {
+            Ok(blobs) => {
+                for (path, pair) in paths.iter().zip(blobs.chunks(2)) {
+                    if let Some(blob) = pair.first().cloned().flatten() {
+                        contents.index.insert(path.to_string(), blob);
+                    }
+                    if let Some(blob) = pair.get(1).cloned().flatten() {
+                        contents.head.insert(path.to_string(), blob);
+                    }
+                }
+            }
+            Err(e) => log::debug!("git cat-file failed: {}", e),
+        }
+        contents.root = Some(root);
+        contents
+    }
+
+    /// The content of `path` that the hunks were made against: the index for
+    /// staged changes, the work tree for unstaged ones, or `HEAD` for the last
+    /// commit. `None` outside a repository or when none of them matches, e.g.
+    /// for a `--diff-file` of another revision.
+    pub fn matching(&self, path: &str, hunks: &[Hunk]) -> Option<Vec<String>> {
+        let root = self.root.as_ref()?;
+        let matching = |text: &str| {
+            let content: Vec<String> = text.lines().map(str::to_string).collect();
+            matches_hunks(&content, hunks).then_some(content)
+        };
+        self.index
+            .get(path)
+            .and_then(|text| matching(text))
+            .or_else(|| {
+                let text = std::fs::read_to_string(root.join(path)).ok()?;
+                matching(&text)
+            })
+            .or_else(|| self.head.get(path).and_then(|text| matching(text)))
+    }
+}
+
+/// Read `objects` with `git cat-file --batch`, `None` for missing ones.
+fn cat_file_batch(root: &Path, objects: &[String]) -> anyhow::Result<Vec<Option<String>>> {
+    let mut child = Command::new("git")
+        .arg("-C")
+        .arg(root)
+        .args(["cat-file", "--batch"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    let input: String = objects.iter().map(|o| format!("{o}\n")).collect();
+    // 在另一个线程写入，避免输出填满管道时双方互相等待
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    let writer = std::thread::spawn(move || stdin.write_all(input.as_bytes()));
+    let output = child.wait_with_output()?;
+    writer
+        .join()
+        .map_err(|_| anyhow::anyhow!("writing to git cat-file panicked"))??;
+    if !output.status.success() {
+        return Err(anyhow::anyhow!(
+            "{}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    parse_batch(&output.stdout, objects.len())
+}
+
+/// Parse `count` entries of `git cat-file --batch` output: `<oid> <type>
+/// <size>` followed by the content and a newline, or `<object> missing`.
+fn parse_batch(mut output: &[u8], count: usize) -> anyhow::Result<Vec<Option<String>>> {
+    let mut blobs = Vec::with_capacity(count);
+    for _ in 0..count {
+        let end = output
+            .iter()
+            .position(|&b| b == b'\n')
+            .ok_or_else(|| anyhow::anyhow!("truncated cat-file output"))?;
+        let header = String::from_utf8_lossy(&output[..end]);
+        output = &output[end + 1..];
+        let fields: Vec<&str> = header.split(' ').collect();
+        match fields.as_slice() {
+            [_, kind, size] => {
+                let size: usize = size.parse()?;
+                let content = output
+                    .get(..size)
+                    .ok_or_else(|| anyhow::anyhow!("truncated cat-file output"))?;
+                blobs
+                    .push((*kind == "blob").then(|| String::from_utf8_lossy(content).into_owned()));
+                output = output.get(size + 1..).unwrap_or_default();
+            }
+            _ => blobs.push(None),
+        }
+    }
+    Ok(blobs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(text: &str) -> Vec<String> {
+        text.lines().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn test_enclosing_symbols() {
+        let rust = lines(
+            "impl Parser {
+    /// Parse one item.
+    pub(crate) fn parse_item(&mut self) -> Result<Item> {
+        match self.peek() {
+            Token::Fn => {
+                self.parse_fn()
+            }
+        }
+    }
+}",
+        );
+        assert_eq!(
+            enclosing_symbols(&rust, 5),
+            vec![
+                "impl Parser",
+                "pub(crate) fn parse_item(&mut self) -> Result<Item>"
+            ]
+        );
+        assert_eq!(enclosing_symbols(&rust, 2), vec!["impl Parser"]);
+        assert!(enclosing_symbols(&rust, 0).is_empty());
+
+        let python = lines(
+            "class Cache:
+    def get(self, key):
+        # look up
+        if key in self.items:
+
+            return self.items[key]",
+        );
+        assert_eq!(
+            enclosing_symbols(&python, 5),
+            vec!["class Cache", "def get(self, key)"]
+        );
+
+        let c = lines("static int run(int argc)\n{\n    if (argc) {\n        return 1;\n    }\n}");
+        assert_eq!(enclosing_symbols(&c, 3), vec!["static int run(int argc)"]);
+        let java = lines("public class App {\n    public void run() {\n        start();\n    }\n}");
+        assert_eq!(
+            enclosing_symbols(&java, 2),
+            vec!["public class App", "public void run()"]
+        );
+    }
+
+    #[test]
+    fn test_annotate_matching_content() {
+        let content = lines("fn main() {\n    let a = 1;\n    let b = 2;\n    run(a, b);\n}");
+        let mut hunks = vec![Hunk {
+            old_start: 2,
+            old_len: 2,
+            new_start: 2,
+            new_len: 2,
+            section: " fn main() {".to_string(),
+            lines: lines("     let a = 1;\n-    let b = 3;\n+    let b = 2;"),
+        }];
+        assert!(matches_hunks(&content, &hunks));
+        assert!(!matches_hunks(&content[1..], &hunks));
+
+        annotate_hunks(&mut hunks, &content);
+        assert_eq!(hunks[0].section, " fn main()");
+    }
+
+    #[test]
+    fn test_parse_batch() {
+        let output = b"1111 blob 12\nfn main() {}\n:gone missing\n2222 blob 0\n\n";
+        assert_eq!(
+            parse_batch(output, 3).unwrap(),
+            vec![Some("fn main() {}".to_string()), None, Some(String::new())]
+        );
+        assert!(parse_batch(b"1111 blob 20\nshort\n", 1).is_err());
+    }
+}
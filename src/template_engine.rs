@@ -1,56 +1,1188 @@
+//! A small template language for prompts.
+//!
+//! - `{{name}}`, `{{this.field}}`: variables, with filters `{{diff | truncate: 2000}}`
+//! - `{{#if name}}…{{else}}…{{/if}}`, `{{#unless name}}…{{/unless}}`
+//! - `{{#each list}}…{{@index}} {{this}}…{{else}}…{{/each}}`
+//! - `{{! comment }}` and `\{{` for a literal `{{`
+//!
+//! Block tags alone on their line don't leave an empty line behind. Values are
+//! inserted as-is and never parsed again, so a diff containing `{{diff}}` is safe.
+
+use std::collections::BTreeMap;
+use std::fmt::{self, Display};
+use std::path::Path;
+
 use crate::{
-    config::{CommitLanguage, Verbosity},
+    config::{CommitLanguage, Config, Task, Verbosity},
     constants::PromptTemplateReplaceLabel,
+    lint::Diagnostic,
 };
 
-pub struct TemplateContext<'a> {
-    pub conventional: bool,
-    pub language: CommitLanguage,
-    pub verbosity: Verbosity,
-    pub diff_content: &'a str,
-    pub user_description: Option<&'a str>,
+/// A value that can be inserted into a template.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Str(String),
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    List(Vec<Value>),
+    Map(BTreeMap<String, Value>),
+}
+
+impl Value {
+    fn is_truthy(&self) -> bool {
+        match self {
+            Value::Str(s) => !s.is_empty(),
+            Value::Bool(b) => *b,
+            Value::Int(i) => *i != 0,
+            Value::Float(f) => *f != 0.0,
+            Value::List(items) => !items.is_empty(),
+            Value::Map(map) => !map.is_empty(),
+        }
+    }
+
+    fn render(&self) -> Result<String, String> {
+        match self {
+            Value::Str(s) => Ok(s.clone()),
+            Value::Bool(b) => Ok(b.to_string()),
+            Value::Int(i) => Ok(i.to_string()),
+            Value::Float(f) => Ok(f.to_string()),
+            Value::List(items) => Ok(items
+                .iter()
+                .map(Value::render)
+                .collect::<Result<Vec<_>, _>>()?
+                .join(", ")),
+            Value::Map(_) => Err("is a table, use one of its fields".to_string()),
+        }
+    }
+}
+
+impl From<&str> for Value {
+    fn from(s: &str) -> Self {
+        Value::Str(s.to_string())
+    }
+}
+
+impl From<String> for Value {
+    fn from(s: String) -> Self {
+        Value::Str(s)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(b: bool) -> Self {
+        Value::Bool(b)
+    }
+}
+
+impl<T: Into<Value>> From<Vec<T>> for Value {
+    fn from(items: Vec<T>) -> Self {
+        Value::List(items.into_iter().map(Into::into).collect())
+    }
+}
+
+impl From<&toml::Value> for Value {
+    fn from(value: &toml::Value) -> Self {
+        match value {
+            toml::Value::String(s) => Value::Str(s.clone()),
+            toml::Value::Integer(i) => Value::Int(*i),
+            toml::Value::Float(f) => Value::Float(*f),
+            toml::Value::Boolean(b) => Value::Bool(*b),
+            toml::Value::Datetime(d) => Value::Str(d.to_string()),
+            toml::Value::Array(items) => Value::List(items.iter().map(Into::into).collect()),
+            toml::Value::Table(table) => {
+                Value::Map(table.iter().map(|(k, v)| (k.clone(), v.into())).collect())
+            }
+        }
+    }
+}
+
+/// Variables available to a template.
+pub struct TemplateContext {
+    vars: BTreeMap<String, Value>,
 }
 
-impl<'a> TemplateContext<'a> {
+impl TemplateContext {
     pub fn new(
         conventional: bool,
         language: CommitLanguage,
         verbosity: Verbosity,
-        diff_content: &'a str,
-        user_description: Option<&'a str>,
+        diff_content: &str,
+        user_description: Option<&str>,
     ) -> Self {
-        Self {
-            conventional,
-            language,
-            verbosity,
+        let mut vars = BTreeMap::new();
+        for label in PromptTemplateReplaceLabel::ALL {
+            let value = match label {
+                PromptTemplateReplaceLabel::ConventionalCommit => conventional.into(),
+                PromptTemplateReplaceLabel::Language => language.to_string().into(),
+                PromptTemplateReplaceLabel::VerbosityLevel => verbosity.as_template_level().into(),
+                PromptTemplateReplaceLabel::Diff => diff_content.into(),
+                PromptTemplateReplaceLabel::UserDescription => {
+                    user_description.unwrap_or("").into()
+                }
+                // 由 GitContext 按 [context] 配置填充，默认为空
+                PromptTemplateReplaceLabel::Branch
+                | PromptTemplateReplaceLabel::Stat
+                | PromptTemplateReplaceLabel::Repo
+                | PromptTemplateReplaceLabel::Style => "".into(),
+                PromptTemplateReplaceLabel::Files
+                | PromptTemplateReplaceLabel::RecentCommits
+                | PromptTemplateReplaceLabel::StyleExamples => Value::List(Vec::new()),
+            };
+            vars.insert(label.name().to_string(), value);
+        }
+        // 便于在条件中书写 {{#if conventional}}
+        vars.insert("conventional".to_string(), conventional.into());
+        Self { vars }
+    }
+
+    /// The built-in variables for `config`, plus its `[template_vars]`.
+    pub fn from_config(
+        config: &Config,
+        diff_content: &str,
+        user_description: Option<&str>,
+    ) -> Self {
+        let mut context = Self::new(
+            config.conventional,
+            config.language,
+            config.verbosity,
             diff_content,
             user_description,
+        );
+        for (name, value) in &config.template_vars {
+            if context.vars.contains_key(name) {
+                log::warn!("Ignoring template_vars.{name}: it is a built-in template variable");
+                continue;
+            }
+            context.vars.insert(name.clone(), value.into());
+        }
+        context
+    }
+
+    pub fn insert(&mut self, name: &str, value: impl Into<Value>) {
+        self.vars.insert(name.to_string(), value.into());
+    }
+}
+
+/// Whether `name` is set by fastcommit rather than by `[template_vars]`.
+pub fn is_builtin_variable(name: &str) -> bool {
+    let context = TemplateContext::new(
+        false,
+        CommitLanguage::default(),
+        Verbosity::default(),
+        "",
+        None,
+    );
+    context.vars.contains_key(name)
+}
+
+/// What a template is used for, deciding which variables it must use.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TemplateKind {
+    Commit,
+    Branch,
+    Pr,
+    System,
+}
+
+impl TemplateKind {
+    fn required(self) -> &'static [PromptTemplateReplaceLabel] {
+        match self {
+            TemplateKind::System => &[],
+            _ => &[PromptTemplateReplaceLabel::Diff],
+        }
+    }
+}
+
+impl From<Task> for TemplateKind {
+    fn from(task: Task) -> Self {
+        match task {
+            Task::Commit => TemplateKind::Commit,
+            Task::Branch => TemplateKind::Branch,
+            Task::Pr => TemplateKind::Pr,
+        }
+    }
+}
+
+impl Display for TemplateKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TemplateKind::Commit => write!(f, "commit"),
+            TemplateKind::Branch => write!(f, "branch"),
+            TemplateKind::Pr => write!(f, "pr"),
+            TemplateKind::System => write!(f, "system"),
+        }
+    }
+}
+
+/// A prompt template and where it came from, for error messages.
+pub struct Template {
+    pub name: String,
+    pub text: String,
+    pub kind: TemplateKind,
+}
+
+impl Template {
+    pub fn builtin(kind: TemplateKind, text: &str) -> Self {
+        Self {
+            name: format!("<built-in {kind} template>"),
+            text: text.to_string(),
+            kind,
+        }
+    }
+
+    pub fn load(kind: TemplateKind, path: &Path) -> anyhow::Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("Failed to read template {}: {}", path.display(), e))?;
+        Ok(Self {
+            name: path.display().to_string(),
+            text,
+            kind,
+        })
+    }
+
+    /// The template configured for `kind` in `[templates]`, or `builtin`.
+    ///
+    /// The PR template falls back to the commit template.
+    pub fn from_config(config: &Config, kind: TemplateKind, builtin: &str) -> anyhow::Result<Self> {
+        let templates = &config.templates;
+        let path = match kind {
+            TemplateKind::Commit => templates.commit.as_ref(),
+            TemplateKind::Branch => templates.branch.as_ref(),
+            TemplateKind::Pr => templates.pr.as_ref().or(templates.commit.as_ref()),
+            TemplateKind::System => templates.system.as_ref(),
+        };
+        match path {
+            Some(path) => Self::load(kind, path),
+            None => Ok(Self::builtin(kind, builtin)),
+        }
+    }
+
+    /// Parse the template, reporting syntax errors, unknown variables and
+    /// filters, and required variables that are never used.
+    fn compile(&self, context: &TemplateContext) -> Result<Vec<Node>, Vec<Diagnostic>> {
+        let (nodes, mut errors) = parse(&self.text);
+        let mut used = Vec::new();
+        check_nodes(&nodes, context, 0, &mut used, &mut errors);
+        let missing: Vec<String> = self
+            .kind
+            .required()
+            .iter()
+            .map(|label| label.name())
+            .filter(|name| !used.contains(name))
+            .map(|name| format!("{{{{{name}}}}}"))
+            .collect();
+        if !missing.is_empty() {
+            errors.push(Error::new(
+                0,
+                format!(
+                    "required placeholder {} is missing from the {} template",
+                    missing.join(", "),
+                    self.kind
+                ),
+            ));
+        }
+        if errors.is_empty() {
+            return Ok(nodes);
+        }
+        let mut diagnostics: Vec<Diagnostic> = errors
+            .into_iter()
+            .map(|e| e.to_diagnostic(&self.text))
+            .collect();
+        diagnostics.sort_by_key(|d| (d.line, d.column));
+        Err(diagnostics)
+    }
+}
+
+/// Validate `template` against `context`, then render it.
+pub fn render_template(template: &Template, context: TemplateContext) -> anyhow::Result<String> {
+    let report = |diagnostics: Vec<Diagnostic>| {
+        let problems: Vec<String> = diagnostics
+            .iter()
+            .map(|d| format!("{}:{}", template.name, d))
+            .collect();
+        anyhow::anyhow!("Invalid prompt template:\n  {}", problems.join("\n  "))
+    };
+    let nodes = template.compile(&context).map_err(report)?;
+    let mut renderer = Renderer {
+        context: &context,
+        loops: Vec::new(),
+        out: String::with_capacity(template.text.len()),
+    };
+    renderer
+        .render(&nodes)
+        .map_err(|e| report(vec![e.to_diagnostic(&template.text)]))?;
+    Ok(renderer.out)
+}
+
+/// An error at a byte offset of the template text.
+struct Error {
+    offset: usize,
+    message: String,
+}
+
+impl Error {
+    fn new(offset: usize, message: impl Into<String>) -> Self {
+        Self {
+            offset,
+            message: message.into(),
+        }
+    }
+
+    fn to_diagnostic(&self, text: &str) -> Diagnostic {
+        let before = &text[..self.offset.min(text.len())];
+        let line = before.matches('\n').count() + 1;
+        let column = before.rsplit('\n').next().unwrap_or("").chars().count() + 1;
+        Diagnostic::new(line, column, self.message.clone())
+    }
+}
+
+#[derive(Debug)]
+enum Node {
+    Text(String),
+    Expr(Expr),
+    If {
+        negate: bool,
+        cond: Expr,
+        then: Vec<Node>,
+        otherwise: Vec<Node>,
+    },
+    Each {
+        list: Expr,
+        body: Vec<Node>,
+        otherwise: Vec<Node>,
+    },
+}
+
+#[derive(Debug)]
+struct Expr {
+    path: Vec<String>,
+    filters: Vec<Filter>,
+    offset: usize,
+}
+
+impl Expr {
+    fn display_path(&self) -> String {
+        self.path.join(".")
+    }
+}
+
+#[derive(Debug)]
+struct Filter {
+    name: String,
+    arg: Option<Value>,
+}
+
+/// Names of the filters, and the argument each takes.
+const FILTERS: [(&str, Arg); 10] = [
+    ("upper", Arg::None),
+    ("lower", Arg::None),
+    ("trim", Arg::None),
+    ("first_line", Arg::None),
+    ("lines", Arg::None),
+    ("length", Arg::None),
+    ("join", Arg::OptionalStr),
+    ("default", Arg::Any),
+    ("truncate", Arg::Int),
+    ("indent", Arg::Int),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Arg {
+    None,
+    OptionalStr,
+    Any,
+    Int,
+}
+
+enum Token {
+    Text(String),
+    Tag { content: String, offset: usize },
+}
+
+impl Token {
+    fn is_block_tag(&self) -> bool {
+        match self {
+            Token::Tag { content, .. } => content.starts_with(['#', '/', '!']) || content == "else",
+            Token::Text(_) => false,
+        }
+    }
+
+    fn text(&self) -> &str {
+        match self {
+            Token::Text(t) => t,
+            Token::Tag { .. } => "",
         }
     }
 }
 
-pub fn render_template(template: &str, context: TemplateContext) -> anyhow::Result<String> {
-    let rendered = template
-        .replace(
-            PromptTemplateReplaceLabel::ConventionalCommit.get_label(),
-            &context.conventional.to_string(),
-        )
-        .replace(
-            PromptTemplateReplaceLabel::Language.get_label(),
-            &context.language.to_string(),
-        )
-        .replace(
-            PromptTemplateReplaceLabel::VerbosityLevel.get_label(),
-            context.verbosity.as_template_level(),
-        )
-        .replace(
-            PromptTemplateReplaceLabel::Diff.get_label(),
-            context.diff_content,
-        )
-        .replace(
-            PromptTemplateReplaceLabel::UserDescription.get_label(),
-            context.user_description.unwrap_or(""),
-        );
-
-    Ok(rendered)
+/// Split `text` into text and tags, alternating and starting and ending with text.
+fn tokenize(text: &str, errors: &mut Vec<Error>) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut buf = String::new();
+    let mut pos = 0;
+    while let Some(found) = text[pos..].find("{{") {
+        let start = pos + found;
+        if text[..start].ends_with('\\') {
+            buf.push_str(&text[pos..start - 1]);
+            buf.push_str("{{");
+            pos = start + 2;
+            continue;
+        }
+        buf.push_str(&text[pos..start]);
+        let line_end = text[start..].find('\n').map_or(text.len(), |i| start + i);
+        match text[start + 2..line_end].find("}}") {
+            Some(end) => {
+                tokens.push(Token::Text(std::mem::take(&mut buf)));
+                tokens.push(Token::Tag {
+                    content: text[start + 2..start + 2 + end].trim().to_string(),
+                    offset: start,
+                });
+                pos = start + 2 + end + 2;
+            }
+            None => {
+                errors.push(Error::new(
+                    start,
+                    "'{{' is not closed by '}}' on the same line",
+                ));
+                buf.push_str("{{");
+                pos = start + 2;
+            }
+        }
+    }
+    buf.push_str(&text[pos..]);
+    tokens.push(Token::Text(buf));
+    strip_standalone_lines(&mut tokens);
+    tokens
+}
+
+/// Remove the indentation and line break around block tags alone on their line.
+fn strip_standalone_lines(tokens: &mut [Token]) {
+    let blank = |s: &str| s.chars().all(|c| matches!(c, ' ' | '\t' | '\r'));
+    let last = tokens.len() - 1;
+    let mut keep: Vec<(usize, usize)> = tokens.iter().map(|t| (0, t.text().len())).collect();
+    for i in (1..last).step_by(2) {
+        if !tokens[i].is_block_tag() {
+            continue;
+        }
+        let (before, after) = (tokens[i - 1].text(), tokens[i + 1].text());
+        let line_start = match before.rfind('\n') {
+            Some(nl) => blank(&before[nl + 1..]).then_some(nl + 1),
+            None => (i == 1 && blank(before)).then_some(0),
+        };
+        let line_end = match after.find('\n') {
+            Some(nl) => blank(&after[..nl]).then_some(nl + 1),
+            None => (i + 1 == last && blank(after)).then_some(after.len()),
+        };
+        if let (Some(start), Some(end)) = (line_start, line_end) {
+            keep[i - 1].1 = start;
+            keep[i + 1].0 = end;
+        }
+    }
+    for (token, (start, end)) in tokens.iter_mut().zip(keep) {
+        if let Token::Text(t) = token {
+            *t = t.get(start..end.max(start)).unwrap_or("").to_string();
+        }
+    }
+}
+
+enum BlockKind {
+    If { negate: bool },
+    Each,
+}
+
+impl BlockKind {
+    fn name(&self) -> &'static str {
+        match self {
+            BlockKind::If { negate: false } => "if",
+            BlockKind::If { negate: true } => "unless",
+            BlockKind::Each => "each",
+        }
+    }
+}
+
+/// A block whose closing tag has not been seen yet.
+struct Block {
+    kind: BlockKind,
+    expr: Expr,
+    offset: usize,
+    body: Vec<Node>,
+    otherwise: Option<Vec<Node>>,
+}
+
+impl Block {
+    fn into_node(self) -> Node {
+        let otherwise = self.otherwise.unwrap_or_default();
+        match self.kind {
+            BlockKind::If { negate } => Node::If {
+                negate,
+                cond: self.expr,
+                then: self.body,
+                otherwise,
+            },
+            BlockKind::Each => Node::Each {
+                list: self.expr,
+                body: self.body,
+                otherwise,
+            },
+        }
+    }
+}
+
+/// Where the next node goes: the innermost open block, or the top level.
+fn target<'a>(root: &'a mut Vec<Node>, blocks: &'a mut [Block]) -> &'a mut Vec<Node> {
+    match blocks.last_mut() {
+        Some(Block {
+            otherwise: Some(nodes),
+            ..
+        }) => nodes,
+        Some(block) => &mut block.body,
+        None => root,
+    }
+}
+
+fn parse(text: &str) -> (Vec<Node>, Vec<Error>) {
+    let mut errors = Vec::new();
+    let tokens = tokenize(text, &mut errors);
+    let mut root = Vec::new();
+    let mut blocks: Vec<Block> = Vec::new();
+
+    for token in tokens {
+        let (content, offset) = match token {
+            Token::Text(t) => {
+                if !t.is_empty() {
+                    target(&mut root, &mut blocks).push(Node::Text(t));
+                }
+                continue;
+            }
+            Token::Tag { content, offset } => (content, offset),
+        };
+
+        if content.starts_with('!') {
+            continue;
+        }
+        if let Some(open) = content.strip_prefix('#') {
+            let (keyword, rest) = open.split_once(char::is_whitespace).unwrap_or((open, ""));
+            let kind = match keyword {
+                "if" => BlockKind::If { negate: false },
+                "unless" => BlockKind::If { negate: true },
+                "each" => BlockKind::Each,
+                _ => {
+                    errors.push(Error::new(
+                        offset,
+                        format!(
+                            "unknown block '{{{{#{keyword}}}}}', expected #if, #unless or #each"
+                        ),
+                    ));
+                    continue;
+                }
+            };
+            if rest.trim().is_empty() {
+                errors.push(Error::new(
+                    offset,
+                    format!("'{{{{#{}}}}}' needs a variable", kind.name()),
+                ));
+                continue;
+            }
+            match parse_expr(rest, offset) {
+                Ok(expr) => blocks.push(Block {
+                    kind,
+                    expr,
+                    offset,
+                    body: Vec::new(),
+                    otherwise: None,
+                }),
+                Err(e) => errors.push(e),
+            }
+        } else if content == "else" {
+            match blocks.last_mut() {
+                Some(block) if block.otherwise.is_none() => block.otherwise = Some(Vec::new()),
+                Some(block) => errors.push(Error::new(
+                    offset,
+                    format!(
+                        "'{{{{#{}}}}}' already has an '{{{{else}}}}'",
+                        block.kind.name()
+                    ),
+                )),
+                None => errors.push(Error::new(offset, "'{{else}}' outside of a block")),
+            }
+        } else if let Some(close) = content.strip_prefix('/') {
+            let close = close.trim();
+            match blocks.pop() {
+                Some(block) if block.kind.name() == close => {
+                    target(&mut root, &mut blocks).push(block.into_node());
+                }
+                Some(block) => {
+                    let opened = Error::new(block.offset, "").to_diagnostic(text);
+                    errors.push(Error::new(
+                        offset,
+                        format!(
+                            "'{{{{/{close}}}}}' does not close '{{{{#{}}}}}' opened at {}:{}",
+                            block.kind.name(),
+                            opened.line,
+                            opened.column
+                        ),
+                    ));
+                    blocks.push(block);
+                }
+                None => errors.push(Error::new(
+                    offset,
+                    format!("'{{{{/{close}}}}}' has no matching opening block"),
+                )),
+            }
+        } else {
+            match parse_expr(&content, offset) {
+                Ok(expr) => target(&mut root, &mut blocks).push(Node::Expr(expr)),
+                Err(e) => errors.push(e),
+            }
+        }
+    }
+    // 未闭合的块仍然加入语法树，以便检查其中的变量
+    while let Some(block) = blocks.pop() {
+        errors.push(Error::new(
+            block.offset,
+            format!(
+                "'{{{{#{0}}}}}' is never closed with '{{{{/{0}}}}}'",
+                block.kind.name()
+            ),
+        ));
+        target(&mut root, &mut blocks).push(block.into_node());
+    }
+    (root, errors)
+}
+
+/// Parse `path | filter | filter: arg`.
+fn parse_expr(text: &str, offset: usize) -> Result<Expr, Error> {
+    let mut parts = split_unquoted(text, '|').into_iter();
+    let path = parts.next().unwrap_or_default();
+    let path = path.trim();
+    let valid_segment = |s: &str| {
+        !s.is_empty()
+            && s.chars()
+                .all(|c| c.is_alphanumeric() || c == '_' || c == '-')
+    };
+    let segments: Vec<String> = path.split('.').map(str::to_string).collect();
+    let valid = segments
+        .iter()
+        .enumerate()
+        .all(|(i, s)| match s.strip_prefix('@') {
+            Some(rest) => i == 0 && segments.len() == 1 && valid_segment(rest),
+            None => valid_segment(s),
+        });
+    if !valid {
+        return Err(Error::new(
+            offset,
+            format!("invalid variable name '{path}'"),
+        ));
+    }
+
+    let mut filters = Vec::new();
+    for part in parts {
+        let (name, arg) = match part.split_once(':') {
+            Some((name, arg)) => (name.trim(), Some(arg.trim())),
+            None => (part.trim(), None),
+        };
+        let arg = match arg {
+            Some(raw) => Some(parse_arg(raw).ok_or_else(|| {
+                Error::new(
+                    offset,
+                    format!(
+                        "invalid argument '{raw}' for filter '{name}', expected a number or a quoted string"
+                    ),
+                )
+            })?),
+            None => None,
+        };
+        filters.push(Filter {
+            name: name.to_string(),
+            arg,
+        });
+    }
+    Ok(Expr {
+        path: segments,
+        filters,
+        offset,
+    })
+}
+
+/// Split on `separator` outside of double-quoted strings.
+fn split_unquoted(text: &str, separator: char) -> Vec<String> {
+    let mut parts = vec![String::new()];
+    let mut quoted = false;
+    let mut escaped = false;
+    for c in text.chars() {
+        if c == separator && !quoted {
+            parts.push(String::new());
+            continue;
+        }
+        if c == '"' && !escaped {
+            quoted = !quoted;
+        }
+        escaped = quoted && !escaped && c == '\\';
+        if let Some(part) = parts.last_mut() {
+            part.push(c);
+        }
+    }
+    parts
+}
+
+/// A filter argument: a number or a double-quoted string with `\n`, `\t` and `\"` escapes.
+fn parse_arg(raw: &str) -> Option<Value> {
+    if let Some(inner) = raw.strip_prefix('"').and_then(|r| r.strip_suffix('"')) {
+        let mut s = String::new();
+        let mut chars = inner.chars();
+        while let Some(c) = chars.next() {
+            if c != '\\' {
+                s.push(c);
+                continue;
+            }
+            match chars.next()? {
+                'n' => s.push('\n'),
+                't' => s.push('\t'),
+                other => s.push(other),
+            }
+        }
+        return Some(Value::Str(s));
+    }
+    raw.parse().ok().map(Value::Int)
+}
+
+fn check_nodes<'a>(
+    nodes: &'a [Node],
+    context: &TemplateContext,
+    depth: usize,
+    used: &mut Vec<&'a str>,
+    errors: &mut Vec<Error>,
+) {
+    for node in nodes {
+        match node {
+            Node::Text(_) => {}
+            Node::Expr(expr) => check_expr(expr, context, depth, used, errors),
+            Node::If {
+                cond,
+                then,
+                otherwise,
+                ..
+            } => {
+                check_expr(cond, context, depth, used, errors);
+                check_nodes(then, context, depth, used, errors);
+                check_nodes(otherwise, context, depth, used, errors);
+            }
+            Node::Each {
+                list,
+                body,
+                otherwise,
+            } => {
+                check_expr(list, context, depth, used, errors);
+                check_nodes(body, context, depth + 1, used, errors);
+                check_nodes(otherwise, context, depth, used, errors);
+            }
+        }
+    }
+}
+
+fn check_expr<'a>(
+    expr: &'a Expr,
+    context: &TemplateContext,
+    depth: usize,
+    used: &mut Vec<&'a str>,
+    errors: &mut Vec<Error>,
+) {
+    let root = expr.path[0].as_str();
+    if root == "this" || root.starts_with('@') {
+        if !matches!(root, "this" | "@index" | "@first" | "@last") {
+            errors.push(Error::new(
+                expr.offset,
+                format!("unknown variable '{root}', expected @index, @first or @last"),
+            ));
+        } else if depth == 0 {
+            errors.push(Error::new(
+                expr.offset,
+                format!("'{root}' can only be used inside '{{{{#each}}}}'"),
+            ));
+        }
+    } else if context.vars.contains_key(root) {
+        used.push(root);
+    } else {
+        let names: Vec<&str> = context.vars.keys().map(String::as_str).collect();
+        errors.push(Error::new(
+            expr.offset,
+            format!(
+                "unknown variable '{root}', expected one of {}",
+                names.join(", ")
+            ),
+        ));
+    }
+
+    for filter in &expr.filters {
+        let Some((_, arg)) = FILTERS.iter().find(|(name, _)| *name == filter.name) else {
+            let names: Vec<&str> = FILTERS.iter().map(|(name, _)| *name).collect();
+            errors.push(Error::new(
+                expr.offset,
+                format!(
+                    "unknown filter '{}', expected one of {}",
+                    filter.name,
+                    names.join(", ")
+                ),
+            ));
+            continue;
+        };
+        let ok = match (arg, &filter.arg) {
+            (Arg::None | Arg::OptionalStr, None) => true,
+            (Arg::OptionalStr, Some(Value::Str(_))) | (Arg::Any, Some(_)) => true,
+            (Arg::Int, Some(Value::Int(n))) => *n >= 0,
+            _ => false,
+        };
+        if !ok {
+            let expected = match arg {
+                Arg::None => "takes no argument",
+                Arg::OptionalStr => "takes an optional quoted string",
+                Arg::Any => "needs an argument",
+                Arg::Int => "needs a non-negative number",
+            };
+            errors.push(Error::new(
+                expr.offset,
+                format!("filter '{}' {expected}", filter.name),
+            ));
+        }
+    }
+}
+
+/// The current item of an `{{#each}}` block.
+struct Loop {
+    item: Value,
+    index: usize,
+    len: usize,
+}
+
+struct Renderer<'a> {
+    context: &'a TemplateContext,
+    loops: Vec<Loop>,
+    out: String,
+}
+
+impl Renderer<'_> {
+    fn render(&mut self, nodes: &[Node]) -> Result<(), Error> {
+        for node in nodes {
+            match node {
+                Node::Text(t) => self.out.push_str(t),
+                Node::Expr(expr) => {
+                    let text = self.eval(expr)?.render().map_err(|e| {
+                        Error::new(expr.offset, format!("'{}' {e}", expr.display_path()))
+                    })?;
+                    self.out.push_str(&text);
+                }
+                Node::If {
+                    negate,
+                    cond,
+                    then,
+                    otherwise,
+                } => {
+                    if self.eval(cond)?.is_truthy() != *negate {
+                        self.render(then)?;
+                    } else {
+                        self.render(otherwise)?;
+                    }
+                }
+                Node::Each {
+                    list,
+                    body,
+                    otherwise,
+                } => {
+                    let items = match self.eval(list)? {
+                        Value::List(items) => items,
+                        Value::Map(map) => map.into_values().collect(),
+                        _ => {
+                            return Err(Error::new(
+                                list.offset,
+                                format!("'{}' is not a list", list.display_path()),
+                            ))
+                        }
+                    };
+                    if items.is_empty() {
+                        self.render(otherwise)?;
+                    }
+                    let len = items.len();
+                    for (index, item) in items.into_iter().enumerate() {
+                        self.loops.push(Loop { item, index, len });
+                        let result = self.render(body);
+                        self.loops.pop();
+                        result?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn eval(&self, expr: &Expr) -> Result<Value, Error> {
+        let error = |message: String| Error::new(expr.offset, message);
+        let root = expr.path[0].as_str();
+        let mut value = match (root, self.loops.last()) {
+            ("this", Some(l)) => l.item.clone(),
+            ("@index", Some(l)) => Value::Int(l.index as i64),
+            ("@first", Some(l)) => Value::Bool(l.index == 0),
+            ("@last", Some(l)) => Value::Bool(l.index + 1 == l.len),
+            _ => self
+                .context
+                .vars
+                .get(root)
+                .cloned()
+                .ok_or_else(|| error(format!("unknown variable '{root}'")))?,
+        };
+        for (i, field) in expr.path.iter().enumerate().skip(1) {
+            value = match value {
+                Value::Map(mut map) => map.remove(field),
+                _ => None,
+            }
+            .ok_or_else(|| error(format!("'{}' is not defined", expr.path[..=i].join("."))))?;
+        }
+        for filter in &expr.filters {
+            value = apply_filter(filter, value).map_err(error)?;
+        }
+        Ok(value)
+    }
+}
+
+fn apply_filter(filter: &Filter, value: Value) -> Result<Value, String> {
+    let text = |value: &Value| {
+        value
+            .render()
+            .map_err(|e| format!("filter '{}': value {e}", filter.name))
+    };
+    let count = || match filter.arg {
+        Some(Value::Int(n)) => n.max(0) as usize,
+        _ => 0,
+    };
+    Ok(match filter.name.as_str() {
+        "upper" => Value::Str(text(&value)?.to_uppercase()),
+        "lower" => Value::Str(text(&value)?.to_lowercase()),
+        "trim" => Value::Str(text(&value)?.trim().to_string()),
+        "first_line" => Value::Str(text(&value)?.lines().next().unwrap_or("").to_string()),
+        "lines" => Value::List(text(&value)?.lines().map(Value::from).collect()),
+        "length" => Value::Int(match &value {
+            Value::List(items) => items.len(),
+            Value::Map(map) => map.len(),
+            other => text(other)?.chars().count(),
+        } as i64),
+        "join" => {
+            let separator = match &filter.arg {
+                Some(Value::Str(s)) => s.as_str(),
+                _ => ", ",
+            };
+            match &value {
+                Value::List(items) => Value::Str(
+                    items
+                        .iter()
+                        .map(text)
+                        .collect::<Result<Vec<_>, _>>()?
+                        .join(separator),
+                ),
+                other => Value::Str(text(other)?),
+            }
+        }
+        "default" => match (value.is_truthy(), &filter.arg) {
+            (false, Some(fallback)) => fallback.clone(),
+            _ => value,
+        },
+        "truncate" => {
+            let s = text(&value)?;
+            if s.chars().count() > count() {
+                Value::Str(s.chars().take(count()).chain(['…']).collect())
+            } else {
+                Value::Str(s)
+            }
+        }
+        "indent" => {
+            let pad = " ".repeat(count());
+            let lines: Vec<String> = text(&value)?
+                .lines()
+                .map(|l| {
+                    if l.is_empty() {
+                        String::new()
+                    } else {
+                        format!("{pad}{l}")
+                    }
+                })
+                .collect();
+            Value::Str(lines.join("\n"))
+        }
+        other => return Err(format!("unknown filter '{other}'")),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn template(kind: TemplateKind, text: &str) -> Template {
+        Template {
+            name: "commit.md".into(),
+            text: text.into(),
+            kind,
+        }
+    }
+
+    fn context(diff: &str) -> TemplateContext {
+        TemplateContext::new(true, CommitLanguage::English, Verbosity::Quiet, diff, None)
+    }
+
+    fn render(text: &str, context: TemplateContext) -> String {
+        render_template(&template(TemplateKind::System, text), context).unwrap()
+    }
+
+    fn messages(t: &Template) -> Vec<String> {
+        t.compile(&context(""))
+            .err()
+            .unwrap_or_default()
+            .iter()
+            .map(ToString::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn test_render_in_one_pass() {
+        let t = template(
+            TemplateKind::Commit,
+            "Write in {{ language }} ({{verbosity_level}}, {{conventional_commit}}).\n{{diff}}\n{{user_description}}",
+        );
+        let rendered = render_template(&t, context("+ let x = \"{{language}}\";")).unwrap();
+        assert_eq!(
+            rendered,
+            "Write in English (简洁, true).\n+ let x = \"{{language}}\";\n"
+        );
+    }
+
+    #[test]
+    fn test_blocks() {
+        let text = "\
+{{#if conventional}}
+Use Conventional Commits.
+{{else}}
+Free-form message.
+{{/if}}
+{{#unless user_description}}No hint.{{/unless}}
+Files:
+{{#each files}}
+  {{@index}}. {{this.path}}{{#if @last}}.{{else}},{{/if}}
+{{else}}
+  (none)
+{{/each}}
+{{! not rendered }}
+Literal \\{{diff}}";
+        let mut ctx = context("");
+        ctx.vars.insert("files".into(), Value::List(Vec::new()));
+        assert_eq!(
+            render(text, ctx),
+            "Use Conventional Commits.\nNo hint.\nFiles:\n  (none)\nLiteral {{diff}}"
+        );
+
+        let file = |path: &str| Value::Map(BTreeMap::from([("path".to_string(), path.into())]));
+        let mut ctx = TemplateContext::new(
+            false,
+            CommitLanguage::English,
+            Verbosity::Quiet,
+            "",
+            Some("hint"),
+        );
+        ctx.vars.insert(
+            "files".into(),
+            Value::List(vec![file("a.rs"), file("b.rs")]),
+        );
+        assert_eq!(
+            render(text, ctx),
+            "Free-form message.\n\nFiles:\n  0. a.rs,\n  1. b.rs.\nLiteral {{diff}}"
+        );
+    }
+
+    #[test]
+    fn test_filters() {
+        let mut ctx = context("line one\nline two\nline three");
+        ctx.vars.insert("tags".into(), vec!["a", "b"].into());
+        assert_eq!(
+            render(
+                "{{diff | first_line | upper}}|{{diff | lines | length}}|{{tags | join: \" | \"}}|{{user_description | default: \"none\"}}|{{diff | truncate: 4}}",
+                ctx
+            ),
+            "LINE ONE|3|a | b|none|line…"
+        );
+        assert_eq!(
+            render("{{diff | indent: 2}}", context("a\n\nb")),
+            "  a\n\n  b"
+        );
+    }
+
+    #[test]
+    fn test_reports_problems_with_positions() {
+        let t = template(
+            TemplateKind::Commit,
+            "# Role\nUse {{lang}} please\n  {{diff\n{{#if conventional}}{{diff | shout}}\n{{this}}\n",
+        );
+        assert_eq!(
+            messages(&t),
+            vec![
+                "2:5: unknown variable 'lang', expected one of branch, conventional, conventional_commit, diff, files, language, recent_commits, repo, stat, style, style_examples, user_description, verbosity_level",
+                "3:3: '{{' is not closed by '}}' on the same line",
+                "4:1: '{{#if}}' is never closed with '{{/if}}'",
+                "4:21: unknown filter 'shout', expected one of upper, lower, trim, first_line, lines, length, join, default, truncate, indent",
+                "5:1: 'this' can only be used inside '{{#each}}'",
+            ]
+        );
+        let err = render_template(&t, context("")).unwrap_err().to_string();
+        assert!(err.starts_with("Invalid prompt template:\n  commit.md:2:5: unknown variable"));
+
+        let t = template(TemplateKind::Branch, "{{#each diff}}{{/if}}");
+        assert_eq!(
+            messages(&t),
+            vec![
+                "1:1: '{{#each}}' is never closed with '{{/each}}'",
+                "1:15: '{{/if}}' does not close '{{#each}}' opened at 1:1",
+            ]
+        );
+
+        let t = template(TemplateKind::Commit, "{{language | truncate}}");
+        assert_eq!(
+            messages(&t),
+            vec![
+                "1:1: filter 'truncate' needs a non-negative number",
+                "1:1: required placeholder {{diff}} is missing from the commit template",
+            ]
+        );
+        assert!(messages(&template(
+            TemplateKind::System,
+            "You write {{language}} commits."
+        ))
+        .is_empty());
+    }
+
+    #[test]
+    fn test_template_vars() {
+        let mut config = Config::default();
+        config.template_vars = toml::toml! {
+            team = "payments"
+            scopes = ["api", "db"]
+            diff = "shadowed"
+        };
+        let ctx = TemplateContext::from_config(&config, "the diff", None);
+        assert_eq!(
+            render("{{team}}: {{scopes | join: \"/\"}} {{diff}}", ctx),
+            "payments: api/db the diff"
+        );
+        assert!(is_builtin_variable("diff"));
+        assert!(!is_builtin_variable("team"));
+    }
+
+    #[test]
+    fn test_builtin_templates_are_valid() {
+        use crate::constants::{
+            BRANCH_NAME_PROMPT, DEFAULT_PROMPT_TEMPLATE, DIFF_CHUNK_SUMMARY_PROMPT,
+        };
+        for (kind, text) in [
+            (TemplateKind::Commit, DEFAULT_PROMPT_TEMPLATE),
+            (TemplateKind::Branch, BRANCH_NAME_PROMPT),
+            (TemplateKind::Commit, DIFF_CHUNK_SUMMARY_PROMPT),
+        ] {
+            assert!(Template::builtin(kind, text).compile(&context("")).is_ok());
+        }
+    }
 }